@@ -8,22 +8,30 @@ use gtk::{
 
 use std::{
     cell::{Cell, RefCell},
+    collections::VecDeque,
+    path::PathBuf,
     rc::Rc,
     time::Duration,
 };
 
+use crate::batch_edit::BatchEditJob;
 use crate::image_list::ImageList;
+use crate::recipe::Recipe;
+use crate::recovery::{Recovery, AUTOSAVE_INTERVAL};
 use crate::settings::Settings;
 use crate::ui::{
     event::{post_event, Event},
     widgets::Widgets,
 };
+use crate::worker::{BackgroundJobs, WorkerPool};
 use crate::{file_list::FileList, ui::controllers::Controllers};
 use crate::{
     image::CoordinatesPair,
-    ui::{action, event},
+    ui::{action, comparison::Comparison, event},
 };
 
+const WORKER_THREAD_COUNT: usize = 2;
+
 pub struct App {
     application: gtk::Application,
     controllers: Controllers,
@@ -31,6 +39,23 @@ pub struct App {
     image_list: Rc<RefCell<ImageList>>,
     file_list: FileList,
     selection_coords: Rc<Cell<Option<CoordinatesPair>>>,
+    comparison: Rc<Cell<Comparison>>,
+    /// Paths of files listed in the collage dialog, in the order of its rows.
+    collage_file_paths: Rc<RefCell<Vec<PathBuf>>>,
+    /// Paths of files listed in the batch edit dialog, in the order of its rows.
+    batch_file_paths: Rc<RefCell<Vec<PathBuf>>>,
+    copied_recipe: Option<Recipe>,
+    batch_edit_job: Option<BatchEditJob>,
+    background_jobs: BackgroundJobs,
+    /// Edits and saves requested while an image was loading or another edit or save was
+    /// running, with path of the image they were requested for. They are run in order once
+    /// the jobs finish.
+    queued_edits: VecDeque<(Option<PathBuf>, Event)>,
+    zoom_generation: u64,
+    /// True while the user is asked what to do with an image changed by another program.
+    external_modification_prompted: bool,
+    /// Recovery file of this instance, edits are autosaved into it.
+    recovery_path: PathBuf,
     settings: Settings,
     sender: glib::Sender<Event>,
 }
@@ -50,14 +75,18 @@ impl App {
         gtk::IconTheme::for_display(&Display::default().unwrap())
             .add_resource_path("/com/github/weclaw1/image-roll/icons/");
 
-        let image_list: Rc<RefCell<ImageList>> = Rc::new(RefCell::new(ImageList::new()));
-
         let file_list: FileList = FileList::new(None).unwrap();
 
         let selection_coords: Rc<Cell<Option<CoordinatesPair>>> = Rc::new(Cell::new(None));
 
         let settings: Settings = Settings::new(application.application_id().unwrap().as_str());
 
+        let mut image_list = ImageList::new();
+        image_list.set_memory_limit(settings.image_cache_memory_limit());
+        image_list.set_backup_location(settings.backup_location());
+        image_list.set_encoder_options(settings.encoder_options());
+        let image_list: Rc<RefCell<ImageList>> = Rc::new(RefCell::new(image_list));
+
         let (window_width, window_height) = settings.window_size();
         widgets
             .window()
@@ -75,6 +104,19 @@ impl App {
             });
         }
 
+        match Recovery::find_abandoned(&Recovery::folder()) {
+            Ok(Some((recovery_path, recovery))) => {
+                post_event(&sender, Event::OfferRecovery(recovery_path, recovery))
+            }
+            Ok(None) => {}
+            Err(error) => warn!("Couldn't look for recovery files: {}", error),
+        }
+        let autosave_sender = sender.clone();
+        glib::timeout_add_seconds_local(AUTOSAVE_INTERVAL, move || {
+            post_event(&autosave_sender, Event::Autosave);
+            glib::Continue(true)
+        });
+
         let mut app = Self {
             application: application.clone(),
             controllers,
@@ -82,6 +124,16 @@ impl App {
             image_list,
             file_list,
             selection_coords,
+            comparison: Rc::new(Cell::new(Comparison::default())),
+            collage_file_paths: Rc::new(RefCell::new(Vec::new())),
+            batch_file_paths: Rc::new(RefCell::new(Vec::new())),
+            copied_recipe: None,
+            batch_edit_job: None,
+            background_jobs: BackgroundJobs::new(WorkerPool::new(WORKER_THREAD_COUNT)),
+            queued_edits: VecDeque::new(),
+            zoom_generation: 0,
+            external_modification_prompted: false,
+            recovery_path: Recovery::session_path(&Recovery::folder()),
             settings,
             sender,
         };
@@ -91,7 +143,11 @@ impl App {
             app.sender.clone(),
             app.image_list.clone(),
             app.selection_coords.clone(),
+            app.comparison.clone(),
+            app.collage_file_paths.clone(),
+            app.batch_file_paths.clone(),
             app.settings.clone(),
+            app.recovery_path.clone(),
         );
 
         event::connect_controllers(
@@ -104,6 +160,8 @@ impl App {
             &app.widgets,
             &app.file_list,
             app.image_list.clone(),
+            app.copied_recipe.is_some(),
+            app.background_jobs.is_busy(),
             &app.settings,
         );
 
@@ -114,9 +172,42 @@ impl App {
     }
 
     pub fn process_event(&mut self, event: Event) {
+        self.handle_event(event);
+        self.run_queued_edits();
+        action::update_buttons_state(
+            &self.widgets,
+            &self.file_list,
+            self.image_list.clone(),
+            self.copied_recipe.is_some(),
+            self.background_jobs.is_busy(),
+            &self.settings,
+        );
+    }
+
+    fn run_queued_edits(&mut self) {
+        while !self.background_jobs.is_busy() {
+            let (image_path, event) = match self.queued_edits.pop_front() {
+                Some(queued_edit) => queued_edit,
+                None => break,
+            };
+            // edits of an image which was closed in the meantime aren't applied to another one,
+            // events which aren't tied to an image are queued without a path
+            if image_path.is_none() || image_path == self.image_list.borrow().current_image_path() {
+                self.handle_event(event);
+            } else {
+                debug!(
+                    "Discarded edit of an image which is no longer shown: {:?}",
+                    event
+                );
+            }
+        }
+    }
+
+    fn handle_event(&mut self, event: Event) {
         match event {
             Event::OpenFile(file) => action::open_file(
                 &self.sender,
+                &self.widgets,
                 self.image_list.clone(),
                 &mut self.file_list,
                 file,
@@ -126,30 +217,143 @@ impl App {
                 &mut self.settings,
                 &self.widgets,
                 self.image_list.clone(),
+                &mut self.background_jobs,
                 file_path,
             ),
+            Event::ImageProxyLoaded(generation, file_path, decoded_proxy) => {
+                action::image_proxy_loaded(
+                    &self.sender,
+                    &mut self.settings,
+                    &self.widgets,
+                    self.image_list.clone(),
+                    &self.background_jobs,
+                    generation,
+                    file_path,
+                    decoded_proxy,
+                )
+            }
+            Event::ImageLoaded(generation, file_path, decoded_image) => action::image_loaded(
+                &self.sender,
+                &mut self.settings,
+                &self.widgets,
+                self.image_list.clone(),
+                &mut self.background_jobs,
+                generation,
+                file_path,
+                decoded_image,
+            ),
+            Event::PrefetchImages => action::prefetch_images(
+                &self.sender,
+                &self.settings,
+                self.image_list.clone(),
+                &self.file_list,
+                &mut self.background_jobs,
+            ),
+            Event::ShowCacheUsage => {
+                action::show_cache_usage(&self.sender, self.image_list.clone())
+            }
+            Event::ImagePrefetched(file_path, decoded_image) => action::image_prefetched(
+                &self.settings,
+                self.image_list.clone(),
+                &self.file_list,
+                &mut self.background_jobs,
+                file_path,
+                decoded_image,
+            ),
             Event::DisplayMessage(message, message_type) => {
                 action::display_message(&self.widgets, message.as_str(), message_type)
             }
-            Event::ImageViewportResize(viewport_size) => {
-                action::image_viewport_resize(&self.sender, &mut self.settings, viewport_size)
-            }
-            Event::RefreshPreview(preview_size) => {
-                action::refresh_preview(&self.widgets, self.image_list.clone(), preview_size)
+            Event::ImageViewportResize(viewport_size) => action::image_viewport_resize(
+                &self.sender,
+                &self.widgets,
+                &mut self.settings,
+                self.image_list.clone(),
+                &mut self.zoom_generation,
+                viewport_size,
+            ),
+            Event::RefreshPreview(preview_size) => action::refresh_preview(
+                &self.sender,
+                &self.widgets,
+                self.image_list.clone(),
+                &self.background_jobs,
+                preview_size,
+            ),
+            Event::PreviewCreated(generation, image_path, preview_image_buffer) => {
+                action::preview_created(
+                    &self.widgets,
+                    self.image_list.clone(),
+                    &self.background_jobs,
+                    generation,
+                    image_path,
+                    preview_image_buffer,
+                )
             }
             Event::ChangePreviewSize(preview_size) => action::change_preview_size(
                 &self.sender,
                 &self.widgets,
                 &mut self.settings,
+                self.image_list.clone(),
+                &mut self.zoom_generation,
                 preview_size,
             ),
+            Event::ZoomSettled(generation) => action::zoom_settled(
+                &self.sender,
+                &self.settings,
+                self.zoom_generation,
+                generation,
+            ),
+            Event::ImageEdit(_)
+            | Event::UndoOperation
+            | Event::RedoOperation
+            | Event::GoToOperation(_)
+            | Event::RemoveOperation(_)
+            | Event::RevertCurrentImage
+            | Event::PasteEdits
+            | Event::ApplyRecipe(_)
+            | Event::SaveCurrentImageWithOptions(..)
+                if self.background_jobs.is_busy() =>
+            {
+                let current_image_path = self.image_list.borrow().current_image_path();
+                self.queued_edits.push_back((current_image_path, event))
+            }
+            Event::ResolveUnsavedEdits(..) | Event::CreateCollage(..)
+                if self.background_jobs.is_busy() =>
+            {
+                self.queued_edits.push_back((None, event))
+            }
             Event::ImageEdit(image_operation) => action::image_edit(
+                &self.sender,
+                self.image_list.clone(),
+                &mut self.background_jobs,
+                image_operation,
+            ),
+            Event::ImageOperationApplied(
+                image_path,
+                operation_tag,
+                image_operation,
+                image_buffer,
+            ) => action::image_operation_applied(
                 &self.sender,
                 &self.settings,
                 self.image_list.clone(),
-                &self.file_list,
+                &mut self.background_jobs,
+                image_path,
+                operation_tag,
                 image_operation,
+                image_buffer,
+            ),
+            Event::HistoryReplayed(image_path, operation_tag, replay) => action::history_replayed(
+                &self.sender,
+                &self.settings,
+                self.image_list.clone(),
+                &mut self.background_jobs,
+                image_path,
+                operation_tag,
+                replay,
             ),
+            Event::StartSelection(position) if self.widgets.eyedropper_button().is_active() => {
+                action::pick_color(&self.widgets, self.image_list.clone(), position)
+            }
             Event::StartSelection(position) if self.widgets.crop_button().is_active() => {
                 action::start_selection(
                     &self.widgets,
@@ -166,20 +370,78 @@ impl App {
                     position,
                 )
             }
+            Event::StartSelection(position) => action::start_divider_drag(
+                self.image_list.clone(),
+                self.comparison.clone(),
+                position,
+            ),
+            Event::DragSelection(position) => action::drag_divider(
+                &self.widgets,
+                self.image_list.clone(),
+                self.comparison.clone(),
+                position,
+            ),
+            Event::ShowOriginal(original_shown) => {
+                action::show_original(&self.widgets, self.comparison.clone(), original_shown)
+            }
+            Event::ToggleSplitView(split_view) => {
+                action::toggle_split_view(&self.widgets, self.comparison.clone(), split_view)
+            }
             Event::SaveCurrentImage(filename) => {
-                action::save_current_image(&self.sender, self.image_list.clone(), filename);
+                action::save_current_image(
+                    &self.sender,
+                    &self.widgets,
+                    self.image_list.clone(),
+                    &self.background_jobs,
+                    &mut self.external_modification_prompted,
+                    filename,
+                );
+            }
+            Event::SaveCurrentImageWithOptions(filename, encoder_options) => {
+                action::save_current_image_with_options(
+                    &self.sender,
+                    &self.settings,
+                    self.image_list.clone(),
+                    &mut self.background_jobs,
+                    filename,
+                    encoder_options,
+                );
+            }
+            Event::ImageSaved(image_save, result, unsaved_edits) => {
+                action::image_saved(
+                    &self.sender,
+                    self.image_list.clone(),
+                    &mut self.file_list,
+                    &mut self.background_jobs,
+                    image_save,
+                    result,
+                    unsaved_edits,
+                );
                 if self.file_list.current_folder_monitor_mut().is_none() {
-                    action::refresh_file_list(&self.sender, &mut self.file_list);
+                    action::refresh_file_list(
+                        &self.sender,
+                        &self.widgets,
+                        self.image_list.clone(),
+                        &mut self.file_list,
+                        &mut self.external_modification_prompted,
+                    );
                 }
             }
             Event::DeleteCurrentImage => {
                 action::delete_current_image(
                     &self.sender,
+                    &self.widgets,
                     &mut self.file_list,
                     self.image_list.clone(),
                 );
                 if self.file_list.current_folder_monitor_mut().is_none() {
-                    action::refresh_file_list(&self.sender, &mut self.file_list);
+                    action::refresh_file_list(
+                        &self.sender,
+                        &self.widgets,
+                        self.image_list.clone(),
+                        &mut self.file_list,
+                        &mut self.external_modification_prompted,
+                    );
                 }
             }
             Event::EndSelection if self.widgets.crop_button().is_active() => action::end_selection(
@@ -188,6 +450,7 @@ impl App {
                 self.image_list.clone(),
                 self.selection_coords.clone(),
             ),
+            Event::EndSelection => action::end_divider_drag(self.comparison.clone()),
             Event::PreviewSmaller(value) => {
                 action::preview_smaller(&self.sender, &self.settings, value)
             }
@@ -195,13 +458,15 @@ impl App {
                 action::preview_larger(&self.sender, &self.settings, value)
             }
             Event::PreviewFitScreen => action::preview_fit_screen(&self.sender),
-            Event::NextImage => {
-                action::next_image(&self.sender, self.image_list.clone(), &mut self.file_list)
-            }
-            Event::PreviousImage => {
-                action::previous_image(&self.sender, self.image_list.clone(), &mut self.file_list)
-            }
-            Event::RefreshFileList => action::refresh_file_list(&self.sender, &mut self.file_list),
+            Event::NextImage => action::next_image(&self.sender, &mut self.file_list),
+            Event::PreviousImage => action::previous_image(&self.sender, &mut self.file_list),
+            Event::RefreshFileList => action::refresh_file_list(
+                &self.sender,
+                &self.widgets,
+                self.image_list.clone(),
+                &mut self.file_list,
+                &mut self.external_modification_prompted,
+            ),
             Event::ResizePopoverDisplayed => {
                 action::resize_popover_displayed(&self.widgets, self.image_list.clone())
             }
@@ -211,11 +476,30 @@ impl App {
             Event::UpdateResizePopoverHeight => {
                 action::update_resize_popover_height(&self.widgets, self.image_list.clone())
             }
-            Event::UndoOperation => {
-                action::undo_operation(&self.sender, &self.settings, self.image_list.clone())
-            }
-            Event::RedoOperation => {
-                action::redo_operation(&self.sender, &self.settings, self.image_list.clone())
+            Event::UndoOperation => action::undo_operation(
+                &self.sender,
+                self.image_list.clone(),
+                &mut self.background_jobs,
+            ),
+            Event::RedoOperation => action::redo_operation(
+                &self.sender,
+                self.image_list.clone(),
+                &mut self.background_jobs,
+            ),
+            Event::GoToOperation(operation_index) => action::go_to_operation(
+                &self.sender,
+                self.image_list.clone(),
+                &mut self.background_jobs,
+                operation_index,
+            ),
+            Event::RemoveOperation(operation_index) => action::remove_operation(
+                &self.sender,
+                self.image_list.clone(),
+                &mut self.background_jobs,
+                operation_index,
+            ),
+            Event::RefreshHistoryPanel => {
+                action::refresh_history_panel(&self.sender, &self.widgets, self.image_list.clone())
             }
             Event::Print => action::print(&self.sender, &self.widgets, self.image_list.clone()),
             Event::HideInfoPanel => action::hide_info_panel(&self.widgets),
@@ -226,14 +510,137 @@ impl App {
                 action::change_scale_on_zoom_gesture(&self.sender, &self.settings, zoom_scale)
             }
             Event::CopyCurrentImage => action::copy_current_image(self.image_list.clone()),
-            Event::Quit => action::quit(&self.application),
+            Event::CopyEdits => {
+                action::copy_edits(self.image_list.clone(), &mut self.copied_recipe)
+            }
+            Event::PasteEdits => {
+                if let Some(recipe) = &self.copied_recipe {
+                    action::apply_recipe(
+                        &self.sender,
+                        self.image_list.clone(),
+                        &mut self.background_jobs,
+                        recipe,
+                    )
+                }
+            }
+            Event::SaveRecipe(recipe_path) => {
+                action::save_recipe(&self.sender, self.image_list.clone(), recipe_path)
+            }
+            Event::ApplyRecipe(recipe_path) => action::load_and_apply_recipe(
+                &self.sender,
+                self.image_list.clone(),
+                &mut self.background_jobs,
+                recipe_path,
+            ),
+            Event::RevertCurrentImage => {
+                action::revert_current_image(&self.sender, &self.settings, self.image_list.clone())
+            }
+            Event::ShowCollageDialog => action::show_collage_dialog(
+                &self.widgets,
+                &self.file_list,
+                &mut self.collage_file_paths.borrow_mut(),
+            ),
+            Event::CreateCollage(selected_file_paths, layout) => action::create_collage(
+                &self.sender,
+                &self.file_list,
+                &mut self.background_jobs,
+                selected_file_paths,
+                layout,
+            ),
+            Event::CollageCreated(folder, collage) => action::collage_created(
+                &self.sender,
+                &self.settings,
+                self.image_list.clone(),
+                &mut self.background_jobs,
+                folder,
+                collage,
+            ),
+            Event::ExportTiles(folder, file_name_pattern, tile_layout) => action::export_tiles(
+                &self.sender,
+                self.image_list.clone(),
+                folder,
+                file_name_pattern,
+                tile_layout,
+            ),
+            Event::ShowBatchEditDialog => action::show_batch_edit_dialog(
+                &self.widgets,
+                &self.file_list,
+                &mut self.batch_file_paths.borrow_mut(),
+                &self.batch_edit_job,
+            ),
+            Event::StartBatchEdit(source, selected_file_paths, options) => {
+                action::start_batch_edit(
+                    &self.sender,
+                    &self.widgets,
+                    self.image_list.clone(),
+                    &self.background_jobs,
+                    &mut self.batch_edit_job,
+                    source,
+                    selected_file_paths,
+                    options,
+                )
+            }
+            Event::CancelBatchEdit => action::cancel_batch_edit(
+                &self.sender,
+                &self.widgets,
+                &self.background_jobs,
+                &mut self.batch_edit_job,
+            ),
+            Event::BatchEditProgress(progress) => action::update_batch_edit_progress(
+                &self.sender,
+                &self.widgets,
+                &self.background_jobs,
+                &mut self.batch_edit_job,
+                progress,
+            ),
+            Event::BatchEditFinished => {
+                action::finish_batch_edit(&self.sender, &self.widgets, &mut self.batch_edit_job)
+            }
+            Event::ResolveUnsavedEdits(edited_image_paths, response, action) => {
+                action::resolve_unsaved_edits(
+                    &self.sender,
+                    &self.settings,
+                    self.image_list.clone(),
+                    &mut self.background_jobs,
+                    edited_image_paths,
+                    response,
+                    action,
+                )
+            }
+            Event::ResolveExternalModification(image_path, response) => {
+                action::resolve_external_modification(
+                    &self.sender,
+                    &self.widgets,
+                    self.image_list.clone(),
+                    &mut self.external_modification_prompted,
+                    image_path,
+                    response,
+                )
+            }
+            Event::Autosave => action::autosave(
+                &self.image_list.borrow(),
+                &self.file_list,
+                &self.recovery_path,
+            ),
+            Event::OfferRecovery(recovery_path, recovery) => {
+                action::offer_recovery(&self.sender, &self.widgets, recovery_path, recovery)
+            }
+            Event::ResolveRecovery(recovery_path, recovery, restore) => action::resolve_recovery(
+                &self.sender,
+                &self.widgets,
+                self.image_list.clone(),
+                &mut self.file_list,
+                recovery_path,
+                recovery,
+                restore,
+            ),
+            Event::Quit => action::quit(
+                &self.sender,
+                &self.widgets,
+                &self.application,
+                self.image_list.clone(),
+            ),
             event => debug!("Discarded unused event: {:?}", event),
         }
-        action::update_buttons_state(
-            &self.widgets,
-            &self.file_list,
-            self.image_list.clone(),
-            &self.settings,
-        );
     }
 }
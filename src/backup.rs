@@ -0,0 +1,113 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::Result;
+
+/// Where the original file is kept before an image is overwritten for the first time.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BackupLocation {
+    /// Next to the image as `name.orig.ext`.
+    SameFolder,
+    /// In the folder under the path of the image, so images with the same name
+    /// from different folders don't share a backup.
+    Folder(PathBuf),
+}
+
+impl BackupLocation {
+    pub fn backup_path(&self, image_path: &Path) -> PathBuf {
+        match self {
+            BackupLocation::SameFolder => {
+                let mut file_name = image_path.file_stem().unwrap_or_default().to_os_string();
+                file_name.push(".orig");
+                if let Some(extension) = image_path.extension() {
+                    file_name.push(".");
+                    file_name.push(extension);
+                }
+                image_path.with_file_name(file_name)
+            }
+            BackupLocation::Folder(folder) => folder.join(
+                image_path
+                    .components()
+                    .filter(|component| matches!(component, Component::Normal(_)))
+                    .collect::<PathBuf>(),
+            ),
+        }
+    }
+
+    /// Copies the image to its backup path unless a backup already exists, so it always
+    /// holds the file from before the first overwrite. Returns path of the created backup.
+    pub fn back_up(&self, image_path: &Path) -> Result<Option<PathBuf>> {
+        let backup_path = self.backup_path(image_path);
+        if !image_path.exists() || backup_path.exists() {
+            return Ok(None);
+        }
+        if let Some(backup_folder) = backup_path.parent() {
+            fs::create_dir_all(backup_folder)?;
+        }
+        fs::copy(image_path, &backup_path)?;
+        Ok(Some(backup_path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::TestResources;
+
+    use super::*;
+
+    #[test]
+    fn backup_is_created_only_before_first_overwrite() {
+        let mut test_resources =
+            TestResources::new("test/backup_is_created_only_before_first_overwrite");
+        test_resources.add_file("test.png", "original");
+        let image_path = test_resources.file_folder().join("test.png");
+
+        let backup_path = BackupLocation::SameFolder.back_up(&image_path).unwrap();
+        assert_eq!(
+            Some(test_resources.file_folder().join("test.orig.png")),
+            backup_path
+        );
+
+        test_resources.add_file("test.png", "edited");
+        assert_eq!(
+            None,
+            BackupLocation::SameFolder.back_up(&image_path).unwrap()
+        );
+        assert_eq!(
+            "original",
+            fs::read_to_string(backup_path.unwrap()).unwrap()
+        );
+
+        let backup_location = BackupLocation::Folder(test_resources.file_folder().join("backups"));
+        backup_location.back_up(&image_path).unwrap();
+        assert_eq!(
+            "edited",
+            fs::read_to_string(backup_location.backup_path(&image_path)).unwrap()
+        );
+    }
+
+    #[test]
+    fn images_with_same_name_from_different_folders_have_separate_backups() {
+        let mut test_resources = TestResources::new(
+            "test/images_with_same_name_from_different_folders_have_separate_backups",
+        );
+        for folder_name in ["first", "second"] {
+            fs::create_dir_all(test_resources.file_folder().join(folder_name)).unwrap();
+            test_resources.add_file(format!("{}/test.png", folder_name), folder_name);
+        }
+        let backup_location = BackupLocation::Folder(test_resources.file_folder().join("backups"));
+
+        for folder_name in ["first", "second"] {
+            let image_path = test_resources
+                .file_folder()
+                .join(folder_name)
+                .join("test.png");
+            let backup_path = backup_location.back_up(&image_path).unwrap().unwrap();
+            assert!(backup_path.starts_with(test_resources.file_folder().join("backups")));
+            assert!(backup_path.ends_with(Path::new(folder_name).join("test.png")));
+            assert_eq!(folder_name, fs::read_to_string(backup_path).unwrap());
+        }
+    }
+}
@@ -0,0 +1,324 @@
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    backup::BackupLocation, decoder, image::save_image_buffer,
+    image_operation::ApplyImageOperation, recipe::Recipe, sidecar::Sidecar,
+};
+
+#[derive(Clone, Debug)]
+pub enum BatchEditSource {
+    CurrentEdits,
+    RecipeFile(PathBuf),
+}
+
+#[derive(Clone, Debug)]
+pub enum OutputLocation {
+    SameFolder,
+    Folder(PathBuf),
+}
+
+#[derive(Clone, Debug)]
+pub struct BatchEditOptions {
+    pub output_location: OutputLocation,
+    pub file_name_suffix: String,
+    /// Extension of the output format, `None` keeps the format of each input file.
+    pub output_format: Option<String>,
+}
+
+impl BatchEditOptions {
+    pub fn output_path(&self, input_path: &Path) -> PathBuf {
+        let folder = match &self.output_location {
+            OutputLocation::SameFolder => input_path.parent().unwrap_or_else(|| Path::new("")),
+            OutputLocation::Folder(folder) => folder.as_path(),
+        };
+        let file_stem = input_path.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = self
+            .output_format
+            .clone()
+            .or_else(|| {
+                input_path
+                    .extension()
+                    .map(|extension| extension.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| String::from("png"));
+        folder.join(format!(
+            "{}{}.{}",
+            file_stem, self.file_name_suffix, extension
+        ))
+    }
+}
+
+#[derive(Debug)]
+pub enum BatchEditProgress {
+    FileEdited(PathBuf),
+    FileFailed(PathBuf, String),
+}
+
+/// State of a batch edit. Files are edited one at a time on worker threads, the next one
+/// is started when the previous one is done, so loading images isn't held up by the batch.
+pub struct BatchEditJob {
+    /// Files which weren't started yet.
+    file_paths: VecDeque<PathBuf>,
+    recipe: Arc<Recipe>,
+    options: Arc<BatchEditOptions>,
+    /// Where files are backed up before they are overwritten, `None` when they aren't.
+    backup_location: Option<Arc<BackupLocation>>,
+    file_running: bool,
+    cancelled: bool,
+    file_count: usize,
+    processed_files: usize,
+    failed_files: usize,
+}
+
+impl BatchEditJob {
+    pub fn new(
+        file_paths: Vec<PathBuf>,
+        recipe: Recipe,
+        options: BatchEditOptions,
+        backup_location: Option<BackupLocation>,
+    ) -> Self {
+        Self {
+            file_count: file_paths.len(),
+            file_paths: file_paths.into(),
+            recipe: Arc::new(recipe),
+            options: Arc::new(options),
+            backup_location: backup_location.map(Arc::new),
+            file_running: false,
+            cancelled: false,
+            processed_files: 0,
+            failed_files: 0,
+        }
+    }
+
+    /// Returns edit of the next file which can be run on a worker thread, `None` when
+    /// all files were started, the batch was cancelled or a file is still being edited.
+    pub fn next_file_edit(&mut self) -> Option<impl FnOnce() -> BatchEditProgress + Send> {
+        if self.cancelled || self.file_running {
+            return None;
+        }
+        let file_path = self.file_paths.pop_front()?;
+        self.file_running = true;
+        let recipe = self.recipe.clone();
+        let options = self.options.clone();
+        let backup_location = self.backup_location.clone();
+        Some(move || edit(&file_path, &recipe, &options, backup_location.as_deref()))
+    }
+
+    /// Stops the batch before the next file.
+    pub fn cancel(&mut self) {
+        self.cancelled = true;
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+    }
+
+    pub fn update(&mut self, progress: &BatchEditProgress) {
+        self.file_running = false;
+        self.processed_files += 1;
+        if let BatchEditProgress::FileFailed(_, _) = progress {
+            self.failed_files += 1;
+        }
+    }
+
+    /// Returns whether no file is being edited and no other will be started.
+    pub fn is_finished(&self) -> bool {
+        !self.file_running && (self.cancelled || self.file_paths.is_empty())
+    }
+
+    pub fn file_count(&self) -> usize {
+        self.file_count
+    }
+
+    pub fn processed_files(&self) -> usize {
+        self.processed_files
+    }
+
+    pub fn failed_files(&self) -> usize {
+        self.failed_files
+    }
+}
+
+/// Applies the recipe to the file and saves the result according to options. Pixbufs never
+/// leave this function, so it can be run on a worker thread.
+pub fn edit(
+    file_path: &Path,
+    recipe: &Recipe,
+    options: &BatchEditOptions,
+    backup_location: Option<&BackupLocation>,
+) -> BatchEditProgress {
+    match edit_file(file_path, recipe, options, backup_location) {
+        Ok(_) => BatchEditProgress::FileEdited(file_path.to_path_buf()),
+        Err(error) => BatchEditProgress::FileFailed(file_path.to_path_buf(), error.to_string()),
+    }
+}
+
+fn edit_file(
+    file_path: &Path,
+    recipe: &Recipe,
+    options: &BatchEditOptions,
+    backup_location: Option<&BackupLocation>,
+) -> Result<PathBuf> {
+    let image_buffer = decoder::load_image_buffer(file_path)?;
+    let image_size = (image_buffer.width() as u32, image_buffer.height() as u32);
+    let image_buffer = recipe.operations(image_size).iter().try_fold(
+        image_buffer,
+        |image_buffer, operation| {
+            image_buffer
+                .apply_operation(operation)
+                .ok_or_else(|| anyhow!("Couldn't apply {}", operation))
+        },
+    )?;
+    let output_path = options.output_path(file_path);
+    if let Some(backup_location) = backup_location {
+        backup_location.back_up(&output_path)?;
+    }
+    save_image_buffer(&image_buffer, &output_path)?;
+    Sidecar::remove(&output_path)?;
+    Ok(output_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use gtk::gdk_pixbuf::{Pixbuf, PixbufRotation};
+
+    use crate::{image_operation::ImageOperation, test_utils::TestResources};
+
+    use super::*;
+
+    const TEST_IMAGE: &[u8] = include_bytes!("resources/test/test_image.png");
+
+    fn resize_recipe() -> Recipe {
+        Recipe::from_operations(
+            &[
+                ImageOperation::Resize((64, 32)),
+                ImageOperation::Rotate(PixbufRotation::Clockwise),
+            ],
+            (128, 128),
+        )
+    }
+
+    #[test]
+    fn output_path_uses_folder_suffix_and_format() {
+        let options = BatchEditOptions {
+            output_location: OutputLocation::Folder(PathBuf::from("/output")),
+            file_name_suffix: String::from("_edited"),
+            output_format: Some(String::from("jpg")),
+        };
+        assert_eq!(
+            PathBuf::from("/output/photo_edited.jpg"),
+            options.output_path(Path::new("/photos/photo.png"))
+        );
+
+        let options = BatchEditOptions {
+            output_location: OutputLocation::SameFolder,
+            file_name_suffix: String::new(),
+            output_format: None,
+        };
+        assert_eq!(
+            PathBuf::from("/photos/photo.png"),
+            options.output_path(Path::new("/photos/photo.png"))
+        );
+    }
+
+    fn run(batch_edit_job: &mut BatchEditJob) -> Vec<BatchEditProgress> {
+        let mut progress = Vec::new();
+        while let Some(file_edit) = batch_edit_job.next_file_edit() {
+            let file_progress = file_edit();
+            batch_edit_job.update(&file_progress);
+            progress.push(file_progress);
+        }
+        assert!(batch_edit_job.is_finished());
+        progress
+    }
+
+    #[test]
+    fn run_edits_every_file_and_reports_errors() {
+        let mut test_resources = TestResources::new("test/run_edits_every_file");
+        test_resources.add_file("first.png", TEST_IMAGE);
+        test_resources.add_file("second.png", TEST_IMAGE);
+        test_resources.add_file("broken.png", b"not an image");
+        let file_paths = ["first.png", "broken.png", "second.png"]
+            .iter()
+            .map(|file_name| test_resources.file_folder().join(file_name))
+            .collect::<Vec<PathBuf>>();
+        let options = BatchEditOptions {
+            output_location: OutputLocation::SameFolder,
+            file_name_suffix: String::from("_edited"),
+            output_format: None,
+        };
+
+        let progress = run(&mut BatchEditJob::new(
+            file_paths.clone(),
+            resize_recipe(),
+            options,
+            None,
+        ));
+
+        assert_eq!(3, progress.len());
+        assert!(
+            matches!(&progress[1], BatchEditProgress::FileFailed(file_path, _) if file_path == &file_paths[1])
+        );
+        let (_, width, height) =
+            Pixbuf::file_info(test_resources.file_folder().join("second_edited.png")).unwrap();
+        assert_eq!((32, 64), (width, height));
+    }
+
+    #[test]
+    fn run_stops_when_cancelled() {
+        let mut test_resources = TestResources::new("test/run_stops_when_cancelled");
+        test_resources.add_file("test.png", TEST_IMAGE);
+        let options = BatchEditOptions {
+            output_location: OutputLocation::SameFolder,
+            file_name_suffix: String::from("_edited"),
+            output_format: None,
+        };
+
+        let mut batch_edit_job = BatchEditJob::new(
+            vec![test_resources.file_folder().join("test.png")],
+            resize_recipe(),
+            options,
+            None,
+        );
+        batch_edit_job.cancel();
+
+        assert!(run(&mut batch_edit_job).is_empty());
+        assert!(!test_resources
+            .file_folder()
+            .join("test_edited.png")
+            .exists());
+    }
+
+    #[test]
+    fn originals_are_backed_up_before_they_are_overwritten() {
+        let mut test_resources =
+            TestResources::new("test/originals_are_backed_up_before_they_are_overwritten");
+        test_resources.add_file("test.png", TEST_IMAGE);
+        let options = BatchEditOptions {
+            output_location: OutputLocation::SameFolder,
+            file_name_suffix: String::new(),
+            output_format: None,
+        };
+
+        run(&mut BatchEditJob::new(
+            vec![test_resources.file_folder().join("test.png")],
+            resize_recipe(),
+            options,
+            Some(BackupLocation::SameFolder),
+        ));
+
+        let (_, width, height) =
+            Pixbuf::file_info(test_resources.file_folder().join("test.png")).unwrap();
+        assert_eq!((32, 64), (width, height));
+        assert_eq!(
+            TEST_IMAGE,
+            std::fs::read(test_resources.file_folder().join("test.orig.png")).unwrap()
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use anyhow::{anyhow, Result};
+use gtk::gdk_pixbuf::{Colorspace, InterpType, Pixbuf};
+
+use crate::image_operation::Color;
+
+#[derive(Clone, Copy, Debug)]
+pub struct CollageLayout {
+    pub rows: u32,
+    pub columns: u32,
+    pub spacing: u32,
+    pub background_color: Color,
+    pub output_size: (u32, u32),
+}
+
+impl CollageLayout {
+    pub fn cell_size(&self) -> Option<(u32, u32)> {
+        let (output_width, output_height) = self.output_size;
+        let cell_width = output_width
+            .checked_sub(self.spacing * (self.columns + 1))?
+            .checked_div(self.columns)?;
+        let cell_height = output_height
+            .checked_sub(self.spacing * (self.rows + 1))?
+            .checked_div(self.rows)?;
+        Some((cell_width, cell_height)).filter(|(width, height)| *width > 0 && *height > 0)
+    }
+
+    pub fn capacity(&self) -> usize {
+        (self.rows * self.columns) as usize
+    }
+
+    fn cell_position(&self, index: usize, cell_size: (u32, u32)) -> (u32, u32) {
+        let (cell_width, cell_height) = cell_size;
+        let row = index as u32 / self.columns;
+        let column = index as u32 % self.columns;
+        (
+            self.spacing + column * (cell_width + self.spacing),
+            self.spacing + row * (cell_height + self.spacing),
+        )
+    }
+}
+
+pub fn create_collage(image_buffers: &[Pixbuf], layout: &CollageLayout) -> Result<Pixbuf> {
+    if image_buffers.is_empty() {
+        return Err(anyhow!("No images were selected for the collage"));
+    }
+    let cell_size = layout
+        .cell_size()
+        .ok_or_else(|| anyhow!("Collage output size is too small for the chosen layout"))?;
+    let (output_width, output_height) = layout.output_size;
+    let collage = Pixbuf::new(
+        Colorspace::Rgb,
+        false,
+        8,
+        output_width as i32,
+        output_height as i32,
+    )
+    .ok_or_else(|| anyhow!("Couldn't allocate collage image buffer"))?;
+    let (red, green, blue) = layout.background_color;
+    collage.fill(u32::from_be_bytes([red, green, blue, 255]));
+
+    for (index, image_buffer) in image_buffers.iter().take(layout.capacity()).enumerate() {
+        let (cell_width, cell_height) = cell_size;
+        let (cell_x, cell_y) = layout.cell_position(index, cell_size);
+        let image_width = image_buffer.width() as f64;
+        let image_height = image_buffer.height() as f64;
+        let scale_ratio = (cell_width as f64 / image_width).min(cell_height as f64 / image_height);
+        let scaled_width = ((image_width * scale_ratio) as i32).max(1);
+        let scaled_height = ((image_height * scale_ratio) as i32).max(1);
+        let x = cell_x as i32 + (cell_width as i32 - scaled_width) / 2;
+        let y = cell_y as i32 + (cell_height as i32 - scaled_height) / 2;
+        image_buffer.composite(
+            &collage,
+            x,
+            y,
+            scaled_width,
+            scaled_height,
+            x as f64,
+            y as f64,
+            scale_ratio,
+            scale_ratio,
+            InterpType::Bilinear,
+            255,
+        );
+    }
+
+    Ok(collage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(rows: u32, columns: u32) -> CollageLayout {
+        CollageLayout {
+            rows,
+            columns,
+            spacing: 10,
+            background_color: (0, 0, 255),
+            output_size: (210, 110),
+        }
+    }
+
+    fn filled_image_buffer(width: i32, height: i32, pixel: u32) -> Pixbuf {
+        let image_buffer = Pixbuf::new(Colorspace::Rgb, false, 8, width, height).unwrap();
+        image_buffer.fill(pixel);
+        image_buffer
+    }
+
+    fn color_at(image_buffer: &Pixbuf, x: usize, y: usize) -> (u8, u8, u8) {
+        let pixels = image_buffer.read_pixel_bytes().unwrap();
+        let position =
+            y * image_buffer.rowstride() as usize + x * image_buffer.n_channels() as usize;
+        (pixels[position], pixels[position + 1], pixels[position + 2])
+    }
+
+    #[test]
+    fn cell_size_takes_spacing_into_account() {
+        assert_eq!(Some((90, 90)), layout(1, 2).cell_size());
+        assert_eq!(None, layout(20, 20).cell_size());
+    }
+
+    #[test]
+    fn create_collage_has_output_size() {
+        let image_buffers = vec![filled_image_buffer(50, 50, 0xff0000ff); 2];
+
+        let collage = create_collage(&image_buffers, &layout(1, 2)).unwrap();
+
+        assert_eq!((210, 110), (collage.width(), collage.height()));
+    }
+
+    #[test]
+    fn create_collage_places_images_in_cells_on_background() {
+        let image_buffers = vec![
+            filled_image_buffer(50, 50, 0xff0000ff),
+            filled_image_buffer(50, 50, 0x00ff00ff),
+        ];
+
+        let collage = create_collage(&image_buffers, &layout(1, 2)).unwrap();
+
+        assert_eq!((0, 0, 255), color_at(&collage, 5, 5));
+        assert_eq!((255, 0, 0), color_at(&collage, 55, 55));
+        assert_eq!((0, 255, 0), color_at(&collage, 155, 55));
+    }
+
+    #[test]
+    fn create_collage_fails_without_images() {
+        assert!(create_collage(&[], &layout(1, 2)).is_err());
+    }
+}
@@ -0,0 +1,238 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use gtk::{
+    gdk_pixbuf::{Colorspace, InterpType, Pixbuf},
+    glib,
+};
+use image::{ColorType, DynamicImage, ImageFormat};
+use jxl_oxide::JxlImage;
+
+/// Extensions of formats which are decoded without gdk-pixbuf when it has no loader for them.
+pub const FALLBACK_EXTENSIONS: &[&str] = &[
+    "qoi", "dds", "exr", "hdr", "ff", "farbfeld", "pbm", "pgm", "ppm", "pnm", "pam", "jxl",
+];
+
+/// Decoder used for a file which gdk-pixbuf can't read.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FallbackDecoder {
+    Image(ImageFormat),
+    JpegXl,
+}
+
+impl FallbackDecoder {
+    fn for_path(path: &Path) -> Option<FallbackDecoder> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "jxl" => Some(FallbackDecoder::JpegXl),
+            extension if FALLBACK_EXTENSIONS.contains(&extension) => {
+                ImageFormat::from_extension(extension).map(FallbackDecoder::Image)
+            }
+            _ => None,
+        }
+    }
+
+    fn decode(self, path: &Path) -> Result<Pixbuf> {
+        match self {
+            FallbackDecoder::Image(format) => {
+                let image = image::io::Reader::with_format(
+                    std::io::BufReader::new(std::fs::File::open(path)?),
+                    format,
+                )
+                .decode()?;
+                image_buffer_from_image(image)
+            }
+            FallbackDecoder::JpegXl => {
+                let image = JxlImage::builder().open(path)?;
+                let render = image.render_frame(0)?;
+                let frame_buffer = render.image_all_channels();
+                let pixels = match frame_buffer.channels() {
+                    1 => frame_buffer
+                        .buf()
+                        .iter()
+                        .flat_map(|gray| [*gray, *gray, *gray, 1.0])
+                        .collect::<Vec<_>>(),
+                    2 => frame_buffer
+                        .buf()
+                        .chunks_exact(2)
+                        .flat_map(|pixel| [pixel[0], pixel[0], pixel[0], pixel[1]])
+                        .collect(),
+                    3 => frame_buffer
+                        .buf()
+                        .chunks_exact(3)
+                        .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 1.0])
+                        .collect(),
+                    _ => frame_buffer
+                        .buf()
+                        .chunks_exact(frame_buffer.channels())
+                        .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], pixel[3]])
+                        .collect(),
+                };
+                // samples are already encoded in sRGB
+                image_buffer_from_rgba(
+                    pixels.iter().map(|sample| to_u8(*sample)).collect(),
+                    frame_buffer.width() as u32,
+                    frame_buffer.height() as u32,
+                )
+            }
+        }
+    }
+
+    fn image_size(self, path: &Path) -> Option<(u32, u32)> {
+        match self {
+            FallbackDecoder::Image(format) => image::io::Reader::with_format(
+                std::io::BufReader::new(std::fs::File::open(path).ok()?),
+                format,
+            )
+            .into_dimensions()
+            .ok(),
+            FallbackDecoder::JpegXl => {
+                let image = JxlImage::builder().open(path).ok()?;
+                Some((image.width(), image.height()))
+            }
+        }
+    }
+}
+
+/// Returns whether the file is in a format which is decoded without gdk-pixbuf.
+pub fn has_fallback_decoder(path: &Path) -> bool {
+    FallbackDecoder::for_path(path).is_some()
+}
+
+/// Decodes the image with gdk-pixbuf, or with a fallback decoder
+/// when gdk-pixbuf has no loader for its format.
+pub fn load_image_buffer(path: &Path) -> Result<Pixbuf> {
+    match Pixbuf::from_file(path) {
+        Ok(image_buffer) => Ok(image_buffer),
+        Err(error) => match FallbackDecoder::for_path(path) {
+            Some(fallback_decoder) if Pixbuf::file_info(path).is_none() => {
+                fallback_decoder.decode(path)
+            }
+            _ => Err(error.into()),
+        },
+    }
+}
+
+/// Decodes the image scaled to fit in the size, keeping its aspect ratio.
+pub fn load_image_buffer_at_scale(path: &Path, width: i32, height: i32) -> Result<Pixbuf> {
+    match Pixbuf::from_file_at_scale(path, width, height, true) {
+        Ok(image_buffer) => Ok(image_buffer),
+        Err(error) => match FallbackDecoder::for_path(path) {
+            Some(fallback_decoder) if Pixbuf::file_info(path).is_none() => {
+                let image_buffer = fallback_decoder.decode(path)?;
+                let scale = f64::min(
+                    width as f64 / image_buffer.width() as f64,
+                    height as f64 / image_buffer.height() as f64,
+                );
+                image_buffer
+                    .scale_simple(
+                        ((image_buffer.width() as f64 * scale).round() as i32).max(1),
+                        ((image_buffer.height() as f64 * scale).round() as i32).max(1),
+                        InterpType::Bilinear,
+                    )
+                    .ok_or_else(|| anyhow!("Couldn't scale {}", path.display()))
+            }
+            _ => Err(error.into()),
+        },
+    }
+}
+
+/// Reads size of the image from its header.
+pub fn image_size(path: &Path) -> Option<(u32, u32)> {
+    match Pixbuf::file_info(path) {
+        Some((_, width, height)) => Some((width as u32, height as u32)),
+        None => FallbackDecoder::for_path(path)?.image_size(path),
+    }
+}
+
+fn image_buffer_from_image(image: DynamicImage) -> Result<Pixbuf> {
+    let (width, height) = (image.width(), image.height());
+    let pixels = match image.color() {
+        // high dynamic range images hold linear light, it is clipped and encoded in sRGB
+        ColorType::Rgb32F | ColorType::Rgba32F => image
+            .to_rgba32f()
+            .pixels()
+            .flat_map(|pixel| {
+                let [red, green, blue, alpha] = pixel.0;
+                [
+                    to_u8(linear_to_srgb(red)),
+                    to_u8(linear_to_srgb(green)),
+                    to_u8(linear_to_srgb(blue)),
+                    to_u8(alpha),
+                ]
+            })
+            .collect(),
+        _ => image.into_rgba8().into_raw(),
+    };
+    image_buffer_from_rgba(pixels, width, height)
+}
+
+fn image_buffer_from_rgba(pixels: Vec<u8>, width: u32, height: u32) -> Result<Pixbuf> {
+    if width == 0 || height == 0 || pixels.len() != width as usize * height as usize * 4 {
+        return Err(anyhow!("Image has no pixels"));
+    }
+    Ok(Pixbuf::from_bytes(
+        &glib::Bytes::from_owned(pixels),
+        Colorspace::Rgb,
+        true,
+        8,
+        width as i32,
+        height as i32,
+        width as i32 * 4,
+    ))
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn to_u8(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::TestResources;
+
+    use super::*;
+
+    #[test]
+    fn images_without_gdk_pixbuf_loader_are_decoded_by_fallback_decoder() {
+        let mut test_resources = TestResources::new(
+            "test/images_without_gdk_pixbuf_loader_are_decoded_by_fallback_decoder",
+        );
+        // 3x2 farbfeld image, pixels are big-endian 16-bit RGBA
+        let mut farbfeld = b"farbfeld".to_vec();
+        farbfeld.extend_from_slice(&3u32.to_be_bytes());
+        farbfeld.extend_from_slice(&2u32.to_be_bytes());
+        for pixel in 0..6u16 {
+            for sample in [pixel * 10000, 0, 65535, 65535] {
+                farbfeld.extend_from_slice(&sample.to_be_bytes());
+            }
+        }
+        test_resources.add_file("test.ff", farbfeld);
+        let image_path = test_resources.file_folder().join("test.ff");
+
+        assert!(has_fallback_decoder(&image_path));
+        assert_eq!(Some((3, 2)), image_size(&image_path));
+        let image_buffer = load_image_buffer(&image_path).unwrap();
+        assert_eq!((3, 2), (image_buffer.width(), image_buffer.height()));
+        let pixels = image_buffer.read_pixel_bytes().unwrap();
+        assert_eq!(&[39, 0, 255, 255], &pixels[4..8]);
+
+        let scaled_image_buffer = load_image_buffer_at_scale(&image_path, 6, 6).unwrap();
+        assert_eq!(
+            (6, 4),
+            (scaled_image_buffer.width(), scaled_image_buffer.height())
+        );
+
+        test_resources.add_file("test.txt", "test");
+        let text_path = test_resources.file_folder().join("test.txt");
+        assert!(!has_fallback_decoder(&text_path));
+        assert!(load_image_buffer(&text_path).is_err());
+    }
+}
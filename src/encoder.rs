@@ -0,0 +1,684 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use anyhow::{anyhow, Result};
+use gtk::gdk_pixbuf::Pixbuf;
+use jpeg_encoder::{ColorType, SamplingFactor};
+
+use crate::image_operation::{ApplyImageOperation, ImageOperation};
+
+/// Longest side of the part of an image which is encoded to estimate size of the whole file.
+const SIZE_ESTIMATE_SAMPLE_SIZE: i32 = 512;
+
+#[derive(Clone, Copy, Debug)]
+pub enum EncoderOptionKind {
+    /// Whole number within the range.
+    Range { min: u32, max: u32 },
+    /// `true` or `false`.
+    Toggle,
+    /// One of the values, each with a label shown to the user.
+    Choice(&'static [(&'static str, &'static str)]),
+}
+
+/// Option of an encoder, values of options are kept as strings.
+#[derive(Clone, Copy, Debug)]
+pub struct EncoderOption {
+    pub key: &'static str,
+    pub label: &'static str,
+    pub kind: EncoderOptionKind,
+    pub default_value: &'static str,
+}
+
+impl EncoderOption {
+    pub fn validate(&self, value: &str) -> Result<()> {
+        let is_valid = match self.kind {
+            EncoderOptionKind::Range { min, max } => value
+                .parse::<u32>()
+                .map_or(false, |value| (min..=max).contains(&value)),
+            EncoderOptionKind::Toggle => value == "true" || value == "false",
+            EncoderOptionKind::Choice(choices) => choices
+                .iter()
+                .any(|(choice_value, _)| *choice_value == value),
+        };
+        if is_valid {
+            Ok(())
+        } else {
+            Err(anyhow!("Invalid value {} of option {}", value, self.label))
+        }
+    }
+}
+
+/// Values of encoder options of all formats, options without a value use their default.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EncoderOptions {
+    /// Values by keys in the form `format/option`.
+    values: HashMap<String, String>,
+}
+
+impl EncoderOptions {
+    pub fn from_values(values: HashMap<String, String>) -> Self {
+        Self { values }
+    }
+
+    pub fn values(&self) -> &HashMap<String, String> {
+        &self.values
+    }
+
+    pub fn set(&mut self, encoder_name: &str, option: &EncoderOption, value: String) {
+        self.values
+            .insert(format!("{}/{}", encoder_name, option.key), value);
+    }
+
+    pub fn value<'a>(&'a self, encoder_name: &str, option: &'a EncoderOption) -> &'a str {
+        self.values
+            .get(&format!("{}/{}", encoder_name, option.key))
+            .map_or(option.default_value, String::as_str)
+    }
+}
+
+/// Writes images in one file format.
+pub trait Encoder: Send + Sync {
+    /// Name of the format shown to the user.
+    fn name(&self) -> &'static str;
+
+    /// Extensions of the format, the first one is used for new files.
+    fn extensions(&self) -> &'static [&'static str];
+
+    fn mime_types(&self) -> &'static [&'static str];
+
+    /// Images with transparency are flattened before they are encoded
+    /// into formats which don't support it.
+    fn supports_alpha(&self) -> bool;
+
+    fn options(&self) -> &'static [EncoderOption];
+
+    /// Encodes the image buffer into contents of a file, options are already validated
+    /// and images with transparency are passed only when the format supports it.
+    fn encode(&self, image_buffer: &Pixbuf, options: &EncoderOptions) -> Result<Vec<u8>>;
+}
+
+/// Encoder of a format which gdk-pixbuf can save.
+#[derive(Clone, Copy)]
+struct PixbufEncoder {
+    name: &'static str,
+    pixbuf_type: &'static str,
+    extensions: &'static [&'static str],
+    mime_types: &'static [&'static str],
+    supports_alpha: bool,
+    options: &'static [EncoderOption],
+}
+
+impl Encoder for PixbufEncoder {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        self.extensions
+    }
+
+    fn mime_types(&self) -> &'static [&'static str] {
+        self.mime_types
+    }
+
+    fn supports_alpha(&self) -> bool {
+        self.supports_alpha
+    }
+
+    fn options(&self) -> &'static [EncoderOption] {
+        self.options
+    }
+
+    fn encode(&self, image_buffer: &Pixbuf, options: &EncoderOptions) -> Result<Vec<u8>> {
+        let options = self
+            .options
+            .iter()
+            .map(|option| (option.key, options.value(self.name, option)))
+            .collect::<Vec<_>>();
+        Ok(image_buffer.save_to_bufferv(self.pixbuf_type, &options)?)
+    }
+}
+
+/// JPEG encoder, it is used instead of the gdk-pixbuf one which
+/// can't write progressive JPEGs nor choose chroma subsampling.
+struct JpegEncoder;
+
+const JPEG_OPTIONS: &[EncoderOption] = &[
+    EncoderOption {
+        key: "quality",
+        label: "Quality",
+        kind: EncoderOptionKind::Range { min: 1, max: 100 },
+        default_value: "90",
+    },
+    EncoderOption {
+        key: "subsampling",
+        label: "Chroma subsampling",
+        kind: EncoderOptionKind::Choice(&[
+            ("4:4:4", "4:4:4 (best colors)"),
+            ("4:2:2", "4:2:2"),
+            ("4:2:0", "4:2:0 (smallest file)"),
+        ]),
+        default_value: "4:2:0",
+    },
+    EncoderOption {
+        key: "progressive",
+        label: "Progressive",
+        kind: EncoderOptionKind::Toggle,
+        default_value: "false",
+    },
+];
+
+impl Encoder for JpegEncoder {
+    fn name(&self) -> &'static str {
+        "JPEG"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["jpg", "jpeg"]
+    }
+
+    fn mime_types(&self) -> &'static [&'static str] {
+        &["image/jpeg"]
+    }
+
+    fn supports_alpha(&self) -> bool {
+        false
+    }
+
+    fn options(&self) -> &'static [EncoderOption] {
+        JPEG_OPTIONS
+    }
+
+    fn encode(&self, image_buffer: &Pixbuf, options: &EncoderOptions) -> Result<Vec<u8>> {
+        let option_value =
+            |option_index: usize| options.value(self.name(), &JPEG_OPTIONS[option_index]);
+        let (width, height) = match (
+            u16::try_from(image_buffer.width()),
+            u16::try_from(image_buffer.height()),
+        ) {
+            (Ok(width), Ok(height)) => (width, height),
+            _ => return Err(anyhow!("Image is too large to be saved as JPEG")),
+        };
+        let color_type = match image_buffer.n_channels() {
+            3 => ColorType::Rgb,
+            _ => ColorType::Rgba,
+        };
+
+        let mut contents = Vec::new();
+        let mut encoder = jpeg_encoder::Encoder::new(&mut contents, option_value(0).parse()?);
+        encoder.set_sampling_factor(match option_value(1) {
+            "4:4:4" => SamplingFactor::R_4_4_4,
+            "4:2:2" => SamplingFactor::R_4_2_2,
+            _ => SamplingFactor::R_4_2_0,
+        });
+        encoder.set_progressive(option_value(2) == "true");
+        encoder.encode(&packed_pixels(image_buffer)?, width, height, color_type)?;
+        Ok(contents)
+    }
+}
+
+/// Returns pixels of the image buffer without padding at the end of rows.
+fn packed_pixels(image_buffer: &Pixbuf) -> Result<Vec<u8>> {
+    let pixel_bytes = image_buffer
+        .read_pixel_bytes()
+        .ok_or_else(|| anyhow!("Couldn't read pixels of the image"))?;
+    let row_length = image_buffer.width() as usize * image_buffer.n_channels() as usize;
+    let rowstride = image_buffer.rowstride() as usize;
+    Ok((0..image_buffer.height() as usize)
+        .flat_map(|row| &pixel_bytes[row * rowstride..row * rowstride + row_length])
+        .copied()
+        .collect())
+}
+
+/// WebP encoder, lossless images are written by a pure Rust encoder and lossy ones by
+/// libwebp, since `image-webp` only encodes losslessly and no pure Rust lossy encoder exists.
+struct WebPEncoder;
+
+const WEBP_OPTIONS: &[EncoderOption] = &[
+    EncoderOption {
+        key: "lossless",
+        label: "Lossless",
+        kind: EncoderOptionKind::Toggle,
+        default_value: "false",
+    },
+    EncoderOption {
+        key: "quality",
+        label: "Quality of lossy images",
+        kind: EncoderOptionKind::Range { min: 1, max: 100 },
+        default_value: "80",
+    },
+];
+
+impl Encoder for WebPEncoder {
+    fn name(&self) -> &'static str {
+        "WebP"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["webp"]
+    }
+
+    fn mime_types(&self) -> &'static [&'static str] {
+        &["image/webp"]
+    }
+
+    fn supports_alpha(&self) -> bool {
+        true
+    }
+
+    fn options(&self) -> &'static [EncoderOption] {
+        WEBP_OPTIONS
+    }
+
+    fn encode(&self, image_buffer: &Pixbuf, options: &EncoderOptions) -> Result<Vec<u8>> {
+        let width = image_buffer.width() as u32;
+        let height = image_buffer.height() as u32;
+        let pixels = packed_pixels(image_buffer)?;
+        let has_alpha = image_buffer.n_channels() == 4;
+
+        if options.value(self.name(), &WEBP_OPTIONS[0]) == "true" {
+            let color_type = if has_alpha {
+                image_webp::ColorType::Rgba8
+            } else {
+                image_webp::ColorType::Rgb8
+            };
+            let mut contents = Vec::new();
+            image_webp::WebPEncoder::new(&mut contents)
+                .encode(&pixels, width, height, color_type)?;
+            return Ok(contents);
+        }
+
+        let quality = options.value(self.name(), &WEBP_OPTIONS[1]).parse()?;
+        let encoder = if has_alpha {
+            webp::Encoder::from_rgba(&pixels, width, height)
+        } else {
+            webp::Encoder::from_rgb(&pixels, width, height)
+        };
+        Ok(encoder.encode(quality).to_vec())
+    }
+}
+
+/// AVIF encoder, images are encoded with rav1e.
+struct AvifEncoder;
+
+const AVIF_OPTIONS: &[EncoderOption] = &[
+    EncoderOption {
+        key: "quality",
+        label: "Quality",
+        kind: EncoderOptionKind::Range { min: 1, max: 100 },
+        default_value: "70",
+    },
+    EncoderOption {
+        key: "speed",
+        label: "Speed, faster makes larger files",
+        kind: EncoderOptionKind::Range { min: 1, max: 10 },
+        default_value: "6",
+    },
+];
+
+impl Encoder for AvifEncoder {
+    fn name(&self) -> &'static str {
+        "AVIF"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["avif"]
+    }
+
+    fn mime_types(&self) -> &'static [&'static str] {
+        &["image/avif"]
+    }
+
+    fn supports_alpha(&self) -> bool {
+        true
+    }
+
+    fn options(&self) -> &'static [EncoderOption] {
+        AVIF_OPTIONS
+    }
+
+    fn encode(&self, image_buffer: &Pixbuf, options: &EncoderOptions) -> Result<Vec<u8>> {
+        let quality = options.value(self.name(), &AVIF_OPTIONS[0]).parse()?;
+        let speed = options.value(self.name(), &AVIF_OPTIONS[1]).parse()?;
+        let n_channels = image_buffer.n_channels() as usize;
+        // opaque images are detected by the encoder, which leaves out the alpha channel
+        let pixels = packed_pixels(image_buffer)?
+            .chunks_exact(n_channels)
+            .map(|pixel| {
+                ravif::RGBA8::new(
+                    pixel[0],
+                    pixel[1],
+                    pixel[2],
+                    pixel.get(3).copied().unwrap_or(255),
+                )
+            })
+            .collect::<Vec<_>>();
+        let encoded_image = ravif::Encoder::new()
+            .with_quality(quality)
+            .with_alpha_quality(quality)
+            .with_speed(speed)
+            .encode_rgba(ravif::Img::new(
+                pixels.as_slice(),
+                image_buffer.width() as usize,
+                image_buffer.height() as usize,
+            ))?;
+        Ok(encoded_image.avif_file)
+    }
+}
+
+/// QOI encoder, the format is lossless and has no options.
+struct QoiEncoder;
+
+impl Encoder for QoiEncoder {
+    fn name(&self) -> &'static str {
+        "QOI"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["qoi"]
+    }
+
+    fn mime_types(&self) -> &'static [&'static str] {
+        &["image/qoi", "image/x-qoi"]
+    }
+
+    fn supports_alpha(&self) -> bool {
+        true
+    }
+
+    fn options(&self) -> &'static [EncoderOption] {
+        &[]
+    }
+
+    fn encode(&self, image_buffer: &Pixbuf, _options: &EncoderOptions) -> Result<Vec<u8>> {
+        Ok(qoi::encode_to_vec(
+            packed_pixels(image_buffer)?,
+            image_buffer.width() as u32,
+            image_buffer.height() as u32,
+        )?)
+    }
+}
+
+const PIXBUF_ENCODERS: &[PixbufEncoder] = &[
+    PixbufEncoder {
+        name: "PNG",
+        pixbuf_type: "png",
+        extensions: &["png"],
+        mime_types: &["image/png"],
+        supports_alpha: true,
+        options: &[EncoderOption {
+            key: "compression",
+            label: "Compression",
+            kind: EncoderOptionKind::Range { min: 0, max: 9 },
+            default_value: "9",
+        }],
+    },
+    PixbufEncoder {
+        name: "TIFF",
+        pixbuf_type: "tiff",
+        extensions: &["tiff", "tif"],
+        mime_types: &["image/tiff"],
+        supports_alpha: true,
+        // values are libtiff compression codecs
+        options: &[EncoderOption {
+            key: "compression",
+            label: "Compression",
+            kind: EncoderOptionKind::Choice(&[("1", "None"), ("5", "LZW"), ("8", "Deflate")]),
+            default_value: "1",
+        }],
+    },
+    PixbufEncoder {
+        name: "BMP",
+        pixbuf_type: "bmp",
+        extensions: &["bmp"],
+        mime_types: &["image/bmp"],
+        supports_alpha: false,
+        options: &[],
+    },
+    PixbufEncoder {
+        name: "ICO",
+        pixbuf_type: "ico",
+        extensions: &["ico"],
+        mime_types: &["image/vnd.microsoft.icon"],
+        supports_alpha: true,
+        options: &[],
+    },
+];
+
+/// Encoders of all formats images can be saved in.
+#[derive(Default)]
+pub struct EncoderRegistry {
+    encoders: Vec<Box<dyn Encoder>>,
+}
+
+impl EncoderRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns registry with encoders of all formats supported by the application.
+    pub fn with_builtin_encoders() -> Self {
+        let mut registry = EncoderRegistry::new();
+        registry.register(Box::new(PIXBUF_ENCODERS[0]));
+        registry.register(Box::new(JpegEncoder));
+        for pixbuf_encoder in &PIXBUF_ENCODERS[1..] {
+            registry.register(Box::new(*pixbuf_encoder));
+        }
+        registry.register(Box::new(WebPEncoder));
+        registry.register(Box::new(AvifEncoder));
+        registry.register(Box::new(QoiEncoder));
+        registry
+    }
+
+    /// Adds the encoder, encoders registered earlier take precedence for shared extensions.
+    pub fn register(&mut self, encoder: Box<dyn Encoder>) {
+        self.encoders.push(encoder);
+    }
+
+    pub fn encoders(&self) -> impl Iterator<Item = &dyn Encoder> {
+        self.encoders.iter().map(|encoder| encoder.as_ref())
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&dyn Encoder> {
+        self.encoders().find(|encoder| encoder.name() == name)
+    }
+
+    pub fn by_extension(&self, extension: &str) -> Option<&dyn Encoder> {
+        let extension = extension.to_lowercase();
+        self.encoders()
+            .find(|encoder| encoder.extensions().contains(&extension.as_str()))
+    }
+
+    /// Returns encoder for the extension of the path, unknown extensions are an error.
+    pub fn by_path(&self, path: &Path) -> Result<&dyn Encoder> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .ok_or_else(|| anyhow!("File path doesn't have file extension"))?;
+        self.by_extension(extension).ok_or_else(|| {
+            anyhow!(
+                "Images can't be saved as {} files, supported formats are {}",
+                extension,
+                self.encoders()
+                    .map(|encoder| encoder.name())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+    }
+
+    /// Appends extension of the format to the path unless it already has
+    /// an extension of a supported format.
+    pub fn with_extension_of(&self, path: PathBuf, encoder: &dyn Encoder) -> PathBuf {
+        if self.by_path(&path).is_ok() {
+            return path;
+        }
+        let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".");
+        file_name.push(encoder.extensions()[0]);
+        path.with_file_name(file_name)
+    }
+}
+
+/// Validates options and encodes the image buffer into contents of a file, transparency
+/// is flattened onto white for formats which don't support it.
+pub fn encode_image_buffer(
+    encoder: &dyn Encoder,
+    image_buffer: &Pixbuf,
+    options: &EncoderOptions,
+) -> Result<Vec<u8>> {
+    for option in encoder.options() {
+        option.validate(options.value(encoder.name(), option))?;
+    }
+    if image_buffer.has_alpha() && !encoder.supports_alpha() {
+        let flattened_image_buffer = image_buffer
+            .apply_operation(&ImageOperation::FlattenOntoColor((255, 255, 255)))
+            .ok_or_else(|| anyhow!("Couldn't remove transparency of the image"))?;
+        return encoder.encode(&flattened_image_buffer, options);
+    }
+    encoder.encode(image_buffer, options)
+}
+
+/// Estimates size in bytes of the file with the image encoded with given options. Only
+/// a part from the middle of the image buffer is encoded and its size is scaled to
+/// `image_size`, the size in which the image is saved.
+pub fn estimate_encoded_size(
+    encoder: &dyn Encoder,
+    image_buffer: &Pixbuf,
+    image_size: (u32, u32),
+    options: &EncoderOptions,
+) -> Result<u64> {
+    let sample_width = image_buffer.width().min(SIZE_ESTIMATE_SAMPLE_SIZE);
+    let sample_height = image_buffer.height().min(SIZE_ESTIMATE_SAMPLE_SIZE);
+    let sample = image_buffer
+        .new_subpixbuf(
+            (image_buffer.width() - sample_width) / 2,
+            (image_buffer.height() - sample_height) / 2,
+            sample_width,
+            sample_height,
+        )
+        .ok_or_else(|| anyhow!("Couldn't cut out a sample of the image"))?;
+    let sample_size = encode_image_buffer(encoder, &sample, options)?.len() as u64;
+    let (image_width, image_height) = image_size;
+    Ok(sample_size * image_width as u64 * image_height as u64
+        / (sample_width as u64 * sample_height as u64).max(1))
+}
+
+/// Returns registry with encoders of all formats supported by the application.
+pub fn encoders() -> &'static EncoderRegistry {
+    static ENCODERS: OnceLock<EncoderRegistry> = OnceLock::new();
+    ENCODERS.get_or_init(EncoderRegistry::with_builtin_encoders)
+}
+
+#[cfg(test)]
+mod tests {
+    use gtk::gdk_pixbuf::{prelude::PixbufLoaderExt, PixbufLoader};
+
+    use super::*;
+
+    #[test]
+    fn encoders_are_found_by_extension_and_unknown_extensions_are_errors() {
+        let registry = EncoderRegistry::with_builtin_encoders();
+        assert_eq!("JPEG", registry.by_extension("JPG").unwrap().name());
+        assert_eq!(
+            "TIFF",
+            registry
+                .by_path(Path::new("/images/test.tif"))
+                .unwrap()
+                .name()
+        );
+        assert!(registry.by_path(Path::new("/images/test.gif")).is_err());
+        assert!(registry.by_path(Path::new("/images/test")).is_err());
+
+        let png_encoder = registry.by_name("PNG").unwrap();
+        assert_eq!(
+            PathBuf::from("/images/test.gif.png"),
+            registry.with_extension_of(PathBuf::from("/images/test.gif"), png_encoder)
+        );
+        assert_eq!(
+            PathBuf::from("/images/test.jpg"),
+            registry.with_extension_of(PathBuf::from("/images/test.jpg"), png_encoder)
+        );
+    }
+
+    #[test]
+    fn option_values_are_validated() {
+        let quality = encoders().by_name("JPEG").unwrap().options()[0];
+        assert!(quality.validate("85").is_ok());
+        assert!(quality.validate("101").is_err());
+        assert!(quality.validate("high").is_err());
+
+        let mut options = EncoderOptions::default();
+        assert_eq!("90", options.value("JPEG", &quality));
+        options.set("JPEG", &quality, String::from("85"));
+        assert_eq!("85", options.value("JPEG", &quality));
+        assert_eq!("90", options.value("WebP", &quality));
+    }
+
+    #[test]
+    fn lower_jpeg_quality_makes_smaller_files() {
+        let image_buffer = Pixbuf::from_file("src/resources/test/test_image.png").unwrap();
+        let image_size = (
+            image_buffer.width() as u32 * 4,
+            image_buffer.height() as u32 * 4,
+        );
+        let jpeg_encoder = encoders().by_name("JPEG").unwrap();
+        let quality = jpeg_encoder.options()[0];
+        let progressive = jpeg_encoder.options()[2];
+        let mut options = EncoderOptions::default();
+        options.set("JPEG", &progressive, String::from("true"));
+
+        options.set("JPEG", &quality, String::from("95"));
+        let high_quality_size =
+            estimate_encoded_size(jpeg_encoder, &image_buffer, image_size, &options).unwrap();
+        options.set("JPEG", &quality, String::from("40"));
+        let low_quality_contents =
+            encode_image_buffer(jpeg_encoder, &image_buffer, &options).unwrap();
+        let low_quality_size =
+            estimate_encoded_size(jpeg_encoder, &image_buffer, image_size, &options).unwrap();
+
+        assert!(low_quality_size < high_quality_size);
+        assert_eq!(low_quality_contents.len() as u64 * 16, low_quality_size);
+        let pixbuf_loader = PixbufLoader::new();
+        pixbuf_loader.write(&low_quality_contents).unwrap();
+        pixbuf_loader.close().unwrap();
+        assert_eq!(
+            image_buffer.width(),
+            pixbuf_loader.pixbuf().unwrap().width()
+        );
+
+        options.set("JPEG", &quality, String::from("0"));
+        assert!(encode_image_buffer(jpeg_encoder, &image_buffer, &options).is_err());
+    }
+
+    #[test]
+    fn lossless_formats_keep_pixels_and_transparency() {
+        let image_buffer = Pixbuf::from_file("src/resources/test/test_image.png")
+            .unwrap()
+            .add_alpha(true, 255, 255, 255)
+            .unwrap();
+        let webp_encoder = encoders().by_name("WebP").unwrap();
+        let mut options = EncoderOptions::default();
+        options.set("WebP", &webp_encoder.options()[0], String::from("true"));
+
+        let webp_contents = encode_image_buffer(webp_encoder, &image_buffer, &options).unwrap();
+        let (webp_width, webp_height) =
+            image_webp::WebPDecoder::new(std::io::Cursor::new(webp_contents))
+                .unwrap()
+                .dimensions();
+        assert_eq!(
+            (image_buffer.width() as u32, image_buffer.height() as u32),
+            (webp_width, webp_height)
+        );
+
+        let qoi_contents =
+            encode_image_buffer(encoders().by_name("QOI").unwrap(), &image_buffer, &options)
+                .unwrap();
+        let (qoi_header, qoi_pixels) = qoi::decode_to_vec(qoi_contents).unwrap();
+        assert_eq!(qoi::Channels::Rgba, qoi_header.channels);
+        assert_eq!(packed_pixels(&image_buffer).unwrap(), qoi_pixels);
+    }
+}
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Result};
 use gtk::{
@@ -6,6 +6,8 @@ use gtk::{
     prelude::FileExt,
 };
 
+use crate::decoder;
+
 pub struct FileList {
     file_list: Vec<gio::FileInfo>,
     current_file: Option<(usize, gio::File)>,
@@ -141,6 +143,31 @@ impl FileList {
         }
     }
 
+    /// Makes the file at given path the current file if it's in the current folder.
+    pub fn select_file(&mut self, file_path: &Path) -> bool {
+        let current_folder = match &self.current_folder {
+            Some(current_folder) => current_folder,
+            None => return false,
+        };
+        if current_folder.path().as_deref() != file_path.parent() {
+            return false;
+        }
+        let file_index = self
+            .file_list
+            .iter()
+            .position(|file| Some(file.name().as_os_str()) == file_path.file_name());
+        match file_index {
+            Some(file_index) => {
+                self.current_file = Some((
+                    file_index,
+                    current_folder.child(self.file_list[file_index].name()),
+                ));
+                true
+            }
+            None => false,
+        }
+    }
+
     // pub fn current_folder(&self) -> Option<&gio::File> {
     //     self.current_folder.as_ref()
     // }
@@ -160,6 +187,46 @@ impl FileList {
         self.current_file.as_ref().and_then(|(_, file)| file.path())
     }
 
+    pub fn file_paths(&self) -> Vec<PathBuf> {
+        match &self.current_folder {
+            Some(current_folder) => self
+                .file_list
+                .iter()
+                .filter_map(|file| current_folder.child(file.name()).path())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns paths of up to `next_count` files after the current one and `previous_count`
+    /// files before it, wrapping around like `next` and `previous`. Nearest files come first.
+    pub fn neighbour_file_paths(&self, next_count: usize, previous_count: usize) -> Vec<PathBuf> {
+        let file_count = self.file_list.len();
+        let (current_index, current_folder) = match (&self.current_file, &self.current_folder) {
+            (Some((current_index, _)), Some(current_folder)) if file_count > 0 => {
+                (*current_index, current_folder)
+            }
+            _ => return Vec::new(),
+        };
+        let mut neighbour_indices: Vec<usize> = Vec::new();
+        for distance in 1..=next_count.max(previous_count) {
+            let next_index = (current_index + distance) % file_count;
+            let previous_index = (current_index + file_count - distance % file_count) % file_count;
+            for (index, count) in [(next_index, next_count), (previous_index, previous_count)] {
+                if distance <= count
+                    && index != current_index
+                    && !neighbour_indices.contains(&index)
+                {
+                    neighbour_indices.push(index);
+                }
+            }
+        }
+        neighbour_indices
+            .into_iter()
+            .filter_map(|index| current_folder.child(self.file_list[index].name()).path())
+            .collect()
+    }
+
     pub fn len(&self) -> usize {
         self.file_list.len()
     }
@@ -178,6 +245,7 @@ impl FileList {
                 file.content_type()
                     .filter(|content_type| content_type.to_string().starts_with("image"))
                     .is_some()
+                    || decoder::has_fallback_decoder(&file.name())
             })
             .collect())
     }
@@ -243,6 +311,22 @@ mod tests {
         assert_eq!(2, file_list.len());
     }
 
+    #[test]
+    fn file_list_contains_files_with_fallback_decoder() {
+        let mut test_resources =
+            TestResources::new("test/file_list_contains_files_with_fallback_decoder");
+        test_resources.add_file("test.png", TEST_IMAGE);
+        test_resources.add_file("test.ff", "farbfeld");
+        test_resources.add_file("test.txt", "test");
+
+        let file_list = FileList::new(Some(gio::File::for_path(
+            test_resources.file_folder().join("test.png"),
+        )))
+        .unwrap();
+
+        assert_eq!(2, file_list.len());
+    }
+
     #[test]
     fn file_list_contains_images_without_extension() {
         let mut test_resources =
@@ -319,6 +403,82 @@ mod tests {
         }
     }
 
+    #[test]
+    fn file_paths_are_in_file_list_order() {
+        let mut test_resources = TestResources::new("test/file_paths_are_in_file_list_order");
+        test_resources.add_file("b.png", TEST_IMAGE);
+        test_resources.add_file("a.png", TEST_IMAGE);
+
+        let file_list = FileList::new(Some(gio::File::for_path(
+            test_resources.file_folder().join("b.png"),
+        )))
+        .unwrap();
+
+        assert_eq!(
+            vec!["a.png", "b.png"],
+            file_list
+                .file_paths()
+                .iter()
+                .map(|file_path| file_path.file_name().unwrap().to_str().unwrap())
+                .collect::<Vec<_>>()
+        );
+        assert!(FileList::new(None).unwrap().file_paths().is_empty());
+    }
+
+    #[test]
+    fn neighbour_file_paths_wrap_around_and_start_with_nearest() {
+        let mut test_resources = TestResources::new("test/neighbour_file_paths_wrap_around");
+        for file_name in ["a.png", "b.png", "c.png", "d.png"] {
+            test_resources.add_file(file_name, TEST_IMAGE);
+        }
+
+        let file_list = FileList::new(Some(gio::File::for_path(
+            test_resources.file_folder().join("a.png"),
+        )))
+        .unwrap();
+        let neighbour_file_names = |next_count, previous_count| {
+            file_list
+                .neighbour_file_paths(next_count, previous_count)
+                .iter()
+                .map(|file_path| file_path.file_name().unwrap().to_str().unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(vec!["b.png", "d.png", "c.png"], neighbour_file_names(2, 1));
+        assert_eq!(vec!["b.png", "d.png", "c.png"], neighbour_file_names(5, 5));
+        assert!(neighbour_file_names(0, 0).is_empty());
+        assert!(FileList::new(None)
+            .unwrap()
+            .neighbour_file_paths(2, 1)
+            .is_empty());
+    }
+
+    #[test]
+    fn select_file_changes_current_file() {
+        let mut test_resources = TestResources::new("test/select_file_changes_current_file");
+        test_resources.add_file("test1.png", TEST_IMAGE);
+        test_resources.add_file("test2.png", TEST_IMAGE);
+
+        let mut file_list = FileList::new(Some(gio::File::for_path(
+            test_resources.file_folder().join("test1.png"),
+        )))
+        .unwrap();
+        let current_folder = file_list.current_file_path().unwrap();
+        let current_folder = current_folder.parent().unwrap();
+
+        assert!(file_list.select_file(&current_folder.join("test2.png")));
+        assert_eq!(
+            current_folder.join("test2.png"),
+            file_list.current_file_path().unwrap()
+        );
+        assert!(!file_list.select_file(&current_folder.join("test3.png")));
+        assert!(!file_list.select_file(Path::new("test1.png")));
+        assert_eq!(
+            current_folder.join("test2.png"),
+            file_list.current_file_path().unwrap()
+        );
+    }
+
     #[test]
     fn refresh_file_list_loads_new_images() {
         let mut test_resources = TestResources::new("test/refresh_file_list_loads_new_images");
@@ -1,134 +1,653 @@
-use std::path::Path;
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
 use anyhow::{anyhow, Result};
 use gtk::gdk_pixbuf::{InterpType, Pixbuf};
 
-use crate::image_operation::{ApplyImageOperation, ImageOperation};
+use crate::{
+    decoder,
+    encoder::{encode_image_buffer, encoders, EncoderOptions},
+    image_operation::{normalize_operations, ApplyImageOperation, Color, ImageOperation},
+    recipe::Recipe,
+    sidecar::Sidecar,
+    snapshot_cache::{MemoryBudget, SnapshotCache},
+    tiled_preview::TiledPreview,
+    tiles::TileLayout,
+    worker::SendableImageBuffer,
+};
 
 pub type Coordinates = (u32, u32);
 pub type CoordinatesPair = (Coordinates, Coordinates);
 
+const SNAPSHOT_INTERVAL: usize = 8;
+/// Memory for snapshots of all images together.
+static SNAPSHOT_MEMORY_BUDGET: MemoryBudget = MemoryBudget::new(256 * 1024 * 1024);
+/// Images with more pixels than this are edited on a downscaled copy of the original.
+const DOWNSCALED_EDITING_MIN_PIXELS: u64 = 36_000_000;
+/// Size of the longer side of the downscaled copy of the original.
+const DOWNSCALED_EDITING_SIZE: u32 = 3000;
+
 pub struct Image {
     original_image_buffer: Option<Pixbuf>,
+    /// Downscaled copy of the original which operations are applied to while editing large
+    /// images, current image buffer and snapshots have its resolution then. Operations keep
+    /// positions in full resolution and are applied to the original when the image is saved.
+    downscaled_original_image_buffer: Option<Pixbuf>,
     current_image_buffer: Option<Pixbuf>,
     preview_image_buffer: Option<Pixbuf>,
+    /// Used instead of preview image buffer when the preview would be too large.
+    tiled_preview: Option<TiledPreview>,
+    /// Preview of the original image at the zoom of the current preview,
+    /// created when they are compared.
+    original_preview: Option<TiledPreview>,
+    /// Size at which the preview is drawn, it differs from size of the preview
+    /// while zooming, until a new preview is created.
+    display_size: Option<(u32, u32)>,
     operations: Vec<ImageOperation>,
     current_operation_index: Option<usize>,
+    snapshot_cache: SnapshotCache,
+    /// Downscaled image shown until the full image is decoded, with size of the full image.
+    proxy: Option<(Pixbuf, (u32, u32))>,
+    unsaved: bool,
+    /// Stamp of the file when it was decoded or saved, `None` for images without a file.
+    file_stamp: Option<FileStamp>,
+    /// Given by the image list when the image is inserted into it, it changes
+    /// when the image is loaded again.
+    load_generation: u64,
+    /// True when edits changed since they were last written to the sidecar file.
+    dirty: bool,
+}
+
+/// State of an image an operation was started on, result of the operation
+/// belongs only to the image in the same state.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OperationTag {
+    load_generation: u64,
+    operation_index: Option<usize>,
+}
+
+/// Modification time and size of an image file, they change when another program
+/// writes to it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FileStamp {
+    modified: SystemTime,
+    size: u64,
+}
+
+impl FileStamp {
+    pub fn read(path: &Path) -> Result<FileStamp> {
+        let metadata = fs::metadata(path)?;
+        Ok(FileStamp {
+            modified: metadata.modified()?,
+            size: metadata.len(),
+        })
+    }
 }
 
 impl Image {
+    /// Loads the image and applies edits stored in its sidecar file.
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Image> {
-        let image_buffer = Pixbuf::from_file(path)?;
-        Ok(Image {
+        let file_stamp = FileStamp::read(path.as_ref())?;
+        let image_buffer = decoder::load_image_buffer(path.as_ref())?;
+        let mut image = Image {
             original_image_buffer: Some(image_buffer.clone()),
+            downscaled_original_image_buffer: None,
             current_image_buffer: Some(image_buffer),
             preview_image_buffer: None,
+            tiled_preview: None,
+            original_preview: None,
+            display_size: None,
             operations: Vec::new(),
             current_operation_index: None,
-        })
+            snapshot_cache: SnapshotCache::new(SNAPSHOT_INTERVAL, &SNAPSHOT_MEMORY_BUDGET),
+            proxy: None,
+            unsaved: false,
+            file_stamp: Some(file_stamp),
+            load_generation: 0,
+            dirty: false,
+        };
+        image.edit_downscaled_if_large();
+        match Sidecar::load(path.as_ref()) {
+            Ok(Some(sidecar)) => {
+                image.operations = sidecar.operations;
+                image.current_operation_index = sidecar.current_operation_index;
+                image.rebuild_current_image_buffer();
+            }
+            Ok(None) => {}
+            Err(error) => warn!("Couldn't load edits: {}", error),
+        }
+        Ok(image)
     }
 
-    pub fn save<P: AsRef<Path>>(&mut self, path: P, clear_operations: bool) -> Result<()> {
-        let current_image_buffer = self
-            .current_image_buffer
-            .as_mut()
-            .ok_or_else(|| anyhow!("Image buffer is missing!"))?;
-        let extension = path
-            .as_ref()
-            .extension()
-            .and_then(|extension| extension.to_str())
-            .ok_or_else(|| anyhow!("File path doesn't have file extension"))?;
-        let lowercase_extension = extension.to_lowercase();
-        let file_type = match lowercase_extension.as_str() {
-            file_type @ "jpeg"
-            | file_type @ "png"
-            | file_type @ "tiff"
-            | file_type @ "ico"
-            | file_type @ "bmp" => file_type,
-            "jpg" => "jpeg",
-            "tif" => "tiff",
-            _ => "png",
-        };
+    pub fn from_image_buffer(image_buffer: Pixbuf) -> Image {
+        Image {
+            original_image_buffer: Some(image_buffer.clone()),
+            downscaled_original_image_buffer: None,
+            current_image_buffer: Some(image_buffer),
+            preview_image_buffer: None,
+            tiled_preview: None,
+            original_preview: None,
+            display_size: None,
+            operations: Vec::new(),
+            current_operation_index: None,
+            snapshot_cache: SnapshotCache::new(SNAPSHOT_INTERVAL, &SNAPSHOT_MEMORY_BUDGET),
+            proxy: None,
+            unsaved: true,
+            file_stamp: None,
+            load_generation: 0,
+            dirty: false,
+        }
+    }
 
-        let options: &[(&str, &str)] = match file_type {
-            "jpeg" => &[("quality", "100")],
-            "png" => &[("compression", "9")],
-            _ => &[],
-        };
-        current_image_buffer.savev(path.as_ref(), file_type, options)?;
+    /// Updates the image after its full resolution buffer was written into the file. When
+    /// the image overwrote its own file, the file becomes its original and edits are cleared.
+    pub fn mark_saved(
+        &mut self,
+        path: &Path,
+        image_buffer: Pixbuf,
+        clear_operations: bool,
+    ) -> Result<()> {
+        self.unsaved = false;
         if clear_operations {
-            self.original_image_buffer = Some(current_image_buffer.clone());
+            self.file_stamp = Some(FileStamp::read(path)?);
+            // buffers dropped while the image was written are decoded from the file again
+            if self.has_image_buffers() {
+                self.original_image_buffer = Some(image_buffer);
+                if self.downscaled_original_image_buffer.is_some() {
+                    self.downscaled_original_image_buffer = self.current_image_buffer.clone();
+                }
+            }
             self.current_operation_index = None;
             self.operations.clear();
+            self.snapshot_cache.clear();
+            self.dirty = true;
+            Sidecar::remove(path)?;
+            self.dirty = false;
         }
 
         Ok(())
     }
 
-    pub fn reload<P: AsRef<Path>>(self, path: P) -> Result<Image> {
-        let original_image_buffer = Pixbuf::from_file(path)?;
-        let mut current_image_buffer = original_image_buffer.clone();
-        if let Some(current_operation_index) = self.current_operation_index {
-            current_image_buffer = self
-                .operations
-                .iter()
-                .take(current_operation_index + 1)
-                .fold(current_image_buffer, |image, operation| {
-                    image.apply_operation(operation).unwrap_or(image)
-                });
+    /// Stores edits in the sidecar file of the image at given path.
+    pub fn save_edits(&mut self, path: &Path) -> Result<()> {
+        if self.unsaved {
+            return Ok(());
+        }
+        Sidecar::new(self.operations.clone(), self.current_operation_index).save(path)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Drops all edits and removes the sidecar file of the image at given path.
+    pub fn revert_to_original(&mut self, path: &Path) -> Result<()> {
+        self.operations.clear();
+        self.current_operation_index = None;
+        self.snapshot_cache.clear();
+        self.current_image_buffer = self
+            .downscaled_original_image_buffer
+            .clone()
+            .or_else(|| self.original_image_buffer.clone());
+        self.dirty = true;
+        Sidecar::remove(path)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Cuts the current image into tiles and saves each of them in the folder. File names are
+    /// created from the pattern by replacing `{name}`, `{row}` and `{col}` placeholders,
+    /// rows and columns are numbered from 0.
+    pub fn export_tiles(
+        &self,
+        folder: &Path,
+        name: &str,
+        file_name_pattern: &str,
+        tile_layout: &TileLayout,
+    ) -> Result<Vec<PathBuf>> {
+        if !file_name_pattern.contains("{row}") || !file_name_pattern.contains("{col}") {
+            return Err(anyhow!(
+                "File name pattern must contain {{row}} and {{col}} placeholders"
+            ));
         }
-        Ok(Image {
+        if file_name_pattern.chars().any(std::path::is_separator) {
+            return Err(anyhow!(
+                "File name pattern must not contain path separators"
+            ));
+        }
+        let image_buffer = self
+            .full_resolution_image_buffer()
+            .ok_or_else(|| anyhow!("Image buffer is missing!"))?;
+        let image_size = self.image_size().unwrap();
+
+        tile_layout
+            .tiles(image_size)?
+            .iter()
+            .map(|tile| {
+                let tile_path = folder.join(
+                    file_name_pattern
+                        .replace("{name}", name)
+                        .replace("{row}", &tile.row.to_string())
+                        .replace("{col}", &tile.column.to_string()),
+                );
+                let tile_image_buffer = image_buffer
+                    .new_subpixbuf(
+                        tile.x as i32,
+                        tile.y as i32,
+                        tile.width as i32,
+                        tile.height as i32,
+                    )
+                    .ok_or_else(|| anyhow!("Couldn't create tile image buffer"))?;
+                save_image_buffer(&tile_image_buffer, &tile_path)?;
+                Ok(tile_path)
+            })
+            .collect()
+    }
+
+    /// Creates an image which isn't decoded yet, it is decoded with given edits
+    /// when it is shown. The edits aren't written to its sidecar file yet.
+    pub fn with_operations(
+        operations: Vec<ImageOperation>,
+        current_operation_index: Option<usize>,
+    ) -> Image {
+        Image {
+            original_image_buffer: None,
+            downscaled_original_image_buffer: None,
+            current_image_buffer: None,
+            preview_image_buffer: None,
+            tiled_preview: None,
+            original_preview: None,
+            display_size: None,
+            operations,
+            current_operation_index,
+            snapshot_cache: SnapshotCache::new(SNAPSHOT_INTERVAL, &SNAPSHOT_MEMORY_BUDGET),
+            proxy: None,
+            unsaved: false,
+            file_stamp: None,
+            load_generation: 0,
+            dirty: true,
+        }
+    }
+
+    fn load_with_operations<P: AsRef<Path>>(
+        path: P,
+        operations: Vec<ImageOperation>,
+        current_operation_index: Option<usize>,
+    ) -> Result<Image> {
+        let file_stamp = FileStamp::read(path.as_ref())?;
+        let original_image_buffer = decoder::load_image_buffer(path.as_ref())?;
+        let mut image = Image {
             original_image_buffer: Some(original_image_buffer),
-            current_image_buffer: Some(current_image_buffer),
+            downscaled_original_image_buffer: None,
+            current_image_buffer: None,
             preview_image_buffer: None,
-            operations: self.operations,
-            current_operation_index: self.current_operation_index,
-        })
+            tiled_preview: None,
+            original_preview: None,
+            display_size: None,
+            operations,
+            current_operation_index,
+            snapshot_cache: SnapshotCache::new(SNAPSHOT_INTERVAL, &SNAPSHOT_MEMORY_BUDGET),
+            proxy: None,
+            unsaved: false,
+            file_stamp: Some(file_stamp),
+            load_generation: 0,
+            dirty: false,
+        };
+        image.edit_downscaled_if_large();
+        image.rebuild_current_image_buffer();
+        Ok(image)
+    }
+
+    /// Returns true for images created by the application which weren't written to disk yet.
+    pub fn is_unsaved(&self) -> bool {
+        self.unsaved
     }
 
     pub fn remove_image_buffers(&mut self) {
         self.original_image_buffer = None;
+        self.downscaled_original_image_buffer = None;
         self.current_image_buffer = None;
         self.preview_image_buffer = None;
+        self.tiled_preview = None;
+        self.original_preview = None;
+        self.display_size = None;
+        self.snapshot_cache.clear();
+        self.proxy = None;
     }
 
-    fn image_buffer_scale_to_fit(&self, canvas_width: u32, canvas_height: u32) -> Option<Pixbuf> {
-        if let Some(image_buffer) = &self.current_image_buffer {
-            let image_width = image_buffer.width() as f64;
-            let image_height = image_buffer.height() as f64;
-            let width_ratio = canvas_width as f64 / image_width;
-            let height_ratio = canvas_height as f64 / image_height;
-            let scale_ratio = width_ratio.min(height_ratio);
-            image_buffer.scale_simple(
-                (image_width * scale_ratio) as i32,
-                (image_height * scale_ratio) as i32,
-                InterpType::Nearest,
-            )
-        } else {
-            None
+    pub fn has_image_buffers(&self) -> bool {
+        self.current_image_buffer.is_some()
+    }
+
+    /// Returns number of bytes used by decoded buffers of the image, buffers shared
+    /// between (downscaled) original and current image are counted once.
+    pub fn memory_usage(&self) -> usize {
+        let original_image_buffers = [
+            self.original_image_buffer.as_ref(),
+            self.downscaled_original_image_buffer.as_ref(),
+        ];
+        let shared_buffer = |image_buffer: &Option<Pixbuf>| {
+            image_buffer
+                .as_ref()
+                .filter(|image_buffer| !original_image_buffers.contains(&Some(*image_buffer)))
+        };
+        [
+            original_image_buffers[0],
+            original_image_buffers[1],
+            shared_buffer(&self.current_image_buffer),
+            shared_buffer(&self.preview_image_buffer),
+            self.proxy.as_ref().map(|(image_buffer, _)| image_buffer),
+        ]
+        .iter()
+        .flatten()
+        .map(|image_buffer| image_buffer.byte_length())
+        .sum::<usize>()
+            + self.snapshot_cache.memory_usage()
+            + [self.tiled_preview.as_ref(), self.original_preview.as_ref()]
+                .iter()
+                .flatten()
+                .map(|tiled_preview| tiled_preview.memory_usage())
+                .sum::<usize>()
+    }
+
+    fn applied_operations(&self) -> usize {
+        self.current_operation_index
+            .map_or(0, |operation_index| operation_index + 1)
+    }
+
+    /// Edits images with many pixels on a downscaled copy of the original,
+    /// so operations stay fast while editing.
+    fn edit_downscaled_if_large(&mut self) {
+        let original_image_buffer = match self.original_image_buffer.as_ref() {
+            Some(original_image_buffer) => original_image_buffer,
+            None => return,
+        };
+        let (width, height) = (
+            original_image_buffer.width() as u32,
+            original_image_buffer.height() as u32,
+        );
+        if (width as u64 * height as u64) > DOWNSCALED_EDITING_MIN_PIXELS {
+            self.edit_downscaled(DOWNSCALED_EDITING_SIZE);
         }
     }
 
-    fn image_buffer_resize(&self, scale: u32) -> Option<Pixbuf> {
-        if let Some(image_buffer) = &self.current_image_buffer {
-            image_buffer.scale_simple(
-                (image_buffer.width() as f64 * (scale as f64 / 100.0)) as i32,
-                (image_buffer.height() as f64 * (scale as f64 / 100.0)) as i32,
-                InterpType::Bilinear,
-            )
-        } else {
-            None
+    /// Applies operations to a copy of the original downscaled to fit in `max_size`,
+    /// instead of the original itself.
+    fn edit_downscaled(&mut self, max_size: u32) {
+        let original_image_buffer = match self.original_image_buffer.as_ref() {
+            Some(original_image_buffer) => original_image_buffer,
+            None => return,
+        };
+        let image_size = (
+            original_image_buffer.width() as u32,
+            original_image_buffer.height() as u32,
+        );
+        if image_size.0 <= max_size && image_size.1 <= max_size {
+            return;
+        }
+        let (width, height) = PreviewSize::BestFit(max_size, max_size).fit(image_size);
+        self.downscaled_original_image_buffer = original_image_buffer.scale_simple(
+            width.max(1) as i32,
+            height.max(1) as i32,
+            InterpType::Bilinear,
+        );
+        self.snapshot_cache.clear();
+        self.rebuild_current_image_buffer();
+    }
+
+    /// Returns true while operations are applied to a downscaled copy of the original.
+    pub fn is_edited_downscaled(&self) -> bool {
+        self.downscaled_original_image_buffer.is_some()
+    }
+
+    /// Returns operations, starting from the original image, with positions and sizes scaled
+    /// to the downscaled copy of the original. They are returned unchanged when the image
+    /// isn't edited downscaled.
+    fn scale_operations_to_image_buffer(
+        &self,
+        operations: &[ImageOperation],
+    ) -> Vec<ImageOperation> {
+        match (
+            self.original_image_buffer.as_ref(),
+            self.downscaled_original_image_buffer.as_ref(),
+        ) {
+            // recipe stores positions as fractions, so it scales them between resolutions
+            (Some(original_image_buffer), Some(downscaled_original_image_buffer)) => {
+                Recipe::from_operations(
+                    operations,
+                    (
+                        original_image_buffer.width() as u32,
+                        original_image_buffer.height() as u32,
+                    ),
+                )
+                .operations((
+                    downscaled_original_image_buffer.width() as u32,
+                    downscaled_original_image_buffer.height() as u32,
+                ))
+            }
+            _ => operations.to_vec(),
+        }
+    }
+
+    /// Returns the operation scaled to the current image buffer, so it can be applied to it
+    /// e.g. on a worker thread. The operation itself is added to history with `push_operation`.
+    pub fn operation_for_image_buffer(&self, image_operation: ImageOperation) -> ImageOperation {
+        if self.downscaled_original_image_buffer.is_none() {
+            return image_operation;
+        }
+        let mut operations = self.operations[..self.applied_operations()].to_vec();
+        operations.push(image_operation);
+        self.scale_operations_to_image_buffer(&operations)
+            .pop()
+            .unwrap_or(image_operation)
+    }
+
+    /// Returns the current image in full resolution. When the image is edited downscaled,
+    /// applied operations are replayed on the original, which may take a while.
+    pub fn full_resolution_image_buffer(&self) -> Option<Pixbuf> {
+        let (image_buffer, operations) = self.full_resolution_source()?;
+        Some(replay_operations(image_buffer, &operations))
+    }
+
+    /// Returns the buffer the full resolution image is created from, with operations which
+    /// have to be replayed on it, so it can be done e.g. on a worker thread.
+    pub fn full_resolution_source(&self) -> Option<(Pixbuf, Vec<ImageOperation>)> {
+        if self.downscaled_original_image_buffer.is_none() {
+            return Some((self.current_image_buffer.clone()?, Vec::new()));
+        }
+        Some((
+            self.original_image_buffer.clone()?,
+            self.operations[..self.applied_operations()].to_vec(),
+        ))
+    }
+
+    /// Replays applied operations starting from the nearest snapshot, or from the original
+    /// image when there is none, so it doesn't have to go through the whole history.
+    /// Returns number of replayed operations.
+    ///
+    /// Operations are normalized only between snapshots, so consecutive resizes on both
+    /// sides of a snapshot are still resampled twice, like when they were applied.
+    /// The full resolution image of a downscaled one is replayed from its original.
+    fn rebuild_current_image_buffer(&mut self) -> usize {
+        let (first_operation, image_buffer, operations) = match self.replay_start() {
+            Some(replay_start) => replay_start,
+            None => return 0,
+        };
+        let snapshot_cache = &mut self.snapshot_cache;
+        let image_buffer = replay_in_parts(
+            image_buffer,
+            &operations,
+            first_operation,
+            |applied_operations, image_buffer| {
+                snapshot_cache.insert(applied_operations, image_buffer)
+            },
+        );
+        self.current_image_buffer = Some(image_buffer);
+        operations.len() - first_operation
+    }
+
+    /// Returns number of operations already applied to the nearest snapshot, or 0 for the
+    /// original, with the image buffer and applied operations scaled to it.
+    fn replay_start(&self) -> Option<(usize, Pixbuf, Vec<ImageOperation>)> {
+        let applied_operations = self.applied_operations();
+        let (first_operation, image_buffer) = match self.snapshot_cache.nearest(applied_operations)
+        {
+            Some((first_operation, image_buffer)) => (first_operation, image_buffer.clone()),
+            None => (
+                0,
+                self.downscaled_original_image_buffer
+                    .clone()
+                    .or_else(|| self.original_image_buffer.clone())?,
+            ),
+        };
+        let mut operations = self.scale_operations_to_image_buffer(&self.operations);
+        operations.truncate(applied_operations);
+        Some((first_operation, image_buffer, operations))
+    }
+
+    /// Prepares rebuilding of the current image buffer after history of the image changed,
+    /// so operations can be replayed on a worker thread. Returns `None` when the image
+    /// has no buffers, it is rebuilt when it is decoded again then.
+    pub fn start_replay(&self) -> Option<Replay> {
+        if !self.has_image_buffers() {
+            return None;
         }
+        let (first_operation, image_buffer, operations) = self.replay_start()?;
+        Some(Replay {
+            image_buffer: SendableImageBuffer::from_image_buffer(&image_buffer)?,
+            first_operation,
+            operations,
+            snapshots: Vec::new(),
+        })
+    }
+
+    /// Takes the image buffer rebuilt by the replay and snapshots taken during it. Buffers
+    /// of the image which were dropped in the meantime are decoded again when it is shown.
+    pub fn finish_replay(&mut self, replay: Replay) {
+        if !self.has_image_buffers() {
+            return;
+        }
+        for (applied_operations, snapshot) in replay.snapshots {
+            self.snapshot_cache
+                .insert(applied_operations, &snapshot.into_image_buffer());
+        }
+        // the replayed image buffer couldn't be sent back, it is rebuilt from the snapshots
+        if replay.first_operation < replay.operations.len() {
+            self.rebuild_current_image_buffer();
+            return;
+        }
+        self.current_image_buffer = Some(replay.image_buffer.into_image_buffer());
     }
 
     pub fn create_preview_image_buffer(&mut self, preview_size: PreviewSize) {
-        self.preview_image_buffer = match preview_size {
-            PreviewSize::BestFit(canvas_width, canvas_height) => {
-                self.image_buffer_scale_to_fit(canvas_width, canvas_height)
+        let image_size = self.image_size();
+        let (source_image_buffer, image_size) = match (&self.current_image_buffer, &self.proxy) {
+            (Some(image_buffer), _) => (
+                image_buffer,
+                image_size.unwrap_or((image_buffer.width() as u32, image_buffer.height() as u32)),
+            ),
+            (None, Some((proxy_image_buffer, image_size))) => (proxy_image_buffer, *image_size),
+            (None, None) => {
+                self.preview_image_buffer = None;
+                self.tiled_preview = None;
+                self.display_size = None;
+                return;
             }
-            PreviewSize::OriginalSize => self.current_image_buffer.clone(),
-            PreviewSize::Resized(scale) => self.image_buffer_resize(scale),
         };
+        let (preview_width, preview_height) = preview_size.fit(image_size);
+        self.display_size = Some((preview_width, preview_height));
+        if TiledPreview::is_needed((preview_width, preview_height)) {
+            self.preview_image_buffer = None;
+            match self.tiled_preview.as_mut() {
+                Some(tiled_preview) if tiled_preview.is_preview_of(source_image_buffer) => {
+                    tiled_preview.set_size((preview_width, preview_height))
+                }
+                _ => {
+                    self.tiled_preview = Some(TiledPreview::new(
+                        source_image_buffer,
+                        (preview_width, preview_height),
+                    ))
+                }
+            }
+            return;
+        }
+        self.tiled_preview = None;
+        self.preview_image_buffer =
+            create_preview_image_buffer(source_image_buffer, image_size, preview_size);
+    }
+
+    /// Changes size at which the existing preview is drawn without creating a new one.
+    pub fn set_display_size(&mut self, preview_size: PreviewSize) {
+        self.display_size = self
+            .image_size()
+            .map(|image_size| preview_size.fit(image_size));
+    }
+
+    /// Returns scale from the existing preview to the size at which it is drawn.
+    pub fn preview_display_scale(&self) -> Option<(f64, f64)> {
+        let (preview_width, preview_height) = self
+            .preview_image_buffer
+            .as_ref()
+            .map(|image_buffer| (image_buffer.width() as u32, image_buffer.height() as u32))
+            .or_else(|| self.tiled_preview.as_ref().map(TiledPreview::size))?;
+        let (display_width, display_height) = self.display_size?;
+        Some((
+            display_width as f64 / preview_width.max(1) as f64,
+            display_height as f64 / preview_height.max(1) as f64,
+        ))
+    }
+
+    /// Returns true when the image is drawn larger than its original size.
+    pub fn is_magnified(&self) -> bool {
+        match (self.display_size, self.image_size()) {
+            (Some((display_width, _)), Some((image_width, _))) => display_width > image_width,
+            _ => false,
+        }
+    }
+
+    pub fn tiled_preview_mut(&mut self) -> Option<&mut TiledPreview> {
+        self.tiled_preview.as_mut()
+    }
+
+    /// Returns preview of the original image with the same zoom as the current preview,
+    /// so they can be compared. It is created from the downscaled original for large images.
+    pub fn original_preview_mut(&mut self) -> Option<&mut TiledPreview> {
+        let original_image_buffer = self.original_image_buffer.as_ref()?;
+        let source_image_buffer = self
+            .downscaled_original_image_buffer
+            .as_ref()
+            .unwrap_or(original_image_buffer);
+        let (display_width, _) = self.preview_image_buffer_size()?;
+        let (image_width, _) = self.image_size()?;
+        let zoom = display_width as f64 / image_width.max(1) as f64;
+        let size = (
+            ((original_image_buffer.width() as f64 * zoom).round() as u32).max(1),
+            ((original_image_buffer.height() as f64 * zoom).round() as u32).max(1),
+        );
+        match self.original_preview.as_mut() {
+            Some(original_preview) if original_preview.is_preview_of(source_image_buffer) => {
+                original_preview.set_size(size)
+            }
+            _ => self.original_preview = Some(TiledPreview::new(source_image_buffer, size)),
+        }
+        self.original_preview.as_mut()
+    }
+
+    /// Returns true while only the downscaled image is available.
+    pub fn is_proxy(&self) -> bool {
+        self.current_image_buffer.is_none() && self.proxy.is_some()
+    }
+
+    /// Sets preview created outside of the image, e.g. on a worker thread.
+    pub fn set_preview_image_buffer(&mut self, preview_image_buffer: Pixbuf) {
+        self.display_size = Some((
+            preview_image_buffer.width() as u32,
+            preview_image_buffer.height() as u32,
+        ));
+        self.preview_image_buffer = Some(preview_image_buffer);
+        self.tiled_preview = None;
     }
 
     pub fn create_print_image_buffer(
@@ -136,14 +655,13 @@ impl Image {
         canvas_width: u32,
         canvas_height: u32,
     ) -> Option<Pixbuf> {
-        if let Some((image_width, image_height)) = self.image_size() {
-            if image_width > canvas_width || image_height > canvas_height {
-                self.image_buffer_scale_to_fit(canvas_width, canvas_height)
-            } else {
-                self.current_image_buffer.clone()
-            }
+        let image_buffer = self.full_resolution_image_buffer()?;
+        if image_buffer.width() as u32 > canvas_width
+            || image_buffer.height() as u32 > canvas_height
+        {
+            scale_to_fit(&image_buffer, canvas_width, canvas_height)
         } else {
-            None
+            Some(image_buffer)
         }
     }
 
@@ -155,10 +673,24 @@ impl Image {
         self.current_image_buffer.as_ref()
     }
 
+    /// Returns size of the current image in full resolution.
     pub fn image_size(&self) -> Option<(u32, u32)> {
+        if let (Some(original_image_buffer), Some(_)) = (
+            self.original_image_buffer.as_ref(),
+            self.downscaled_original_image_buffer.as_ref(),
+        ) {
+            return Some(self.operations[..self.applied_operations()].iter().fold(
+                (
+                    original_image_buffer.width() as u32,
+                    original_image_buffer.height() as u32,
+                ),
+                |image_size, operation| operation.output_size(image_size),
+            ));
+        }
         self.current_image_buffer
             .as_ref()
             .map(|image_buffer| (image_buffer.width() as u32, image_buffer.height() as u32))
+            .or_else(|| self.proxy.as_ref().map(|(_, image_size)| *image_size))
     }
 
     pub fn image_aspect_ratio(&self) -> Option<f64> {
@@ -166,10 +698,18 @@ impl Image {
             .map(|(image_width, image_height)| image_width as f64 / image_height as f64)
     }
 
+    /// Returns size at which the preview is drawn.
     pub fn preview_image_buffer_size(&self) -> Option<(u32, u32)> {
-        self.preview_image_buffer
-            .as_ref()
-            .map(|image_buffer| (image_buffer.width() as u32, image_buffer.height() as u32))
+        if self.preview_image_buffer.is_none() && self.tiled_preview.is_none() {
+            return None;
+        }
+        self.display_size
+            .or_else(|| {
+                self.preview_image_buffer
+                    .as_ref()
+                    .map(|image_buffer| (image_buffer.width() as u32, image_buffer.height() as u32))
+            })
+            .or_else(|| self.tiled_preview.as_ref().map(TiledPreview::size))
     }
 
     pub fn preview_coords_to_image_coords(
@@ -198,30 +738,69 @@ impl Image {
         }
     }
 
+    pub fn color_at(&self, coords: Coordinates) -> Option<Color> {
+        let (x, y) = coords;
+        let (image_width, image_height) = self.image_size()?;
+        if x >= image_width || y >= image_height {
+            return None;
+        }
+        // coordinates are in full resolution, the image may be edited downscaled
+        let image_buffer = self.current_image_buffer.as_ref()?;
+        let x = (x as u64 * image_buffer.width() as u64 / image_width as u64) as i32;
+        let y = (y as u64 * image_buffer.height() as u64 / image_height as u64) as i32;
+        let pixel = image_buffer.new_subpixbuf(x, y, 1, 1)?.read_pixel_bytes()?;
+        Some((pixel[0], pixel[1], pixel[2]))
+    }
+
     pub fn has_operations(&self) -> bool {
         !self.operations.is_empty() && self.current_operation_index.is_some()
     }
 
+    /// Returns true when the file at given path was changed by another program since
+    /// the image was decoded or saved.
+    pub fn is_modified_externally(&self, path: &Path) -> bool {
+        match (self.file_stamp, FileStamp::read(path)) {
+            (Some(file_stamp), Ok(current_file_stamp)) => file_stamp != current_file_stamp,
+            _ => false,
+        }
+    }
+
+    /// Keeps the image as it is, so the file at given path can be overwritten
+    /// despite changes made to it by another program.
+    pub fn ignore_external_modification(&mut self, path: &Path) -> Result<()> {
+        self.file_stamp = Some(FileStamp::read(path)?);
+        Ok(())
+    }
+
+    /// Returns true when the image has changes which aren't written into its file,
+    /// edits kept only in its sidecar file count too.
+    pub fn is_edited(&self) -> bool {
+        self.unsaved || self.has_operations()
+    }
+
+    /// Returns true when edits changed since they were last written to the sidecar file.
+    pub fn has_unwritten_edits(&self) -> bool {
+        self.dirty
+    }
+
+    /// Keeps edits of the image it replaces, when they weren't written to the sidecar file.
+    pub fn inherit_unwritten_edits(&mut self, replaced_image: &Image) {
+        self.dirty |= replaced_image.dirty;
+    }
+
     pub fn can_undo_operation(&self) -> bool {
         self.current_operation_index.is_some()
     }
 
-    pub fn undo_operation(&mut self) {
-        if self.can_undo_operation() {
-            self.current_operation_index = self.current_operation_index.unwrap().checked_sub(1);
-            self.current_image_buffer = Some(
-                self.operations
-                    .iter()
-                    .take(
-                        self.current_operation_index
-                            .map_or(0, |operation_index| operation_index + 1),
-                    )
-                    .fold(
-                        self.original_image_buffer.clone().unwrap(),
-                        |image, operation| image.apply_operation(operation).unwrap_or(image),
-                    ),
-            );
+    /// Moves back in history, returns false when there is nothing to undo. The current
+    /// image buffer is rebuilt afterwards with `start_replay`.
+    pub fn undo_operation(&mut self) -> bool {
+        if !self.can_undo_operation() {
+            return false;
         }
+        self.dirty = true;
+        self.current_operation_index = self.current_operation_index.unwrap().checked_sub(1);
+        true
     }
 
     pub fn can_redo_operation(&self) -> bool {
@@ -231,22 +810,456 @@ impl Image {
         }
     }
 
-    pub fn redo_operation(&mut self) {
-        if self.can_redo_operation() {
-            self.current_operation_index = self
-                .current_operation_index
-                .map_or(Some(0), |current_operation_index| {
-                    Some(current_operation_index + 1)
-                });
-            self.current_image_buffer = Some(
-                self.operations
-                    .iter()
-                    .take(self.current_operation_index.unwrap() + 1)
-                    .fold(
-                        self.original_image_buffer.clone().unwrap(),
-                        |image, operation| image.apply_operation(operation).unwrap_or(image),
-                    ),
-            );
+    /// Moves forward in history, returns false when there is nothing to redo. The current
+    /// image buffer is rebuilt afterwards with `start_replay`.
+    pub fn redo_operation(&mut self) -> bool {
+        if !self.can_redo_operation() {
+            return false;
+        }
+        self.dirty = true;
+        self.current_operation_index = self
+            .current_operation_index
+            .map_or(Some(0), |current_operation_index| {
+                Some(current_operation_index + 1)
+            });
+        true
+    }
+
+    /// Adds operation to history with image buffer it produced, e.g. on a worker thread.
+    /// Operations after the current one are dropped.
+    pub fn push_operation(&mut self, image_operation: ImageOperation, image_buffer: Pixbuf) {
+        let applied_operations = self.applied_operations();
+        self.operations.truncate(applied_operations);
+        self.snapshot_cache.invalidate_after(applied_operations);
+        self.operations.push(image_operation);
+        self.current_operation_index = Some(self.operations.len() - 1);
+        self.dirty = true;
+        self.snapshot_cache
+            .insert(self.operations.len(), &image_buffer);
+        self.current_image_buffer = Some(image_buffer);
+    }
+
+    pub fn operations(&self) -> &[ImageOperation] {
+        &self.operations
+    }
+
+    pub fn current_operation_index(&self) -> Option<usize> {
+        self.current_operation_index
+    }
+
+    pub fn operation_tag(&self) -> OperationTag {
+        OperationTag {
+            load_generation: self.load_generation,
+            operation_index: self.current_operation_index,
+        }
+    }
+
+    pub fn set_load_generation(&mut self, load_generation: u64) {
+        self.load_generation = load_generation;
+    }
+
+    /// Returns recipe made from operations applied to the image.
+    pub fn recipe(&self) -> Option<Recipe> {
+        let original_image_buffer = self.original_image_buffer.as_ref()?;
+        Some(Recipe::from_operations(
+            &self.operations[..self.applied_operations()],
+            (
+                original_image_buffer.width() as u32,
+                original_image_buffer.height() as u32,
+            ),
+        ))
+    }
+
+    /// Adds operations from the recipe on top of the current image, operations after
+    /// the current one are dropped. Returns false when there is nothing to add. The current
+    /// image buffer is rebuilt afterwards with `start_replay`.
+    pub fn push_recipe(&mut self, recipe: &Recipe) -> bool {
+        let operations = match self.image_size() {
+            Some(image_size) => recipe.operations(image_size),
+            None => return false,
+        };
+        if operations.is_empty() {
+            return false;
+        }
+        let applied_operations = self.applied_operations();
+        self.operations.truncate(applied_operations);
+        self.snapshot_cache.invalidate_after(applied_operations);
+        self.operations.extend(operations);
+        self.current_operation_index = Some(self.operations.len() - 1);
+        self.dirty = true;
+        true
+    }
+
+    /// Moves in history to the state after the operation with given index, or to the original
+    /// image when index is `None`. Returns false for an index out of history. The current
+    /// image buffer is rebuilt afterwards with `start_replay`.
+    pub fn go_to_operation(&mut self, operation_index: Option<usize>) -> bool {
+        if operation_index.map_or(false, |operation_index| {
+            operation_index >= self.operations.len()
+        }) {
+            return false;
+        }
+        self.current_operation_index = operation_index;
+        self.dirty = true;
+        true
+    }
+
+    /// Removes the operation from history, returns false for an index out of history.
+    /// Operations following it are applied again when the current image buffer is rebuilt
+    /// with `start_replay`.
+    pub fn remove_operation(&mut self, operation_index: usize) -> bool {
+        if operation_index >= self.operations.len() {
+            return false;
+        }
+        self.operations.remove(operation_index);
+        self.dirty = true;
+        self.snapshot_cache.invalidate_after(operation_index);
+        self.current_operation_index = match self.current_operation_index {
+            Some(current_operation_index) if current_operation_index >= operation_index => {
+                current_operation_index.checked_sub(1)
+            }
+            current_operation_index => current_operation_index,
+        };
+        true
+    }
+}
+
+/// Rebuild of the current image buffer after history of an image changed. Operations are
+/// replayed by `run`, which can be called on a worker thread, and the result is taken
+/// by `Image::finish_replay`.
+#[derive(Debug)]
+pub struct Replay {
+    /// Snapshot or original the operations are replayed on, the rebuilt image buffer
+    /// once the replay has run.
+    image_buffer: SendableImageBuffer,
+    /// Number of operations already applied to the image buffer.
+    first_operation: usize,
+    /// Applied operations scaled to the image buffer.
+    operations: Vec<ImageOperation>,
+    /// Image buffers taken at snapshot intervals, with number of operations applied to them.
+    snapshots: Vec<(usize, SendableImageBuffer)>,
+}
+
+impl Replay {
+    pub fn run(&mut self) {
+        let mut snapshots = Vec::new();
+        let image_buffer = replay_in_parts(
+            self.image_buffer.clone().into_image_buffer(),
+            &self.operations,
+            self.first_operation,
+            |applied_operations, image_buffer| {
+                if applied_operations % SNAPSHOT_INTERVAL == 0 {
+                    snapshots.extend(
+                        SendableImageBuffer::from_image_buffer(image_buffer)
+                            .map(|snapshot| (applied_operations, snapshot)),
+                    );
+                }
+            },
+        );
+        self.image_buffer = match snapshots.last() {
+            Some((applied_operations, snapshot))
+                if *applied_operations == self.operations.len() =>
+            {
+                snapshot.clone()
+            }
+            _ => match SendableImageBuffer::from_image_buffer(&image_buffer) {
+                Some(image_buffer) => image_buffer,
+                None => return,
+            },
+        };
+        self.first_operation = self.operations.len();
+        self.snapshots = snapshots;
+    }
+}
+
+/// Replays operations following the first one on the image buffer. Operations are replayed
+/// in parts ending at snapshot intervals, `snapshot` is called with the result of each part
+/// and number of operations applied to it.
+fn replay_in_parts<F>(
+    mut image_buffer: Pixbuf,
+    operations: &[ImageOperation],
+    first_operation: usize,
+    mut snapshot: F,
+) -> Pixbuf
+where
+    F: FnMut(usize, &Pixbuf),
+{
+    let mut replayed_operations = first_operation;
+    while replayed_operations < operations.len() {
+        let next_snapshot = (replayed_operations / SNAPSHOT_INTERVAL + 1) * SNAPSHOT_INTERVAL;
+        let replay_end = next_snapshot.min(operations.len());
+        image_buffer =
+            replay_operations(image_buffer, &operations[replayed_operations..replay_end]);
+        snapshot(replay_end, &image_buffer);
+        replayed_operations = replay_end;
+    }
+    image_buffer
+}
+
+/// Applies normalized operations to the image buffer, operations which fail are skipped.
+pub fn replay_operations(image_buffer: Pixbuf, operations: &[ImageOperation]) -> Pixbuf {
+    normalize_operations(operations)
+        .iter()
+        .fold(image_buffer, |image_buffer, operation| {
+            image_buffer
+                .apply_operation(operation)
+                .unwrap_or(image_buffer)
+        })
+}
+
+pub fn save_image_buffer(image_buffer: &Pixbuf, path: &Path) -> Result<()> {
+    save_image_buffer_with_options(image_buffer, path, &EncoderOptions::default())
+}
+
+/// Saves the image buffer in the format of the file extension, transparency is flattened
+/// onto white for formats which don't support it.
+pub fn save_image_buffer_with_options(
+    image_buffer: &Pixbuf,
+    path: &Path,
+    options: &EncoderOptions,
+) -> Result<()> {
+    let contents = encode_image_buffer(encoders().by_path(path)?, image_buffer, options)?;
+    write_atomically(path, |temporary_path| {
+        fs::write(temporary_path, &contents)?;
+        Ok(())
+    })
+}
+
+/// Writes the file into a temporary file in the same folder, which is synced to disk and
+/// renamed over the target, so a failed write never leaves a partially written file behind.
+/// Permissions of the replaced file are kept.
+fn write_atomically<F>(path: &Path, write: F) -> Result<()>
+where
+    F: FnOnce(&Path) -> Result<()>,
+{
+    let mut temporary_file_name = OsString::from(".");
+    temporary_file_name.push(path.file_name().unwrap_or_default());
+    temporary_file_name.push(format!(".{}.tmp", std::process::id()));
+    let temporary_path = path.with_file_name(temporary_file_name);
+
+    let result = write(&temporary_path).and_then(|_| {
+        File::open(&temporary_path)?.sync_all()?;
+        if let Ok(metadata) = fs::metadata(path) {
+            fs::set_permissions(&temporary_path, metadata.permissions())?;
+        }
+        fs::rename(&temporary_path, path)?;
+        Ok(())
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary_path);
+    }
+    result
+}
+
+/// Creates preview of the image buffer, it can be called on a worker thread. Image size is
+/// the full resolution of the image, which may be larger than the image buffer.
+pub fn create_preview_image_buffer(
+    image_buffer: &Pixbuf,
+    image_size: (u32, u32),
+    preview_size: PreviewSize,
+) -> Option<Pixbuf> {
+    let (preview_width, preview_height) = preview_size.fit(image_size);
+    if (preview_width as i32, preview_height as i32)
+        == (image_buffer.width(), image_buffer.height())
+    {
+        return Some(image_buffer.clone());
+    }
+    let interp_type = match preview_size {
+        PreviewSize::BestFit(_, _) => InterpType::Nearest,
+        _ => InterpType::Bilinear,
+    };
+    image_buffer.scale_simple(preview_width as i32, preview_height as i32, interp_type)
+}
+
+fn scale_to_fit(image_buffer: &Pixbuf, canvas_width: u32, canvas_height: u32) -> Option<Pixbuf> {
+    let image_width = image_buffer.width() as f64;
+    let image_height = image_buffer.height() as f64;
+    let width_ratio = canvas_width as f64 / image_width;
+    let height_ratio = canvas_height as f64 / image_height;
+    let scale_ratio = width_ratio.min(height_ratio);
+    image_buffer.scale_simple(
+        (image_width * scale_ratio) as i32,
+        (image_height * scale_ratio) as i32,
+        InterpType::Nearest,
+    )
+}
+
+/// Image decoded on a worker thread with its edits already applied,
+/// it can be sent to the main thread and turned into `Image` there.
+#[derive(Debug)]
+pub struct DecodedImage {
+    original_image_buffer: SendableImageBuffer,
+    /// `Some` for large images, which are edited downscaled.
+    downscaled_original_image_buffer: Option<SendableImageBuffer>,
+    /// `None` when no operations are applied and current image is the (downscaled) original.
+    current_image_buffer: Option<SendableImageBuffer>,
+    operations: Vec<ImageOperation>,
+    current_operation_index: Option<usize>,
+    file_stamp: Option<FileStamp>,
+}
+
+impl DecodedImage {
+    /// Decodes the image and applies given operations,
+    /// or edits from its sidecar file when there are none.
+    pub fn decode(
+        path: &Path,
+        operations: Option<(Vec<ImageOperation>, Option<usize>)>,
+    ) -> Result<DecodedImage> {
+        let image = match operations {
+            Some((operations, current_operation_index)) => {
+                Image::load_with_operations(path, operations, current_operation_index)?
+            }
+            None => Image::load(path)?,
+        };
+        let sendable_image_buffer = |image_buffer: Option<&Pixbuf>| {
+            image_buffer
+                .and_then(SendableImageBuffer::from_image_buffer)
+                .ok_or_else(|| anyhow!("Couldn't read pixels of {}", path.display()))
+        };
+        let current_image_buffer = match image.applied_operations() {
+            0 => None,
+            _ => Some(sendable_image_buffer(image.current_image_buffer.as_ref())?),
+        };
+        let downscaled_original_image_buffer = match image.downscaled_original_image_buffer {
+            Some(ref image_buffer) => Some(sendable_image_buffer(Some(image_buffer))?),
+            None => None,
+        };
+        Ok(DecodedImage {
+            original_image_buffer: sendable_image_buffer(image.original_image_buffer.as_ref())?,
+            downscaled_original_image_buffer,
+            current_image_buffer,
+            operations: image.operations,
+            current_operation_index: image.current_operation_index,
+            file_stamp: image.file_stamp,
+        })
+    }
+}
+
+impl From<DecodedImage> for Image {
+    fn from(decoded_image: DecodedImage) -> Self {
+        let original_image_buffer = decoded_image.original_image_buffer.into_image_buffer();
+        let downscaled_original_image_buffer = decoded_image
+            .downscaled_original_image_buffer
+            .map(SendableImageBuffer::into_image_buffer);
+        let current_image_buffer = decoded_image.current_image_buffer.map_or_else(
+            || {
+                downscaled_original_image_buffer
+                    .clone()
+                    .unwrap_or_else(|| original_image_buffer.clone())
+            },
+            |image_buffer| image_buffer.into_image_buffer(),
+        );
+        Image {
+            original_image_buffer: Some(original_image_buffer),
+            downscaled_original_image_buffer,
+            current_image_buffer: Some(current_image_buffer),
+            preview_image_buffer: None,
+            tiled_preview: None,
+            original_preview: None,
+            display_size: None,
+            operations: decoded_image.operations,
+            current_operation_index: decoded_image.current_operation_index,
+            snapshot_cache: SnapshotCache::new(SNAPSHOT_INTERVAL, &SNAPSHOT_MEMORY_BUDGET),
+            proxy: None,
+            unsaved: false,
+            file_stamp: decoded_image.file_stamp,
+            load_generation: 0,
+            dirty: false,
+        }
+    }
+}
+
+/// Downscaled image decoded on a worker thread with its edits scaled to it,
+/// shown while the full image is still being decoded.
+#[derive(Debug)]
+pub struct DecodedProxy {
+    image_buffer: SendableImageBuffer,
+    image_size: (u32, u32),
+    operations: Vec<ImageOperation>,
+    current_operation_index: Option<usize>,
+}
+
+impl DecodedProxy {
+    /// Decodes the image scaled down to fit in `max_size` and applies given operations,
+    /// or edits from its sidecar file when there are none. Returns `None` for images which
+    /// aren't much larger than `max_size`, decoding them fully is fast enough.
+    pub fn decode(
+        path: &Path,
+        operations: Option<(Vec<ImageOperation>, Option<usize>)>,
+        max_size: (u32, u32),
+    ) -> Result<Option<DecodedProxy>> {
+        let (width, height) = decoder::image_size(path)
+            .ok_or_else(|| anyhow!("Couldn't read size of {}", path.display()))?;
+        if width <= max_size.0 * 2 && height <= max_size.1 * 2 {
+            return Ok(None);
+        }
+        let (operations, current_operation_index) = match operations {
+            Some(operations) => operations,
+            None => match Sidecar::load(path) {
+                Ok(Some(sidecar)) => (sidecar.operations, sidecar.current_operation_index),
+                Ok(None) => (Vec::new(), None),
+                Err(error) => {
+                    warn!("Couldn't load edits: {}", error);
+                    (Vec::new(), None)
+                }
+            },
+        };
+        let applied_operations =
+            &operations[..current_operation_index.map_or(0, |operation_index| operation_index + 1)];
+
+        let image_buffer =
+            decoder::load_image_buffer_at_scale(path, max_size.0 as i32, max_size.1 as i32)?;
+        let proxy_size = (image_buffer.width() as u32, image_buffer.height() as u32);
+        // operations hold positions in full image coordinates, recipe scales them to the proxy
+        let image_buffer = normalize_operations(
+            &Recipe::from_operations(applied_operations, (width, height)).operations(proxy_size),
+        )
+        .iter()
+        .try_fold(image_buffer, |image_buffer, operation| {
+            image_buffer.apply_operation(operation)
+        })
+        .ok_or_else(|| anyhow!("Couldn't apply edits to {}", path.display()))?;
+        let image_size = applied_operations
+            .iter()
+            .fold((width, height), |image_size, operation| {
+                operation.output_size(image_size)
+            });
+
+        Ok(Some(DecodedProxy {
+            image_buffer: SendableImageBuffer::from_image_buffer(&image_buffer)
+                .ok_or_else(|| anyhow!("Couldn't read pixels of {}", path.display()))?,
+            image_size,
+            operations,
+            current_operation_index,
+        }))
+    }
+
+    pub fn operations(&self) -> (Vec<ImageOperation>, Option<usize>) {
+        (self.operations.clone(), self.current_operation_index)
+    }
+}
+
+impl From<DecodedProxy> for Image {
+    fn from(decoded_proxy: DecodedProxy) -> Self {
+        Image {
+            original_image_buffer: None,
+            downscaled_original_image_buffer: None,
+            current_image_buffer: None,
+            preview_image_buffer: None,
+            tiled_preview: None,
+            original_preview: None,
+            display_size: None,
+            operations: decoded_proxy.operations,
+            current_operation_index: decoded_proxy.current_operation_index,
+            snapshot_cache: SnapshotCache::new(SNAPSHOT_INTERVAL, &SNAPSHOT_MEMORY_BUDGET),
+            proxy: Some((
+                decoded_proxy.image_buffer.into_image_buffer(),
+                decoded_proxy.image_size,
+            )),
+            unsaved: false,
+            file_stamp: None,
+            load_generation: 0,
+            dirty: false,
         }
     }
 }
@@ -255,17 +1268,13 @@ impl ApplyImageOperation for Image {
     type Result = Self;
 
     fn apply_operation(mut self, image_operation: &ImageOperation) -> Self::Result {
-        if let Some(image_buffer) = self.current_image_buffer {
-            let applied_operation_image_buffer = image_buffer.apply_operation(image_operation);
-            if applied_operation_image_buffer.is_some() {
-                if let Some(current_operation_index) = self.current_operation_index {
-                    self.operations.truncate(current_operation_index + 1);
-                }
-                self.operations.push(*image_operation);
-                self.current_operation_index = Some(self.operations.len() - 1);
-            }
-            self.current_image_buffer =
-                Some(applied_operation_image_buffer.unwrap_or(image_buffer));
+        let image_buffer_operation = self.operation_for_image_buffer(*image_operation);
+        if let Some(applied_operation_image_buffer) = self
+            .current_image_buffer
+            .as_ref()
+            .and_then(|image_buffer| image_buffer.apply_operation(&image_buffer_operation))
+        {
+            self.push_operation(*image_operation, applied_operation_image_buffer);
         }
         self
     }
@@ -289,6 +1298,22 @@ impl From<PreviewSize> for String {
 }
 
 impl PreviewSize {
+    /// Returns size of the preview of an image with given size.
+    pub fn fit(&self, image_size: (u32, u32)) -> (u32, u32) {
+        let (image_width, image_height) = (image_size.0 as f64, image_size.1 as f64);
+        let scale_ratio = match *self {
+            PreviewSize::BestFit(canvas_width, canvas_height) => {
+                (canvas_width as f64 / image_width).min(canvas_height as f64 / image_height)
+            }
+            PreviewSize::OriginalSize => 1.0,
+            PreviewSize::Resized(scale) => scale as f64 / 100.0,
+        };
+        (
+            (image_width * scale_ratio) as u32,
+            (image_height * scale_ratio) as u32,
+        )
+    }
+
     pub fn smaller(self) -> Option<PreviewSize> {
         match self {
             PreviewSize::BestFit(_, _) => Some(PreviewSize::OriginalSize),
@@ -384,6 +1409,12 @@ mod tests {
 
     const TEST_IMAGE: &[u8] = include_bytes!("resources/test/test_image.png");
 
+    fn save<P: AsRef<Path>>(image: &mut Image, path: P, clear_operations: bool) -> Result<()> {
+        let image_buffer = image.full_resolution_image_buffer().unwrap();
+        save_image_buffer(&image_buffer, path.as_ref())?;
+        image.mark_saved(path.as_ref(), image_buffer, clear_operations)
+    }
+
     #[test]
     fn test_load_image() {
         let mut test_resources = TestResources::new("test/test_load_image");
@@ -412,10 +1443,51 @@ mod tests {
 
         let mut image = Image::load(test_resources.file_folder().join("test.png")).unwrap();
         let saved_file_path = test_resources.file_folder().join("test2.png");
-        image.save(&saved_file_path, false).unwrap();
+        save(&mut image, &saved_file_path, false).unwrap();
         assert!(std::fs::File::open(saved_file_path).is_ok());
     }
 
+    #[test]
+    fn overwritten_image_keeps_permissions_and_no_temporary_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut test_resources =
+            TestResources::new("test/overwritten_image_keeps_permissions_and_no_temporary_file");
+        test_resources.add_file("test.png", TEST_IMAGE);
+        let image_path = test_resources.file_folder().join("test.png");
+        fs::set_permissions(&image_path, fs::Permissions::from_mode(0o600)).unwrap();
+
+        let mut image = Image::load(&image_path).unwrap();
+        image = image.apply_operation(&ImageOperation::Rotate(PixbufRotation::Clockwise));
+        save(&mut image, &image_path, true).unwrap();
+
+        let permissions = fs::metadata(&image_path).unwrap().permissions();
+        assert_eq!(0o600, permissions.mode() & 0o777);
+        assert_eq!(
+            1,
+            fs::read_dir(test_resources.file_folder()).unwrap().count()
+        );
+        assert!(Image::load(&image_path).is_ok());
+    }
+
+    #[test]
+    fn image_from_image_buffer_is_unsaved_until_saved() {
+        let test_resources = TestResources::new("test/image_from_image_buffer_is_unsaved");
+
+        let image_buffer = Pixbuf::new(gtk::gdk_pixbuf::Colorspace::Rgb, false, 8, 10, 10).unwrap();
+        let mut image = Image::from_image_buffer(image_buffer);
+        assert!(image.is_unsaved());
+        assert!(!image.has_operations());
+
+        save(
+            &mut image,
+            test_resources.file_folder().join("test.png"),
+            true,
+        )
+        .unwrap();
+        assert!(!image.is_unsaved());
+    }
+
     #[test]
     fn test_save_image_without_clear_operations() {
         let mut test_resources =
@@ -425,9 +1497,12 @@ mod tests {
         let mut image = Image::load(test_resources.file_folder().join("test.png")).unwrap();
         image = image.apply_operation(&ImageOperation::Rotate(PixbufRotation::Clockwise));
         assert!(image.has_operations());
-        image
-            .save(test_resources.file_folder().join("test2.png"), false)
-            .unwrap();
+        save(
+            &mut image,
+            test_resources.file_folder().join("test2.png"),
+            false,
+        )
+        .unwrap();
         assert!(image.has_operations());
         assert_ne!(
             image.original_image_buffer.unwrap().pixel_bytes(),
@@ -443,9 +1518,12 @@ mod tests {
         let mut image = Image::load(test_resources.file_folder().join("test.png")).unwrap();
         image = image.apply_operation(&ImageOperation::Rotate(PixbufRotation::Clockwise));
         assert!(image.has_operations());
-        image
-            .save(test_resources.file_folder().join("test2.png"), true)
-            .unwrap();
+        save(
+            &mut image,
+            test_resources.file_folder().join("test2.png"),
+            true,
+        )
+        .unwrap();
         assert!(!image.has_operations());
         assert_eq!(
             image.original_image_buffer.unwrap().pixel_bytes(),
@@ -466,7 +1544,7 @@ mod tests {
             let saved_file_path = test_resources
                 .file_folder()
                 .join(format!("{}.{}", "test2", extension));
-            image.save(&saved_file_path, false).unwrap();
+            save(&mut image, &saved_file_path, false).unwrap();
             let saved_file_inferred_extension = infer::get_from_path(saved_file_path)
                 .unwrap()
                 .unwrap()
@@ -476,6 +1554,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn save_image_fails_for_unknown_extensions_and_flattens_transparency_when_needed() {
+        let test_resources = TestResources::new(
+            "test/save_image_fails_for_unknown_extensions_and_flattens_transparency_when_needed",
+        );
+        let image_buffer = Pixbuf::new(gtk::gdk_pixbuf::Colorspace::Rgb, true, 8, 10, 10).unwrap();
+        image_buffer.fill(0);
+
+        let unknown_file_path = test_resources.file_folder().join("test.xyz");
+        assert!(save_image_buffer(&image_buffer, &unknown_file_path).is_err());
+        assert!(!unknown_file_path.exists());
+
+        let jpeg_file_path = test_resources.file_folder().join("test.jpg");
+        save_image_buffer(&image_buffer, &jpeg_file_path).unwrap();
+        let saved_image_buffer = Pixbuf::from_file(&jpeg_file_path).unwrap();
+        assert!(saved_image_buffer
+            .pixel_bytes()
+            .unwrap()
+            .iter()
+            .all(|byte| *byte > 250));
+    }
+
     #[test]
     fn file_extensions_jpg_and_jpeg_are_supported() {
         let mut test_resources =
@@ -484,7 +1584,7 @@ mod tests {
 
         let mut image = Image::load(test_resources.file_folder().join("test.jpg")).unwrap();
         let saved_file_path = test_resources.file_folder().join("test2.jpg");
-        image.save(&saved_file_path, false).unwrap();
+        save(&mut image, &saved_file_path, false).unwrap();
         let saved_file_inferred_extension = infer::get_from_path(saved_file_path)
             .unwrap()
             .unwrap()
@@ -495,7 +1595,7 @@ mod tests {
 
         let mut image = Image::load(test_resources.file_folder().join("test.jpeg")).unwrap();
         let saved_file_path = test_resources.file_folder().join("test2.jpeg");
-        image.save(&saved_file_path, false).unwrap();
+        save(&mut image, &saved_file_path, false).unwrap();
         let saved_file_inferred_extension = infer::get_from_path(saved_file_path)
             .unwrap()
             .unwrap()
@@ -503,6 +1603,70 @@ mod tests {
         assert_eq!(saved_file_inferred_extension, "jpg");
     }
 
+    #[test]
+    fn export_tiles_saves_every_tile() {
+        let mut test_resources = TestResources::new("test/export_tiles_saves_every_tile");
+        test_resources.add_file("test.png", TEST_IMAGE);
+
+        let mut image = Image::load(test_resources.file_folder().join("test.png")).unwrap();
+        image = image.apply_operation(&ImageOperation::Resize((100, 50)));
+        let tile_paths = image
+            .export_tiles(
+                test_resources.file_folder(),
+                "test",
+                "{name}_{row}_{col}.png",
+                &TileLayout::Grid {
+                    rows: 2,
+                    columns: 3,
+                    overlap: 0,
+                },
+            )
+            .unwrap();
+
+        assert_eq!(6, tile_paths.len());
+        assert_eq!(
+            test_resources.file_folder().join("test_1_2.png"),
+            tile_paths[5]
+        );
+        let (_, width, height) = Pixbuf::file_info(&tile_paths[5]).unwrap();
+        assert_eq!((34, 25), (width, height));
+    }
+
+    #[test]
+    fn export_tiles_requires_row_and_column_in_pattern_without_separators() {
+        let mut test_resources = TestResources::new(
+            "test/export_tiles_requires_row_and_column_in_pattern_without_separators",
+        );
+        test_resources.add_file("test.png", TEST_IMAGE);
+
+        let image = Image::load(test_resources.file_folder().join("test.png")).unwrap();
+
+        assert!(image
+            .export_tiles(
+                test_resources.file_folder(),
+                "test",
+                "{name}_{row}.png",
+                &TileLayout::Grid {
+                    rows: 2,
+                    columns: 2,
+                    overlap: 0,
+                },
+            )
+            .is_err());
+        assert!(image
+            .export_tiles(
+                test_resources.file_folder(),
+                "test",
+                "../{name}_{row}_{col}.png",
+                &TileLayout::Grid {
+                    rows: 2,
+                    columns: 2,
+                    overlap: 0,
+                },
+            )
+            .is_err());
+    }
+
     #[test]
     fn test_image_reload() {
         let mut test_resources = TestResources::new("test/test_image_reload");
@@ -515,9 +1679,13 @@ mod tests {
         image.remove_image_buffers();
         assert!(image.original_image_buffer.is_none() && image.current_image_buffer.is_none());
 
-        image = image
-            .reload(test_resources.file_folder().join("test.png"))
-            .unwrap();
+        image = Image::from(
+            DecodedImage::decode(
+                &test_resources.file_folder().join("test.png"),
+                Some((image.operations, image.current_operation_index)),
+            )
+            .unwrap(),
+        );
         assert_eq!(
             original_image_buffer.unwrap().pixel_bytes(),
             image.original_image_buffer.unwrap().pixel_bytes()
@@ -583,6 +1751,20 @@ mod tests {
         );
     }
 
+    #[test]
+    fn color_at() {
+        let mut test_resources = TestResources::new("test/color_at");
+        test_resources.add_file("test.png", TEST_IMAGE);
+
+        let mut image = Image::load(test_resources.file_folder().join("test.png")).unwrap();
+        image = image.apply_operation(&ImageOperation::Resize((10, 10)));
+        image = image.apply_operation(&ImageOperation::MakeColorTransparent((0, 0, 0), 255, 0));
+        image = image.apply_operation(&ImageOperation::FlattenOntoColor((255, 0, 0)));
+
+        assert_eq!(Some((255, 0, 0)), image.color_at((5, 5)));
+        assert_eq!(None, image.color_at((10, 10)));
+    }
+
     #[test]
     fn undo_operation() {
         let mut test_resources = TestResources::new("test/undo_operation");
@@ -646,4 +1828,347 @@ mod tests {
                 != image.current_image_buffer.unwrap().pixel_bytes()
         );
     }
+
+    #[test]
+    fn undo_with_snapshots_matches_replaying_all_operations() {
+        let mut test_resources = TestResources::new("test/undo_with_snapshots_matches_replaying");
+        test_resources.add_file("test.png", TEST_IMAGE);
+
+        let mut image = Image::load(test_resources.file_folder().join("test.png")).unwrap();
+        for _ in 0..(SNAPSHOT_INTERVAL * 3) {
+            image = image.apply_operation(&ImageOperation::Rotate(PixbufRotation::Clockwise));
+        }
+        image = image.apply_operation(&ImageOperation::Rotate(PixbufRotation::Clockwise));
+        assert!(image.snapshot_cache.memory_usage() > 0);
+
+        image.undo_operation();
+        let mut replay = image.start_replay().unwrap();
+        let replay = std::thread::spawn(move || {
+            replay.run();
+            replay
+        })
+        .join()
+        .unwrap();
+        image.finish_replay(replay);
+        let expected_image_buffer = image.operations[..image.operations.len() - 1].iter().fold(
+            image.original_image_buffer.clone().unwrap(),
+            |image_buffer, operation| image_buffer.apply_operation(operation).unwrap(),
+        );
+        assert_eq!(
+            expected_image_buffer.pixel_bytes(),
+            image.current_image_buffer.unwrap().pixel_bytes()
+        );
+    }
+
+    #[test]
+    fn undo_replays_only_operations_after_nearest_snapshot() {
+        let mut test_resources =
+            TestResources::new("test/undo_replays_only_operations_after_nearest_snapshot");
+        test_resources.add_file("test.png", TEST_IMAGE);
+
+        let mut image = Image::load(test_resources.file_folder().join("test.png")).unwrap();
+        for _ in 0..(SNAPSHOT_INTERVAL * 50) {
+            image = image.apply_operation(&ImageOperation::Rotate(PixbufRotation::Clockwise));
+        }
+        for _ in 0..(SNAPSHOT_INTERVAL * 2) {
+            image.current_operation_index = image.current_operation_index.unwrap().checked_sub(1);
+            assert!(image.rebuild_current_image_buffer() < SNAPSHOT_INTERVAL);
+        }
+    }
+
+    #[test]
+    fn go_to_operation() {
+        let mut test_resources = TestResources::new("test/go_to_operation");
+        test_resources.add_file("test.png", TEST_IMAGE);
+
+        let mut image = Image::load(test_resources.file_folder().join("test.png")).unwrap();
+        image = image.apply_operation(&ImageOperation::Resize((100, 100)));
+        image = image.apply_operation(&ImageOperation::Resize((50, 20)));
+        image = image.apply_operation(&ImageOperation::Rotate(PixbufRotation::Clockwise));
+
+        image.go_to_operation(Some(0));
+        image.rebuild_current_image_buffer();
+        assert_eq!(image.current_operation_index(), Some(0));
+        assert_eq!(image.image_size(), Some((100, 100)));
+
+        image.go_to_operation(None);
+        assert_eq!(image.current_operation_index(), None);
+        assert_eq!(image.operations().len(), 3);
+
+        assert!(!image.go_to_operation(Some(3)));
+        assert_eq!(image.current_operation_index(), None);
+
+        image.go_to_operation(Some(2));
+        image.rebuild_current_image_buffer();
+        assert_eq!(image.image_size(), Some((20, 50)));
+    }
+
+    #[test]
+    fn remove_operation_applies_following_operations_again() {
+        let mut test_resources = TestResources::new("test/remove_operation");
+        test_resources.add_file("test.png", TEST_IMAGE);
+
+        let mut image = Image::load(test_resources.file_folder().join("test.png")).unwrap();
+        image = image.apply_operation(&ImageOperation::Resize((100, 100)));
+        image = image.apply_operation(&ImageOperation::Resize((50, 20)));
+        image = image.apply_operation(&ImageOperation::Rotate(PixbufRotation::Clockwise));
+
+        image.remove_operation(1);
+        image.rebuild_current_image_buffer();
+        assert_eq!(image.operations().len(), 2);
+        assert_eq!(image.current_operation_index(), Some(1));
+        assert_eq!(image.image_size(), Some((100, 100)));
+
+        image.go_to_operation(Some(0));
+        image.remove_operation(0);
+        assert_eq!(image.operations().len(), 1);
+        assert_eq!(image.current_operation_index(), None);
+        assert!(image.can_redo_operation());
+    }
+
+    #[test]
+    fn load_applies_edits_from_sidecar() {
+        let mut test_resources = TestResources::new("test/load_applies_edits_from_sidecar");
+        test_resources.add_file("test.png", TEST_IMAGE);
+        let image_path = test_resources.file_folder().join("test.png");
+
+        let mut image = Image::load(&image_path).unwrap();
+        image = image.apply_operation(&ImageOperation::Resize((100, 100)));
+        image = image.apply_operation(&ImageOperation::Rotate(PixbufRotation::Clockwise));
+        image.undo_operation();
+        image.save_edits(&image_path).unwrap();
+
+        let image = Image::load(&image_path).unwrap();
+        assert_eq!(image.operations().len(), 2);
+        assert_eq!(image.current_operation_index(), Some(0));
+        assert_eq!(image.image_size(), Some((100, 100)));
+    }
+
+    #[test]
+    fn revert_to_original_drops_edits_and_sidecar() {
+        let mut test_resources = TestResources::new("test/revert_to_original");
+        test_resources.add_file("test.png", TEST_IMAGE);
+        let image_path = test_resources.file_folder().join("test.png");
+
+        let mut image = Image::load(&image_path).unwrap();
+        image = image.apply_operation(&ImageOperation::Resize((10, 10)));
+        image.save_edits(&image_path).unwrap();
+        assert!(Sidecar::path(&image_path).exists());
+
+        image.revert_to_original(&image_path).unwrap();
+        assert!(!image.has_operations());
+        assert_eq!(image.image_size(), Some((128, 128)));
+        assert!(!Sidecar::path(&image_path).exists());
+        assert!(!Image::load(&image_path).unwrap().has_operations());
+    }
+
+    #[test]
+    fn image_stays_edited_until_edits_are_written_into_its_file() {
+        let mut test_resources =
+            TestResources::new("test/image_stays_edited_until_edits_are_written_into_its_file");
+        test_resources.add_file("test.png", TEST_IMAGE);
+        let image_path = test_resources.file_folder().join("test.png");
+
+        let mut image = Image::load(&image_path).unwrap();
+        assert!(!image.is_edited());
+        image = image.apply_operation(&ImageOperation::Resize((10, 20)));
+        assert!(image.is_edited());
+        assert!(image.has_unwritten_edits());
+
+        image.save_edits(&image_path).unwrap();
+        assert!(image.is_edited());
+        assert!(!image.has_unwritten_edits());
+
+        save(&mut image, &image_path, true).unwrap();
+        assert!(!image.is_edited());
+        assert!(!Sidecar::path(&image_path).exists());
+    }
+
+    #[test]
+    fn recipe_can_be_applied_to_another_image() {
+        let mut test_resources = TestResources::new("test/recipe_can_be_applied_to_another_image");
+        test_resources.add_file("test.png", TEST_IMAGE);
+        let image_path = test_resources.file_folder().join("test.png");
+
+        let mut image = Image::load(&image_path).unwrap();
+        image = image.apply_operation(&ImageOperation::Crop(((0, 0), (64, 32))));
+        image = image.apply_operation(&ImageOperation::Rotate(PixbufRotation::Clockwise));
+        image = image.apply_operation(&ImageOperation::Resize((10, 10)));
+        image.undo_operation();
+        let recipe = image.recipe().unwrap();
+
+        let mut other_image = Image::load(&image_path).unwrap();
+        other_image = other_image.apply_operation(&ImageOperation::Resize((256, 256)));
+        assert!(other_image.push_recipe(&recipe));
+        other_image.rebuild_current_image_buffer();
+        assert_eq!(other_image.operations().len(), 3);
+        assert_eq!(other_image.image_size(), Some((64, 128)));
+    }
+
+    #[test]
+    fn decoded_image_can_be_sent_to_another_thread() {
+        let mut test_resources = TestResources::new("test/decoded_image_can_be_sent");
+        test_resources.add_file("test.png", TEST_IMAGE);
+        let image_path = test_resources.file_folder().join("test.png");
+
+        let decoded_image = std::thread::spawn(move || {
+            DecodedImage::decode(
+                &image_path,
+                Some((vec![ImageOperation::Resize((64, 32))], Some(0))),
+            )
+        })
+        .join()
+        .unwrap()
+        .unwrap();
+        let image = Image::from(decoded_image);
+
+        assert_eq!(image.image_size(), Some((64, 32)));
+        assert_eq!(image.operations().len(), 1);
+        assert!(image.can_undo_operation());
+    }
+
+    #[test]
+    fn memory_usage_counts_shared_buffers_once() {
+        let mut test_resources = TestResources::new("test/memory_usage_counts_shared_buffers");
+        test_resources.add_file("test.png", TEST_IMAGE);
+
+        let mut image = Image::from(
+            DecodedImage::decode(&test_resources.file_folder().join("test.png"), None).unwrap(),
+        );
+        let original_size = image.original_image_buffer.as_ref().unwrap().byte_length();
+        assert_eq!(original_size, image.memory_usage());
+
+        image = image.apply_operation(&ImageOperation::Resize((64, 64)));
+        assert!(image.memory_usage() > original_size);
+
+        image.remove_image_buffers();
+        assert_eq!(0, image.memory_usage());
+        assert!(!image.has_image_buffers());
+    }
+
+    #[test]
+    fn proxy_is_decoded_only_for_large_images() {
+        let mut test_resources = TestResources::new("test/proxy_is_decoded_only_for_large");
+        test_resources.add_file("test.png", TEST_IMAGE);
+        let image_path = test_resources.file_folder().join("test.png");
+
+        assert!(DecodedProxy::decode(&image_path, None, (100, 100))
+            .unwrap()
+            .is_none());
+
+        let decoded_proxy = DecodedProxy::decode(
+            &image_path,
+            Some((vec![ImageOperation::Crop(((0, 0), (128, 64)))], Some(0))),
+            (32, 32),
+        )
+        .unwrap()
+        .unwrap();
+        let mut image = Image::from(decoded_proxy);
+        assert!(image.is_proxy());
+        assert!(!image.has_image_buffers());
+        assert_eq!(Some((128, 64)), image.image_size());
+        assert_eq!(1, image.operations().len());
+
+        image.create_preview_image_buffer(PreviewSize::OriginalSize);
+        assert_eq!(Some((128, 64)), image.preview_image_buffer_size());
+    }
+
+    #[test]
+    fn large_preview_is_tiled() {
+        let mut test_resources = TestResources::new("test/large_preview_is_tiled");
+        test_resources.add_file("test.png", TEST_IMAGE);
+
+        let mut image = Image::load(test_resources.file_folder().join("test.png")).unwrap();
+        image.create_preview_image_buffer(PreviewSize::Resized(5000));
+        assert!(image.preview_image_buffer().is_none());
+        assert_eq!(Some((6400, 6400)), image.preview_image_buffer_size());
+        assert!(image.tiled_preview_mut().is_some());
+
+        image.create_preview_image_buffer(PreviewSize::Resized(200));
+        assert!(image.tiled_preview_mut().is_none());
+        assert_eq!(Some((256, 256)), image.preview_image_buffer_size());
+    }
+
+    #[test]
+    fn zoomed_preview_is_drawn_scaled_until_it_is_created_again() {
+        let mut test_resources = TestResources::new("test/zoomed_preview_is_drawn_scaled");
+        test_resources.add_file("test.png", TEST_IMAGE);
+
+        let mut image = Image::load(test_resources.file_folder().join("test.png")).unwrap();
+        image.create_preview_image_buffer(PreviewSize::OriginalSize);
+        assert_eq!(Some((1.0, 1.0)), image.preview_display_scale());
+        assert!(!image.is_magnified());
+
+        image.set_display_size(PreviewSize::Resized(200));
+        assert_eq!(Some((256, 256)), image.preview_image_buffer_size());
+        assert_eq!(Some((2.0, 2.0)), image.preview_display_scale());
+        assert!(image.is_magnified());
+
+        image.create_preview_image_buffer(PreviewSize::Resized(200));
+        assert_eq!(Some((1.0, 1.0)), image.preview_display_scale());
+    }
+
+    #[test]
+    fn push_operation_drops_undone_operations() {
+        let mut test_resources = TestResources::new("test/push_operation_drops_undone");
+        test_resources.add_file("test.png", TEST_IMAGE);
+
+        let mut image = Image::load(test_resources.file_folder().join("test.png")).unwrap();
+        image = image.apply_operation(&ImageOperation::Resize((100, 100)));
+        image = image.apply_operation(&ImageOperation::Resize((50, 50)));
+        image.undo_operation();
+
+        let image_buffer = Pixbuf::new(gtk::gdk_pixbuf::Colorspace::Rgb, false, 8, 10, 20).unwrap();
+        image.push_operation(ImageOperation::Resize((10, 20)), image_buffer);
+        assert_eq!(image.operations().len(), 2);
+        assert_eq!(image.current_operation_index(), Some(1));
+        assert_eq!(image.image_size(), Some((10, 20)));
+    }
+
+    #[test]
+    fn downscaled_edits_are_saved_in_full_resolution() {
+        let mut test_resources = TestResources::new("test/downscaled_edits_are_saved");
+        test_resources.add_file("test.png", TEST_IMAGE);
+
+        let mut image = Image::load(test_resources.file_folder().join("test.png")).unwrap();
+        image.edit_downscaled(64);
+        assert!(image.is_edited_downscaled());
+
+        let mut image = image.apply_operation(&ImageOperation::Crop(((0, 0), (100, 50))));
+        image.undo_operation();
+        image.redo_operation();
+        image.rebuild_current_image_buffer();
+        let current_image_buffer = image.current_image_buffer().unwrap();
+        assert_eq!(
+            (50, 25),
+            (current_image_buffer.width(), current_image_buffer.height())
+        );
+        assert_eq!(Some((100, 50)), image.image_size());
+
+        let saved_file_path = test_resources.file_folder().join("saved.png");
+        save(&mut image, &saved_file_path, true).unwrap();
+        let saved_image_buffer = Pixbuf::from_file(&saved_file_path).unwrap();
+        assert_eq!(
+            (100, 50),
+            (saved_image_buffer.width(), saved_image_buffer.height())
+        );
+        assert!(!image.has_operations());
+        assert!(image.is_edited_downscaled());
+        assert_eq!(Some((100, 50)), image.image_size());
+    }
+
+    #[test]
+    fn original_preview_has_zoom_of_edited_preview() {
+        let mut test_resources = TestResources::new("test/original_preview_has_zoom");
+        test_resources.add_file("test.png", TEST_IMAGE);
+
+        let image = Image::load(test_resources.file_folder().join("test.png")).unwrap();
+        let mut image = image.apply_operation(&ImageOperation::Crop(((0, 0), (64, 32))));
+        image.create_preview_image_buffer(PreviewSize::Resized(200));
+        assert_eq!(Some((128, 64)), image.preview_image_buffer_size());
+
+        let original_preview = image.original_preview_mut().unwrap();
+        assert_eq!((256, 256), original_preview.size());
+        assert_eq!(0, original_preview.memory_usage());
+    }
 }
@@ -1,16 +1,37 @@
 use std::{
-    collections::HashMap,
+    cell::RefCell,
+    collections::{HashMap, HashSet},
     ops::{Index, IndexMut},
     path::{Path, PathBuf},
 };
 
-use crate::image::Image;
+use crate::{
+    backup::BackupLocation,
+    encoder::EncoderOptions,
+    image::{replay_operations, save_image_buffer_with_options, DecodedImage, Image},
+    image_operation::ImageOperation,
+    recovery::RecoveredImage,
+    worker::SendableImageBuffer,
+};
 
 use anyhow::{anyhow, Result};
 use gtk::gdk::Texture;
 
+const DEFAULT_MEMORY_LIMIT: usize = 1024 * 1024 * 1024;
+
 pub struct ImageList {
     images: HashMap<PathBuf, Image>,
+    /// Paths of images ordered from the least to the most recently used.
+    recently_used_image_paths: Vec<PathBuf>,
+    memory_limit: usize,
+    /// Where files are backed up before they are overwritten, `None` when they aren't.
+    backup_location: Option<BackupLocation>,
+    /// Options of encoders images are saved with.
+    encoder_options: EncoderOptions,
+    /// Folders where edits couldn't be saved, e.g. because they are read-only.
+    folders_failing_edits_save: RefCell<HashSet<PathBuf>>,
+    /// Generation given to the last inserted image.
+    load_generation: u64,
     current_image_path: Option<PathBuf>,
 }
 
@@ -18,32 +39,182 @@ impl ImageList {
     pub fn new() -> Self {
         Self {
             images: HashMap::new(),
+            recently_used_image_paths: Vec::new(),
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+            backup_location: None,
+            encoder_options: EncoderOptions::default(),
+            folders_failing_edits_save: RefCell::new(HashSet::new()),
+            load_generation: 0,
             current_image_path: None,
         }
     }
 
+    /// Removes all images, memory limit stays the same.
+    pub fn clear(&mut self) {
+        self.images.clear();
+        self.recently_used_image_paths.clear();
+        self.current_image_path = None;
+    }
+
     pub fn remove(&mut self, key: &Path) -> Option<Image> {
+        self.recently_used_image_paths
+            .retain(|image_path| image_path != key);
         self.images.remove(key)
     }
 
-    pub fn insert(&mut self, key: PathBuf, value: Image) {
+    pub fn get(&self, key: &Path) -> Option<&Image> {
+        self.images.get(key)
+    }
+
+    pub fn get_mut(&mut self, key: &Path) -> Option<&mut Image> {
+        self.images.get_mut(key)
+    }
+
+    /// Drops decoded buffers of saved images other than the current one and the kept ones,
+    /// their edits stay in the list.
+    pub fn remove_image_buffers_except(&mut self, kept_image_paths: &[PathBuf]) {
+        let current_image_path = self.current_image_path.as_ref();
+        self.images
+            .iter_mut()
+            .filter(|(image_path, image)| {
+                Some(*image_path) != current_image_path
+                    && !kept_image_paths.contains(image_path)
+                    && !image.is_unsaved()
+            })
+            .for_each(|(_, image)| image.remove_image_buffers());
+    }
+
+    pub fn insert(&mut self, key: PathBuf, mut value: Image) {
+        self.load_generation += 1;
+        value.set_load_generation(self.load_generation);
+        // an image decoded again keeps edits which weren't written to the sidecar yet
+        if let Some(replaced_image) = self.images.get(&key) {
+            value.inherit_unwritten_edits(replaced_image);
+        }
+        self.mark_as_used(&key);
         self.images.insert(key, value);
+        self.evict_image_buffers();
+    }
+
+    pub fn set_memory_limit(&mut self, memory_limit: usize) {
+        self.memory_limit = memory_limit;
+        self.evict_image_buffers();
+    }
+
+    pub fn memory_limit(&self) -> usize {
+        self.memory_limit
+    }
+
+    pub fn backup_location(&self) -> Option<&BackupLocation> {
+        self.backup_location.as_ref()
+    }
+
+    pub fn set_backup_location(&mut self, backup_location: Option<BackupLocation>) {
+        self.backup_location = backup_location;
+    }
+
+    pub fn set_encoder_options(&mut self, encoder_options: EncoderOptions) {
+        self.encoder_options = encoder_options;
+    }
+
+    /// Remembers that edits of the image couldn't be saved, returns `false` when they
+    /// already failed to be saved in its folder, so the error is reported only once.
+    pub fn mark_edits_save_failed(&self, image_path: &Path) -> bool {
+        let folder = image_path.parent().unwrap_or(image_path).to_path_buf();
+        self.folders_failing_edits_save.borrow_mut().insert(folder)
+    }
+
+    pub fn encoder_options(&self) -> &EncoderOptions {
+        &self.encoder_options
+    }
+
+    /// Keeps a copy of the file at the path before it is overwritten for the first time.
+    fn back_up(&self, image_path: &Path) -> Result<()> {
+        if let Some(backup_location) = self.backup_location.as_ref() {
+            if let Some(backup_path) = backup_location.back_up(image_path)? {
+                info!(
+                    "Backed up {} to {}",
+                    image_path.display(),
+                    backup_path.display()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns number of bytes used by decoded buffers of all images.
+    pub fn memory_usage(&self) -> usize {
+        self.images.values().map(Image::memory_usage).sum()
+    }
+
+    pub fn image_count(&self) -> usize {
+        self.images.len()
+    }
+
+    pub fn decoded_image_count(&self) -> usize {
+        self.images
+            .values()
+            .filter(|image| image.has_image_buffers())
+            .count()
+    }
+
+    /// Drops decoded buffers of least recently used images until memory usage is within
+    /// the limit. Current and unsaved images are never evicted, edits of evicted images
+    /// are kept so they are applied again when the image is decoded.
+    pub fn evict_image_buffers(&mut self) {
+        let mut memory_usage = self.memory_usage();
+        for image_path in &self.recently_used_image_paths {
+            if memory_usage <= self.memory_limit {
+                break;
+            }
+            if Some(image_path) == self.current_image_path.as_ref() {
+                continue;
+            }
+            if let Some(image) = self.images.get_mut(image_path) {
+                if image.is_unsaved() || !image.has_image_buffers() {
+                    continue;
+                }
+                memory_usage -= image.memory_usage();
+                image.remove_image_buffers();
+                debug!("Evicted decoded buffers of {}", image_path.display());
+            }
+        }
+    }
+
+    fn mark_as_used(&mut self, image_path: &Path) {
+        self.recently_used_image_paths
+            .retain(|recently_used_image_path| recently_used_image_path != image_path);
+        self.recently_used_image_paths
+            .push(image_path.to_path_buf());
+    }
+
+    /// Inserts an image which doesn't exist on disk yet under a free file name in given folder
+    /// and makes it the current image.
+    pub fn insert_new_image(&mut self, folder: &Path, file_stem: &str, image: Image) -> PathBuf {
+        let image_path = (1..)
+            .map(|number| match number {
+                1 => folder.join(format!("{}.png", file_stem)),
+                number => folder.join(format!("{} {}.png", file_stem, number)),
+            })
+            .find(|image_path| !self.images.contains_key(image_path) && !image_path.exists())
+            .unwrap();
+        self.insert(image_path.clone(), image);
+        self.set_current_image_path(Some(image_path.clone()));
+        image_path
     }
 
     pub fn set_current_image_path(&mut self, current_image_path: Option<PathBuf>) {
+        if let Some(current_image_path) = &current_image_path {
+            self.mark_as_used(current_image_path);
+        }
         self.current_image_path = current_image_path;
+        self.evict_image_buffers();
     }
 
     // pub fn current_image(&self) -> Option<&Image> {
     //     self.current_image_path.as_ref().map(|image_path| self.images.get(image_path)).flatten()
     // }
 
-    pub fn remove_current_image(&mut self) -> Option<Image> {
-        self.current_image_path
-            .clone()
-            .and_then(|image_path| self.remove(&image_path))
-    }
-
     pub fn current_image_mut(&mut self) -> Option<&mut Image> {
         self.current_image_path
             .clone()
@@ -60,35 +231,216 @@ impl ImageList {
         self.current_image_path.clone()
     }
 
-    pub fn save_current_image(&mut self, filename: Option<PathBuf>) -> Result<()> {
-        let (filename, clear_operations) = if let Some(filename) = filename {
-            (filename, false)
-        } else {
-            (
-                self.current_image_path
-                    .clone()
-                    .ok_or_else(|| anyhow!("Current image path is not set"))?,
-                true,
-            )
+    /// Prepares saving of the current image into the file, or into its own file
+    /// when it is `None`.
+    pub fn prepare_current_image_save(&mut self, file_path: Option<PathBuf>) -> Result<ImageSave> {
+        let image_path = self
+            .current_image_path
+            .clone()
+            .ok_or_else(|| anyhow!("Current image path is not set"))?;
+        self.prepare_save(&image_path, file_path)
+    }
+
+    /// Prepares saving of the image into the file, or into its own file when it is `None`.
+    /// The file is backed up and the image is decoded again if its buffers were dropped,
+    /// encoding and writing are left to `ImageSave::write`.
+    pub fn prepare_save(
+        &mut self,
+        image_path: &Path,
+        file_path: Option<PathBuf>,
+    ) -> Result<ImageSave> {
+        let image = self
+            .images
+            .get(image_path)
+            .ok_or_else(|| anyhow!("Image {} is not loaded", image_path.display()))?;
+        // images which were never saved become the file they are saved into
+        let (file_path, clear_operations) = match file_path {
+            Some(file_path) => (file_path, image.is_unsaved()),
+            None => (image_path.to_path_buf(), true),
         };
+        if clear_operations && image.is_modified_externally(&file_path) {
+            return Err(modified_externally_error(&file_path));
+        }
+        self.back_up(&file_path)?;
+        let image = self.images.get_mut(image_path).unwrap();
+        if !image.has_image_buffers() && !image.is_unsaved() {
+            let decoded_image = DecodedImage::decode(
+                image_path,
+                Some((image.operations().to_vec(), image.current_operation_index())),
+            )?;
+            *image = Image::from(decoded_image);
+        }
+        let (image_buffer, operations) = image
+            .full_resolution_source()
+            .ok_or_else(|| anyhow!("Image buffer is missing!"))?;
+        Ok(ImageSave {
+            image_path: image_path.to_path_buf(),
+            file_path,
+            clear_operations,
+            image_buffer: SendableImageBuffer::from_image_buffer(&image_buffer)
+                .ok_or_else(|| anyhow!("Couldn't read pixels of the image"))?,
+            operations,
+            encoder_options: self.encoder_options.clone(),
+        })
+    }
 
-        let current_image = self
-            .current_image_mut()
-            .ok_or_else(|| anyhow!("Couldn't load current image"))?;
+    /// Updates the image after the save wrote it, images which were never saved
+    /// are moved to the path of their new file.
+    pub fn finish_save(&mut self, image_save: ImageSave) -> Result<()> {
+        let image = self
+            .images
+            .get_mut(&image_save.image_path)
+            .ok_or_else(|| anyhow!("Image {} is not loaded", image_save.image_path.display()))?;
+        let image_was_unsaved = image.is_unsaved();
+        image.mark_saved(
+            &image_save.file_path,
+            image_save.image_buffer.into_image_buffer(),
+            image_save.clear_operations,
+        )?;
+        if image_was_unsaved {
+            let image = self.remove(&image_save.image_path).unwrap();
+            self.insert(image_save.file_path.clone(), image);
+            if self.current_image_path.as_ref() == Some(&image_save.image_path) {
+                self.set_current_image_path(Some(image_save.file_path));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns paths of images with changes which aren't written to their files.
+    pub fn edited_image_paths(&self) -> Vec<PathBuf> {
+        let mut edited_image_paths = self
+            .images
+            .iter()
+            .filter(|(_, image)| image.is_edited())
+            .map(|(image_path, _)| image_path.clone())
+            .collect::<Vec<_>>();
+        edited_image_paths.sort();
+        edited_image_paths
+    }
+
+    /// Returns edits of images which can be restored after a crash. Only edits which weren't
+    /// written to sidecar files are included, images which were never saved are left out
+    /// since they exist only in memory.
+    pub fn recoverable_images(&self) -> Vec<RecoveredImage> {
+        let mut recoverable_images = self
+            .images
+            .iter()
+            .filter(|(_, image)| image.has_unwritten_edits() && !image.is_unsaved())
+            .map(|(image_path, image)| RecoveredImage {
+                path: image_path.clone(),
+                operations: image.operations().to_vec(),
+                current_operation_index: image.current_operation_index(),
+            })
+            .collect::<Vec<_>>();
+        recoverable_images.sort_by(|first, second| first.path.cmp(&second.path));
+        recoverable_images
+    }
+
+    /// Adds images with edits restored after a crash, they are decoded when they are shown.
+    /// Decoded images are replaced too, so they have to be loaded again.
+    pub fn restore(&mut self, recovered_images: Vec<RecoveredImage>) {
+        for recovered_image in recovered_images {
+            let image = Image::with_operations(
+                recovered_image.operations,
+                recovered_image.current_operation_index,
+            );
+            self.insert(recovered_image.path, image);
+        }
+    }
 
-        current_image.save(filename, clear_operations)?;
+    /// Drops edits of the image together with its sidecar file,
+    /// images which were never saved are removed.
+    pub fn discard_edits(&mut self, image_path: &Path) -> Result<()> {
+        let image = match self.images.get_mut(image_path) {
+            Some(image) => image,
+            None => return Ok(()),
+        };
+        if !image.is_unsaved() {
+            return image.revert_to_original(image_path);
+        }
+        self.remove(image_path);
+        if self.current_image_path.as_deref() == Some(image_path) {
+            self.current_image_path = None;
+        }
         Ok(())
     }
 
+    pub fn save_current_image_edits(&mut self) -> Result<()> {
+        let current_image_path = match self.current_image_path.clone() {
+            Some(current_image_path) => current_image_path,
+            None => return Ok(()),
+        };
+        match self.images.get_mut(&current_image_path) {
+            Some(current_image) => current_image.save_edits(&current_image_path),
+            None => Ok(()),
+        }
+    }
+
+    pub fn revert_current_image(&mut self) -> Result<()> {
+        let current_image_path = self
+            .current_image_path
+            .clone()
+            .ok_or_else(|| anyhow!("Current image path is not set"))?;
+        self.current_image_mut()
+            .ok_or_else(|| anyhow!("Couldn't load current image"))?
+            .revert_to_original(&current_image_path)
+    }
+
     pub fn copy_current_image(&self, clipboard: gtk::gdk::Clipboard) {
         if let Some(current_image) = self.current_image() {
-            if let Some(buffer) = current_image.current_image_buffer() {
-                clipboard.set_texture(&Texture::for_pixbuf(buffer));
+            if let Some(buffer) = current_image.full_resolution_image_buffer() {
+                clipboard.set_texture(&Texture::for_pixbuf(&buffer));
             }
         }
     }
 }
 
+/// Image being saved, it is encoded and written with `write`, which can be called on a worker
+/// thread, and the image in the list is updated with `ImageList::finish_save` afterwards.
+#[derive(Debug)]
+pub struct ImageSave {
+    /// Path of the image in the list.
+    image_path: PathBuf,
+    file_path: PathBuf,
+    /// True when the file becomes the new original of the image.
+    clear_operations: bool,
+    image_buffer: SendableImageBuffer,
+    /// Operations replayed on the image buffer to get the full resolution image, when the
+    /// image is edited downscaled.
+    operations: Vec<ImageOperation>,
+    encoder_options: EncoderOptions,
+}
+
+impl ImageSave {
+    pub fn image_path(&self) -> &Path {
+        &self.image_path
+    }
+
+    pub fn file_path(&self) -> &Path {
+        &self.file_path
+    }
+
+    /// Encodes the image in full resolution and writes it into the file.
+    pub fn write(&mut self) -> Result<()> {
+        let mut image_buffer = self.image_buffer.clone().into_image_buffer();
+        if !self.operations.is_empty() {
+            image_buffer = replay_operations(image_buffer, &self.operations);
+            self.image_buffer = SendableImageBuffer::from_image_buffer(&image_buffer)
+                .ok_or_else(|| anyhow!("Couldn't read pixels of the image"))?;
+            self.operations.clear();
+        }
+        save_image_buffer_with_options(&image_buffer, &self.file_path, &self.encoder_options)
+    }
+}
+
+fn modified_externally_error(image_path: &Path) -> anyhow::Error {
+    anyhow!(
+        "{} was changed by another program, reload it or save it as a new file",
+        image_path.display()
+    )
+}
+
 impl Index<&PathBuf> for ImageList {
     type Output = Image;
 
@@ -105,8 +457,12 @@ impl IndexMut<&PathBuf> for ImageList {
 
 #[cfg(test)]
 mod tests {
+    use gtk::gdk_pixbuf::{Colorspace, Pixbuf, PixbufRotation};
+
     use crate::{
+        decoder,
         image_operation::{ApplyImageOperation, ImageOperation},
+        sidecar::Sidecar,
         test_utils::TestResources,
     };
 
@@ -114,6 +470,18 @@ mod tests {
 
     const TEST_IMAGE: &[u8] = include_bytes!("resources/test/test_image.png");
 
+    fn save_current_image(image_list: &mut ImageList, file_path: Option<PathBuf>) -> Result<()> {
+        let mut image_save = image_list.prepare_current_image_save(file_path)?;
+        image_save.write()?;
+        image_list.finish_save(image_save)
+    }
+
+    fn save_image(image_list: &mut ImageList, image_path: &Path) -> Result<()> {
+        let mut image_save = image_list.prepare_save(image_path, None)?;
+        image_save.write()?;
+        image_list.finish_save(image_save)
+    }
+
     #[test]
     fn save_current_image_overwrites_image_at_current_image_path_when_filename_is_set_to_none() {
         let mut test_resources = TestResources::new("test/save_current_image_overwrites_image_at_current_image_path_when_filename_is_set_to_none");
@@ -133,7 +501,7 @@ mod tests {
         let mut image_list = ImageList::new();
         image_list.insert(image_path.clone(), image);
         image_list.set_current_image_path(Some(image_path.clone()));
-        image_list.save_current_image(None).unwrap();
+        save_current_image(&mut image_list, None).unwrap();
 
         let modification_date = std::fs::File::open(&image_path)
             .unwrap()
@@ -158,13 +526,172 @@ mod tests {
         image_list.set_current_image_path(Some(image_path.clone()));
 
         let new_image_path = test_resources.file_folder().join("test2.png");
-        image_list
-            .save_current_image(Some(new_image_path.clone()))
-            .unwrap();
+        save_current_image(&mut image_list, Some(new_image_path.clone())).unwrap();
 
         assert!(std::fs::File::open(new_image_path).is_ok());
     }
 
+    #[test]
+    fn operation_tag_changes_when_image_is_edited_or_inserted_again() {
+        let image_path = PathBuf::from("test.png");
+        let image_buffer = Pixbuf::new(Colorspace::Rgb, true, 8, 4, 2).unwrap();
+        let mut image_list = ImageList::new();
+        image_list.insert(
+            image_path.clone(),
+            Image::from_image_buffer(image_buffer.clone()),
+        );
+        let operation_tag = image_list.get(&image_path).unwrap().operation_tag();
+
+        let image = image_list.get_mut(&image_path).unwrap();
+        image.push_operation(
+            ImageOperation::Rotate(PixbufRotation::Clockwise),
+            image_buffer.clone(),
+        );
+        assert_ne!(operation_tag, image.operation_tag());
+        image.undo_operation();
+        assert_eq!(operation_tag, image.operation_tag());
+
+        image_list.insert(image_path.clone(), Image::from_image_buffer(image_buffer));
+        assert_ne!(
+            operation_tag,
+            image_list.get(&image_path).unwrap().operation_tag()
+        );
+    }
+
+    #[test]
+    fn insert_new_image_uses_free_file_name() {
+        let mut test_resources = TestResources::new("test/insert_new_image_uses_free_file_name");
+        test_resources.add_file("Collage.png", TEST_IMAGE);
+        let image_buffer = Pixbuf::new(Colorspace::Rgb, false, 8, 10, 10).unwrap();
+
+        let mut image_list = ImageList::new();
+        let first_path = image_list.insert_new_image(
+            test_resources.file_folder(),
+            "Collage",
+            Image::from_image_buffer(image_buffer.clone()),
+        );
+        let second_path = image_list.insert_new_image(
+            test_resources.file_folder(),
+            "Collage",
+            Image::from_image_buffer(image_buffer),
+        );
+
+        assert_eq!(
+            test_resources.file_folder().join("Collage 2.png"),
+            first_path
+        );
+        assert_eq!(
+            test_resources.file_folder().join("Collage 3.png"),
+            second_path
+        );
+        assert_eq!(Some(second_path), image_list.current_image_path());
+    }
+
+    #[test]
+    fn remove_image_buffers_except_keeps_current_kept_and_unsaved_images() {
+        let test_resources = TestResources::new("test/remove_image_buffers_except");
+        let image_buffer = Pixbuf::new(Colorspace::Rgb, false, 8, 10, 10).unwrap();
+        let saved_image = || {
+            let mut image = Image::from_image_buffer(image_buffer.clone());
+            image
+                .mark_saved(
+                    &test_resources.file_folder().join("saved.png"),
+                    image_buffer.clone(),
+                    false,
+                )
+                .unwrap();
+            image
+        };
+
+        let mut image_list = ImageList::new();
+        for image_path in ["current.png", "kept.png", "other.png"] {
+            image_list.insert(PathBuf::from(image_path), saved_image());
+        }
+        image_list.insert(
+            PathBuf::from("unsaved.png"),
+            Image::from_image_buffer(image_buffer.clone()),
+        );
+        image_list.set_current_image_path(Some(PathBuf::from("current.png")));
+
+        image_list.remove_image_buffers_except(&[PathBuf::from("kept.png")]);
+
+        let has_image_buffers = |image_path: &str| {
+            image_list
+                .get(Path::new(image_path))
+                .unwrap()
+                .has_image_buffers()
+        };
+        assert!(has_image_buffers("current.png"));
+        assert!(has_image_buffers("kept.png"));
+        assert!(has_image_buffers("unsaved.png"));
+        assert!(!has_image_buffers("other.png"));
+        assert!(image_list.get(Path::new("other.png")).is_some());
+    }
+
+    #[test]
+    fn least_recently_used_image_buffers_are_evicted_and_edits_are_kept() {
+        let mut test_resources = TestResources::new("test/least_recently_used_images_evicted");
+        test_resources.add_file("test.png", TEST_IMAGE);
+        let load_image = || {
+            Image::load(test_resources.file_folder().join("test.png"))
+                .unwrap()
+                .apply_operation(&ImageOperation::Resize((10, 10)))
+        };
+
+        let mut image_list = ImageList::new();
+        for image_path in ["first.png", "second.png", "third.png"] {
+            image_list.insert(PathBuf::from(image_path), load_image());
+        }
+        image_list.set_current_image_path(Some(PathBuf::from("first.png")));
+        let image_memory_usage = image_list
+            .get(Path::new("first.png"))
+            .unwrap()
+            .memory_usage();
+
+        image_list.set_memory_limit(image_memory_usage * 2);
+
+        let has_image_buffers = |image_path: &str| {
+            image_list
+                .get(Path::new(image_path))
+                .unwrap()
+                .has_image_buffers()
+        };
+        assert!(has_image_buffers("first.png"));
+        assert!(!has_image_buffers("second.png"));
+        assert!(has_image_buffers("third.png"));
+        assert_eq!(2, image_list.decoded_image_count());
+        assert!(image_list.memory_usage() <= image_memory_usage * 2);
+        assert_eq!(
+            1,
+            image_list
+                .get(Path::new("second.png"))
+                .unwrap()
+                .operations()
+                .len()
+        );
+    }
+
+    #[test]
+    fn save_current_image_moves_unsaved_image_to_filename() {
+        let test_resources =
+            TestResources::new("test/save_current_image_moves_unsaved_image_to_filename");
+        let image_buffer = Pixbuf::new(Colorspace::Rgb, false, 8, 10, 10).unwrap();
+
+        let mut image_list = ImageList::new();
+        let new_image_path = image_list.insert_new_image(
+            test_resources.file_folder(),
+            "Collage",
+            Image::from_image_buffer(image_buffer),
+        );
+        let saved_image_path = test_resources.file_folder().join("saved.png");
+        save_current_image(&mut image_list, Some(saved_image_path.clone())).unwrap();
+
+        assert!(std::fs::File::open(&saved_image_path).is_ok());
+        assert!(!new_image_path.exists());
+        assert_eq!(Some(saved_image_path), image_list.current_image_path());
+        assert!(!image_list.current_image().unwrap().is_unsaved());
+    }
+
     #[test]
     fn save_current_image_clears_image_operations_when_filename_is_set_to_none() {
         let mut test_resources = TestResources::new(
@@ -183,11 +710,38 @@ mod tests {
 
         assert!(image_list.current_image().unwrap().has_operations());
 
-        image_list.save_current_image(None).unwrap();
+        save_current_image(&mut image_list, None).unwrap();
 
         assert!(!image_list.current_image().unwrap().has_operations());
     }
 
+    #[test]
+    fn image_is_written_by_image_save_and_updated_when_save_finishes() {
+        let mut test_resources = TestResources::new(
+            "test/image_is_written_by_image_save_and_updated_when_save_finishes",
+        );
+        test_resources.add_file("test.png", TEST_IMAGE);
+
+        let image_path = test_resources.file_folder().join("test.png");
+
+        let mut image = Image::load(&image_path).unwrap();
+        image = image.apply_operation(&ImageOperation::Resize((10, 10)));
+
+        let mut image_list = ImageList::new();
+        image_list.insert(image_path.clone(), image);
+        image_list.set_current_image_path(Some(image_path.clone()));
+
+        let mut image_save = image_list.prepare_current_image_save(None).unwrap();
+        assert_eq!(TEST_IMAGE, std::fs::read(&image_path).unwrap());
+
+        image_save.write().unwrap();
+        assert_eq!(Some((10, 10)), decoder::image_size(&image_path));
+        assert!(image_list.current_image().unwrap().has_operations());
+
+        image_list.finish_save(image_save).unwrap();
+        assert!(!image_list.current_image().unwrap().has_operations());
+    }
+
     #[test]
     fn save_current_image_does_not_clear_image_operations_when_filename_is_set() {
         let mut test_resources = TestResources::new(
@@ -206,10 +760,170 @@ mod tests {
 
         assert!(image_list.current_image().unwrap().has_operations());
 
+        save_current_image(
+            &mut image_list,
+            Some(test_resources.file_folder().join("test2.png")),
+        )
+        .unwrap();
+
+        assert!(image_list.current_image().unwrap().has_operations());
+    }
+
+    #[test]
+    fn save_current_image_removes_sidecar_when_filename_is_set_to_none() {
+        let mut test_resources = TestResources::new(
+            "test/save_current_image_removes_sidecar_when_filename_is_set_to_none",
+        );
+        test_resources.add_file("test.png", TEST_IMAGE);
+
+        let image_path = test_resources.file_folder().join("test.png");
+
+        let mut image = Image::load(&image_path).unwrap();
+        image = image.apply_operation(&ImageOperation::Resize((10, 10)));
+
+        let mut image_list = ImageList::new();
+        image_list.insert(image_path.clone(), image);
+        image_list.set_current_image_path(Some(image_path.clone()));
+        image_list.save_current_image_edits().unwrap();
+        assert!(Sidecar::path(&image_path).exists());
+
+        save_current_image(&mut image_list, None).unwrap();
+
+        assert!(!Sidecar::path(&image_path).exists());
+    }
+
+    #[test]
+    fn edited_images_are_saved_or_discarded() {
+        let mut test_resources = TestResources::new("test/edited_images_are_saved_or_discarded");
+        test_resources.add_file("saved.png", TEST_IMAGE);
+        test_resources.add_file("discarded.png", TEST_IMAGE);
+        let saved_image_path = test_resources.file_folder().join("saved.png");
+        let discarded_image_path = test_resources.file_folder().join("discarded.png");
+
+        let mut image_list = ImageList::new();
+        for image_path in [&saved_image_path, &discarded_image_path] {
+            let image = Image::load(image_path)
+                .unwrap()
+                .apply_operation(&ImageOperation::Resize((10, 10)));
+            image_list.insert(image_path.clone(), image);
+        }
+        image_list.remove_image_buffers_except(&[]);
+        assert_eq!(
+            vec![discarded_image_path.clone(), saved_image_path.clone()],
+            image_list.edited_image_paths()
+        );
+
+        save_image(&mut image_list, &saved_image_path).unwrap();
+        let saved_image_buffer = Pixbuf::from_file(&saved_image_path).unwrap();
+        assert_eq!(
+            (10, 10),
+            (saved_image_buffer.width(), saved_image_buffer.height())
+        );
+        image_list.discard_edits(&discarded_image_path).unwrap();
+        assert!(image_list.edited_image_paths().is_empty());
+        assert!(!image_list[&discarded_image_path].has_operations());
+    }
+
+    #[test]
+    fn image_with_edits_written_to_sidecar_is_listed_as_edited() {
+        let mut test_resources =
+            TestResources::new("test/image_with_edits_written_to_sidecar_is_listed_as_edited");
+        test_resources.add_file("test.png", TEST_IMAGE);
+        let image_path = test_resources.file_folder().join("test.png");
+
+        let image = Image::load(&image_path)
+            .unwrap()
+            .apply_operation(&ImageOperation::Resize((10, 10)));
+        let mut image_list = ImageList::new();
+        image_list.insert(image_path.clone(), image);
+        image_list.set_current_image_path(Some(image_path.clone()));
+        image_list.save_current_image_edits().unwrap();
+
+        assert_eq!(vec![image_path.clone()], image_list.edited_image_paths());
+        image_list.discard_edits(&image_path).unwrap();
+        assert!(image_list.edited_image_paths().is_empty());
+        assert!(!Sidecar::path(&image_path).exists());
+    }
+
+    #[test]
+    fn externally_modified_image_is_not_overwritten_until_change_is_ignored() {
+        let mut test_resources = TestResources::new(
+            "test/externally_modified_image_is_not_overwritten_until_change_is_ignored",
+        );
+        test_resources.add_file("test.png", TEST_IMAGE);
+        let image_path = test_resources.file_folder().join("test.png");
+
+        let image = Image::load(&image_path)
+            .unwrap()
+            .apply_operation(&ImageOperation::Resize((10, 10)));
+        let mut image_list = ImageList::new();
+        image_list.insert(image_path.clone(), image);
+        image_list.set_current_image_path(Some(image_path.clone()));
+        assert!(!image_list
+            .current_image()
+            .unwrap()
+            .is_modified_externally(&image_path));
+
+        Pixbuf::new(Colorspace::Rgb, false, 8, 20, 20)
+            .unwrap()
+            .savev(&image_path, "png", &[])
+            .unwrap();
+        assert!(image_list
+            .current_image()
+            .unwrap()
+            .is_modified_externally(&image_path));
+        assert!(save_current_image(&mut image_list, None).is_err());
+        assert!(save_image(&mut image_list, &image_path).is_err());
+        assert_eq!(20, Pixbuf::from_file(&image_path).unwrap().width());
+
         image_list
-            .save_current_image(Some(test_resources.file_folder().join("test2.png")))
+            .current_image_mut()
+            .unwrap()
+            .ignore_external_modification(&image_path)
             .unwrap();
+        save_current_image(&mut image_list, None).unwrap();
+        assert_eq!(10, Pixbuf::from_file(&image_path).unwrap().width());
+        assert!(!image_list
+            .current_image()
+            .unwrap()
+            .is_modified_externally(&image_path));
+    }
 
-        assert!(image_list.current_image().unwrap().has_operations());
+    #[test]
+    fn edits_of_saved_images_are_restored_after_crash() {
+        let mut test_resources =
+            TestResources::new("test/edits_of_saved_images_are_restored_after_crash");
+        test_resources.add_file("test.png", TEST_IMAGE);
+        let image_path = test_resources.file_folder().join("test.png");
+        let unsaved_image_path = test_resources.file_folder().join("unsaved.png");
+
+        let mut image_list = ImageList::new();
+        let image = Image::load(&image_path)
+            .unwrap()
+            .apply_operation(&ImageOperation::Resize((10, 10)));
+        image_list.insert(image_path.clone(), image);
+        let unsaved_image =
+            Image::from_image_buffer(Pixbuf::new(Colorspace::Rgb, false, 8, 10, 10).unwrap());
+        image_list.insert(unsaved_image_path, unsaved_image);
+
+        let recoverable_images = image_list.recoverable_images();
+        assert_eq!(1, recoverable_images.len());
+        assert_eq!(image_path, recoverable_images[0].path);
+        image_list
+            .get_mut(&image_path)
+            .unwrap()
+            .save_edits(&image_path)
+            .unwrap();
+        assert!(image_list.recoverable_images().is_empty());
+
+        let mut restored_image_list = ImageList::new();
+        restored_image_list.restore(recoverable_images);
+        let restored_image = restored_image_list.get(&image_path).unwrap();
+        assert!(!restored_image.has_image_buffers());
+        assert_eq!(
+            format!("{:?}", [ImageOperation::Resize((10, 10))]),
+            format!("{:?}", restored_image.operations())
+        );
+        assert_eq!(vec![image_path], restored_image_list.edited_image_paths());
     }
 }
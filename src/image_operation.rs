@@ -1,14 +1,173 @@
-use std::cmp;
+use std::{cmp, fmt};
 
-use gtk::gdk_pixbuf::{InterpType, Pixbuf, PixbufRotation};
+use gtk::{
+    gdk_pixbuf::{Colorspace, InterpType, Pixbuf, PixbufRotation},
+    glib,
+};
+use serde::{Deserialize, Serialize};
 
 use crate::image::CoordinatesPair;
 
-#[derive(Copy, Clone, Debug)]
+pub type Color = (u8, u8, u8);
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum ImageOperation {
-    Rotate(PixbufRotation),
+    Rotate(#[serde(with = "rotation_degrees")] PixbufRotation),
     Crop(CoordinatesPair),
     Resize((u32, u32)),
+    /// Makes pixels close to the color transparent. The first value is the tolerance,
+    /// the second one is the feathering range in which pixels become partially transparent.
+    MakeColorTransparent(Color, u8, u8),
+    FlattenOntoColor(Color),
+}
+
+impl ImageOperation {
+    /// Returns size of an image with given size after applying the operation.
+    pub fn output_size(&self, (width, height): (u32, u32)) -> (u32, u32) {
+        match self {
+            ImageOperation::Rotate(PixbufRotation::Clockwise)
+            | ImageOperation::Rotate(PixbufRotation::Counterclockwise) => (height, width),
+            ImageOperation::Crop((
+                (start_position_x, start_position_y),
+                (end_position_x, end_position_y),
+            )) => (
+                start_position_x.abs_diff(*end_position_x),
+                start_position_y.abs_diff(*end_position_y),
+            ),
+            ImageOperation::Resize(size) => *size,
+            _ => (width, height),
+        }
+    }
+}
+
+/// Returns operations which produce the same image in fewer steps, so replaying history
+/// is faster and resizes don't lose quality. Consecutive rotations are merged and dropped
+/// when they make a full turn, a crop inside the previous crop is merged into it and
+/// consecutive resizes become a single resize from the image before them.
+pub fn normalize_operations(operations: &[ImageOperation]) -> Vec<ImageOperation> {
+    let mut normalized_operations: Vec<ImageOperation> = Vec::with_capacity(operations.len());
+    for operation in operations {
+        match (normalized_operations.last_mut(), *operation) {
+            (Some(ImageOperation::Rotate(previous_rotation)), ImageOperation::Rotate(rotation)) => {
+                let degrees = (rotation_degrees::to_degrees(previous_rotation)
+                    + rotation_degrees::to_degrees(&rotation))
+                    % 360;
+                match rotation_degrees::from_degrees(degrees) {
+                    Some(PixbufRotation::None) | None => {
+                        normalized_operations.pop();
+                    }
+                    Some(rotation) => *previous_rotation = rotation,
+                }
+            }
+            (_, ImageOperation::Rotate(PixbufRotation::None)) => {}
+            (Some(ImageOperation::Crop(previous_area)), ImageOperation::Crop(area))
+                if lies_inside(area, *previous_area) =>
+            {
+                // the second crop is relative to the area of the first one
+                let ((previous_x, previous_y), _) = ordered_area(*previous_area);
+                let ((start_x, start_y), (end_x, end_y)) = ordered_area(area);
+                *previous_area = (
+                    (previous_x + start_x, previous_y + start_y),
+                    (previous_x + end_x, previous_y + end_y),
+                );
+            }
+            (Some(ImageOperation::Resize(previous_size)), ImageOperation::Resize(size)) => {
+                *previous_size = size;
+            }
+            _ => normalized_operations.push(*operation),
+        }
+    }
+    normalized_operations
+}
+
+/// Returns whether the area, relative to the previous area, lies inside of it.
+fn lies_inside(area: CoordinatesPair, previous_area: CoordinatesPair) -> bool {
+    let ((previous_x, previous_y), (previous_end_x, previous_end_y)) = ordered_area(previous_area);
+    let (_, (end_x, end_y)) = ordered_area(area);
+    end_x <= previous_end_x - previous_x && end_y <= previous_end_y - previous_y
+}
+
+/// Returns area with start position in the top left corner and end position in the bottom right.
+fn ordered_area(((start_x, start_y), (end_x, end_y)): CoordinatesPair) -> CoordinatesPair {
+    (
+        (start_x.min(end_x), start_y.min(end_y)),
+        (start_x.max(end_x), start_y.max(end_y)),
+    )
+}
+
+/// Stores rotation as clockwise degrees, because `PixbufRotation` doesn't implement serde traits.
+pub mod rotation_degrees {
+    use gtk::gdk_pixbuf::PixbufRotation;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn to_degrees(rotation: &PixbufRotation) -> u16 {
+        match rotation {
+            PixbufRotation::Clockwise => 90,
+            PixbufRotation::Upsidedown => 180,
+            PixbufRotation::Counterclockwise => 270,
+            _ => 0,
+        }
+    }
+
+    pub fn from_degrees(degrees: u16) -> Option<PixbufRotation> {
+        match degrees {
+            0 => Some(PixbufRotation::None),
+            90 => Some(PixbufRotation::Clockwise),
+            180 => Some(PixbufRotation::Upsidedown),
+            270 => Some(PixbufRotation::Counterclockwise),
+            _ => None,
+        }
+    }
+
+    pub fn serialize<S: Serializer>(
+        rotation: &PixbufRotation,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u16(to_degrees(rotation))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<PixbufRotation, D::Error> {
+        let degrees = u16::deserialize(deserializer)?;
+        from_degrees(degrees)
+            .ok_or_else(|| de::Error::custom(format!("unsupported rotation: {} degrees", degrees)))
+    }
+}
+
+impl fmt::Display for ImageOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageOperation::Rotate(PixbufRotation::Clockwise) => write!(f, "Rotate 90° CW"),
+            ImageOperation::Rotate(PixbufRotation::Counterclockwise) => {
+                write!(f, "Rotate 90° CCW")
+            }
+            ImageOperation::Rotate(PixbufRotation::Upsidedown) => write!(f, "Rotate 180°"),
+            ImageOperation::Rotate(_) => write!(f, "Rotate"),
+            ImageOperation::Crop((
+                (start_position_x, start_position_y),
+                (end_position_x, end_position_y),
+            )) => write!(
+                f,
+                "Crop {}×{} at {},{}",
+                start_position_x.abs_diff(*end_position_x),
+                start_position_y.abs_diff(*end_position_y),
+                cmp::min(start_position_x, end_position_x),
+                cmp::min(start_position_y, end_position_y)
+            ),
+            ImageOperation::Resize((width, height)) => write!(f, "Resize to {}×{}", width, height),
+            ImageOperation::MakeColorTransparent((red, green, blue), tolerance, feathering) => {
+                write!(
+                    f,
+                    "Make #{:02x}{:02x}{:02x} transparent (tolerance {}, feathering {})",
+                    red, green, blue, tolerance, feathering
+                )
+            }
+            ImageOperation::FlattenOntoColor((red, green, blue)) => {
+                write!(f, "Flatten onto #{:02x}{:02x}{:02x}", red, green, blue)
+            }
+        }
+    }
 }
 
 pub trait ApplyImageOperation {
@@ -36,8 +195,75 @@ impl ApplyImageOperation for &Pixbuf {
             ImageOperation::Resize((width, height)) => {
                 self.scale_simple(*width as i32, *height as i32, InterpType::Bilinear)
             }
+            ImageOperation::MakeColorTransparent(color, tolerance, feathering) => {
+                make_color_transparent(self, *color, *tolerance, *feathering)
+            }
+            ImageOperation::FlattenOntoColor(color) => flatten_onto_color(self, *color),
+        }
+    }
+}
+
+fn make_color_transparent(
+    image_buffer: &Pixbuf,
+    (red, green, blue): Color,
+    tolerance: u8,
+    feathering: u8,
+) -> Option<Pixbuf> {
+    let image_buffer = image_buffer.add_alpha(false, 0, 0, 0)?;
+    let width = image_buffer.width() as usize;
+    let height = image_buffer.height() as usize;
+    let rowstride = image_buffer.rowstride() as usize;
+    let mut pixels = image_buffer.read_pixel_bytes()?.to_vec();
+
+    for row in pixels.chunks_mut(rowstride).take(height) {
+        for pixel in row[..width * 4].chunks_exact_mut(4) {
+            let distance = [
+                pixel[0].abs_diff(red),
+                pixel[1].abs_diff(green),
+                pixel[2].abs_diff(blue),
+            ]
+            .into_iter()
+            .max()
+            .unwrap_or_default();
+            if distance <= tolerance {
+                pixel[3] = 0;
+            } else if distance - tolerance < feathering {
+                pixel[3] =
+                    (pixel[3] as u32 * (distance - tolerance) as u32 / feathering as u32) as u8;
+            }
         }
     }
+
+    Some(Pixbuf::from_bytes(
+        &glib::Bytes::from_owned(pixels),
+        Colorspace::Rgb,
+        true,
+        8,
+        width as i32,
+        height as i32,
+        rowstride as i32,
+    ))
+}
+
+fn flatten_onto_color(image_buffer: &Pixbuf, (red, green, blue): Color) -> Option<Pixbuf> {
+    let width = image_buffer.width();
+    let height = image_buffer.height();
+    let flattened_image_buffer = Pixbuf::new(Colorspace::Rgb, false, 8, width, height)?;
+    flattened_image_buffer.fill(u32::from_be_bytes([red, green, blue, 255]));
+    image_buffer.composite(
+        &flattened_image_buffer,
+        0,
+        0,
+        width,
+        height,
+        0.0,
+        0.0,
+        1.0,
+        1.0,
+        InterpType::Nearest,
+        255,
+    );
+    Some(flattened_image_buffer)
 }
 
 #[cfg(test)]
@@ -107,4 +333,103 @@ mod tests {
                 .pixel_bytes()
         );
     }
+
+    #[test]
+    fn test_apply_make_color_transparent_image_operation_on_pixbuf() {
+        let pixbuf = Pixbuf::new(Colorspace::Rgb, false, 8, 10, 10).unwrap();
+        pixbuf.fill(0xffffffff);
+        pixbuf.put_pixel(0, 0, 250, 250, 250, 255);
+        pixbuf.put_pixel(1, 0, 240, 240, 240, 255);
+        pixbuf.put_pixel(2, 0, 0, 0, 0, 255);
+        let image_operation = ImageOperation::MakeColorTransparent((255, 255, 255), 5, 20);
+
+        let transparent_pixbuf = pixbuf.apply_operation(&image_operation).unwrap();
+        let pixels = transparent_pixbuf.read_pixel_bytes().unwrap();
+        let alpha_at = |x: usize| pixels[x * 4 + 3];
+
+        assert!(transparent_pixbuf.has_alpha());
+        assert_eq!(0, alpha_at(0));
+        assert!(alpha_at(1) > 0 && alpha_at(1) < 255);
+        assert_eq!(255, alpha_at(2));
+        assert_eq!(0, alpha_at(3));
+    }
+
+    #[test]
+    fn test_apply_flatten_onto_color_image_operation_on_pixbuf() {
+        let pixbuf = Pixbuf::new(Colorspace::Rgb, true, 8, 10, 10).unwrap();
+        pixbuf.fill(0x00000000);
+        let image_operation = ImageOperation::FlattenOntoColor((255, 0, 0));
+
+        let flattened_pixbuf = pixbuf.apply_operation(&image_operation).unwrap();
+        let pixels = flattened_pixbuf.read_pixel_bytes().unwrap();
+
+        assert!(!flattened_pixbuf.has_alpha());
+        assert_eq!(&[255, 0, 0], &pixels[0..3]);
+    }
+
+    #[test]
+    fn image_operation_descriptions() {
+        assert_eq!(
+            ImageOperation::Crop(((1240, 10), (40, 810))).to_string(),
+            "Crop 1200×800 at 40,10"
+        );
+        assert_eq!(
+            ImageOperation::Rotate(PixbufRotation::Clockwise).to_string(),
+            "Rotate 90° CW"
+        );
+        assert_eq!(
+            ImageOperation::FlattenOntoColor((255, 0, 16)).to_string(),
+            "Flatten onto #ff0010"
+        );
+    }
+
+    #[test]
+    fn operations_are_normalized_for_replay() {
+        let operations = vec![
+            ImageOperation::Rotate(PixbufRotation::Clockwise),
+            ImageOperation::Rotate(PixbufRotation::Upsidedown),
+            ImageOperation::Rotate(PixbufRotation::Clockwise),
+            ImageOperation::Crop(((10, 10), (110, 60))),
+            ImageOperation::Crop(((30, 20), (5, 10))),
+            ImageOperation::Resize((50, 50)),
+            ImageOperation::Resize((20, 40)),
+            ImageOperation::Rotate(PixbufRotation::Counterclockwise),
+            ImageOperation::FlattenOntoColor((0, 0, 0)),
+            ImageOperation::Resize((10, 10)),
+            ImageOperation::Crop(((0, 0), (8, 8))),
+            // doesn't lie inside of the previous crop, so it isn't merged into it
+            ImageOperation::Crop(((4, 4), (12, 8))),
+        ];
+        assert_eq!(
+            format!(
+                "{:?}",
+                vec![
+                    ImageOperation::Crop(((15, 20), (40, 30))),
+                    ImageOperation::Resize((20, 40)),
+                    ImageOperation::Rotate(PixbufRotation::Counterclockwise),
+                    ImageOperation::FlattenOntoColor((0, 0, 0)),
+                    ImageOperation::Resize((10, 10)),
+                    ImageOperation::Crop(((0, 0), (8, 8))),
+                    ImageOperation::Crop(((4, 4), (12, 8))),
+                ]
+            ),
+            format!("{:?}", normalize_operations(&operations))
+        );
+    }
+
+    #[test]
+    fn image_operations_can_be_serialized() {
+        let operations = vec![
+            ImageOperation::Rotate(PixbufRotation::Counterclockwise),
+            ImageOperation::Crop(((10, 20), (30, 40))),
+            ImageOperation::MakeColorTransparent((255, 255, 255), 10, 5),
+        ];
+        let json = serde_json::to_string(&operations).unwrap();
+        let deserialized_operations: Vec<ImageOperation> = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            format!("{:?}", operations),
+            format!("{:?}", deserialized_operations)
+        );
+        assert!(serde_json::from_str::<ImageOperation>(r#"{"Rotate":45}"#).is_err());
+    }
 }
@@ -5,12 +5,24 @@ use gtk::{gio::ApplicationFlags, prelude::*, Application};
 extern crate log;
 
 mod app;
+mod backup;
+mod batch_edit;
+mod collage;
+mod decoder;
+mod encoder;
 mod file_list;
 mod image;
 mod image_list;
 mod image_operation;
+mod recipe;
+mod recovery;
 mod settings;
+mod sidecar;
+mod snapshot_cache;
+mod tiled_preview;
+mod tiles;
 mod ui;
+mod worker;
 
 #[cfg(test)]
 mod test_utils;
@@ -0,0 +1,222 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Result};
+use gtk::gdk_pixbuf::PixbufRotation;
+use serde::{Deserialize, Serialize};
+
+use crate::image_operation::{rotation_degrees, Color, ImageOperation};
+
+const RECIPE_VERSION: u32 = 1;
+
+/// Image operation with positions and sizes stored as fractions of the image size,
+/// so it can be applied to images with different resolutions.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "operation", rename_all = "snake_case")]
+pub enum RecipeStep {
+    Rotate {
+        #[serde(with = "rotation_degrees")]
+        degrees: PixbufRotation,
+    },
+    Crop {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    },
+    Resize {
+        width: f64,
+        height: f64,
+    },
+    MakeColorTransparent {
+        color: Color,
+        tolerance: u8,
+        feathering: u8,
+    },
+    FlattenOntoColor {
+        color: Color,
+    },
+}
+
+/// List of edits which can be saved to a file and replayed on other images.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Recipe {
+    version: u32,
+    steps: Vec<RecipeStep>,
+}
+
+impl Recipe {
+    /// Creates recipe from operations applied to an image with given size.
+    pub fn from_operations(operations: &[ImageOperation], image_size: (u32, u32)) -> Recipe {
+        let mut image_size = image_size;
+        let steps = operations
+            .iter()
+            .map(|operation| {
+                let (width, height) = (image_size.0.max(1) as f64, image_size.1.max(1) as f64);
+                let step = match *operation {
+                    ImageOperation::Rotate(rotation) => RecipeStep::Rotate { degrees: rotation },
+                    ImageOperation::Crop(((start_x, start_y), (end_x, end_y))) => {
+                        RecipeStep::Crop {
+                            x: start_x.min(end_x) as f64 / width,
+                            y: start_y.min(end_y) as f64 / height,
+                            width: start_x.abs_diff(end_x) as f64 / width,
+                            height: start_y.abs_diff(end_y) as f64 / height,
+                        }
+                    }
+                    ImageOperation::Resize((new_width, new_height)) => RecipeStep::Resize {
+                        width: new_width as f64 / width,
+                        height: new_height as f64 / height,
+                    },
+                    ImageOperation::MakeColorTransparent(color, tolerance, feathering) => {
+                        RecipeStep::MakeColorTransparent {
+                            color,
+                            tolerance,
+                            feathering,
+                        }
+                    }
+                    ImageOperation::FlattenOntoColor(color) => {
+                        RecipeStep::FlattenOntoColor { color }
+                    }
+                };
+                image_size = operation.output_size(image_size);
+                step
+            })
+            .collect();
+        Recipe {
+            version: RECIPE_VERSION,
+            steps,
+        }
+    }
+
+    /// Returns operations which apply the recipe to an image with given size.
+    pub fn operations(&self, image_size: (u32, u32)) -> Vec<ImageOperation> {
+        let mut image_size = image_size;
+        self.steps
+            .iter()
+            .map(|step| {
+                let (width, height) = image_size;
+                let scale = |fraction: f64, length: u32| {
+                    ((fraction * length as f64).round().max(0.0) as u32).min(length)
+                };
+                let operation = match *step {
+                    RecipeStep::Rotate { degrees } => ImageOperation::Rotate(degrees),
+                    RecipeStep::Crop {
+                        x,
+                        y,
+                        width: crop_width,
+                        height: crop_height,
+                    } => {
+                        let start_x = scale(x, width).min(width.saturating_sub(1));
+                        let start_y = scale(y, height).min(height.saturating_sub(1));
+                        let end_x = scale(x + crop_width, width).max(start_x + 1);
+                        let end_y = scale(y + crop_height, height).max(start_y + 1);
+                        ImageOperation::Crop(((start_x, start_y), (end_x, end_y)))
+                    }
+                    RecipeStep::Resize {
+                        width: width_scale,
+                        height: height_scale,
+                    } => ImageOperation::Resize((
+                        ((width_scale * width as f64).round() as u32).max(1),
+                        ((height_scale * height as f64).round() as u32).max(1),
+                    )),
+                    RecipeStep::MakeColorTransparent {
+                        color,
+                        tolerance,
+                        feathering,
+                    } => ImageOperation::MakeColorTransparent(color, tolerance, feathering),
+                    RecipeStep::FlattenOntoColor { color } => {
+                        ImageOperation::FlattenOntoColor(color)
+                    }
+                };
+                image_size = operation.output_size(image_size);
+                operation
+            })
+            .collect()
+    }
+
+    pub fn load(path: &Path) -> Result<Recipe> {
+        let recipe: Recipe = serde_json::from_str(&fs::read_to_string(path)?)?;
+        if recipe.version > RECIPE_VERSION {
+            return Err(anyhow!(
+                "Recipe {} was saved by a newer version of Image Roll",
+                path.display()
+            ));
+        }
+        Ok(recipe)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::TestResources;
+
+    use super::*;
+
+    #[test]
+    fn recipe_scales_crop_and_resize_to_image_size() {
+        let recipe = Recipe::from_operations(
+            &[
+                ImageOperation::Resize((50, 100)),
+                ImageOperation::Crop(((5, 10), (30, 35))),
+                ImageOperation::Rotate(PixbufRotation::Clockwise),
+            ],
+            (100, 200),
+        );
+
+        let operations = recipe.operations((200, 400));
+        assert_eq!(
+            format!(
+                "{:?}",
+                vec![
+                    ImageOperation::Resize((100, 200)),
+                    ImageOperation::Crop(((10, 20), (60, 70))),
+                    ImageOperation::Rotate(PixbufRotation::Clockwise),
+                ]
+            ),
+            format!("{:?}", operations)
+        );
+    }
+
+    #[test]
+    fn recipe_crop_stays_inside_image() {
+        let recipe = Recipe::from_operations(&[ImageOperation::Crop(((9, 9), (10, 10)))], (10, 10));
+
+        let operations = recipe.operations((3, 3));
+        assert_eq!(
+            format!("{:?}", vec![ImageOperation::Crop(((2, 2), (3, 3)))]),
+            format!("{:?}", operations)
+        );
+    }
+
+    #[test]
+    fn recipe_can_be_saved_and_loaded() {
+        let test_resources = TestResources::new("test/recipe_can_be_saved_and_loaded");
+        let recipe_path = test_resources.file_folder().join("recipe.json");
+
+        Recipe::from_operations(
+            &[
+                ImageOperation::Rotate(PixbufRotation::Counterclockwise),
+                ImageOperation::FlattenOntoColor((255, 255, 255)),
+            ],
+            (100, 100),
+        )
+        .save(&recipe_path)
+        .unwrap();
+
+        let recipe = Recipe::load(&recipe_path).unwrap();
+        assert_eq!(
+            format!(
+                "{:?}",
+                vec![
+                    ImageOperation::Rotate(PixbufRotation::Counterclockwise),
+                    ImageOperation::FlattenOntoColor((255, 255, 255)),
+                ]
+            ),
+            format!("{:?}", recipe.operations((50, 50)))
+        );
+    }
+}
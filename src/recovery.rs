@@ -0,0 +1,290 @@
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use gtk::glib;
+use serde::{Deserialize, Serialize};
+
+use crate::{image_operation::ImageOperation, sidecar::Sidecar};
+
+/// Seconds between writes of the recovery file, it is rewritten even without changes
+/// so other instances can tell that it is still in use when procfs isn't available.
+pub const AUTOSAVE_INTERVAL: u32 = 10;
+/// Recovery files which weren't rewritten for this long were left by an unclean exit,
+/// used only when running instances can't be looked up in procfs.
+const ABANDONED_AFTER: Duration = Duration::from_secs(3 * AUTOSAVE_INTERVAL as u64);
+const RECOVERY_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecoveredImage {
+    pub path: PathBuf,
+    pub operations: Vec<ImageOperation>,
+    pub current_operation_index: Option<usize>,
+}
+
+/// Edits of all edited images and the open file of a running instance, kept in the state
+/// folder so they can be restored after a crash. Each instance writes its own file
+/// and removes it when its window is closed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Recovery {
+    version: u32,
+    pub current_file: Option<PathBuf>,
+    pub images: Vec<RecoveredImage>,
+}
+
+impl Recovery {
+    pub fn new(current_file: Option<PathBuf>, images: Vec<RecoveredImage>) -> Self {
+        Self {
+            version: RECOVERY_VERSION,
+            current_file,
+            images,
+        }
+    }
+
+    /// Returns folder for recovery files in the XDG state directory.
+    pub fn folder() -> PathBuf {
+        std::env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .filter(|state_folder| state_folder.is_absolute())
+            .unwrap_or_else(|| glib::home_dir().join(".local").join("state"))
+            .join("image-roll")
+    }
+
+    /// Returns a path of recovery file for this instance in the folder.
+    pub fn session_path(folder: &Path) -> PathBuf {
+        let start_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        folder.join(format!(
+            "recovery-{}-{}.json",
+            std::process::id(),
+            start_time
+        ))
+    }
+
+    /// Finds the most recent recovery file left in the folder by an instance which didn't
+    /// exit cleanly. Edits which were written to sidecar files later are left out of it,
+    /// recovery files with no edits left are removed.
+    pub fn find_abandoned(folder: &Path) -> Result<Option<(PathBuf, Recovery)>> {
+        Recovery::find_abandoned_where(folder, |recovery_path, modified| {
+            !is_written_by_running_instance(recovery_path, modified)
+        })
+    }
+
+    fn find_abandoned_where(
+        folder: &Path,
+        is_abandoned: impl Fn(&Path, SystemTime) -> bool,
+    ) -> Result<Option<(PathBuf, Recovery)>> {
+        let entries = match fs::read_dir(folder) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
+        let mut recovery_files = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                let file_name = entry.file_name();
+                let file_name = file_name.to_string_lossy();
+                file_name.starts_with("recovery-") && file_name.ends_with(".json")
+            })
+            .filter_map(|entry| {
+                let modified = entry.metadata().and_then(|metadata| metadata.modified());
+                modified.ok().map(|modified| (modified, entry.path()))
+            })
+            .filter(|(modified, recovery_path)| is_abandoned(recovery_path, *modified))
+            .collect::<Vec<_>>();
+        recovery_files.sort();
+
+        while let Some((modified, recovery_path)) = recovery_files.pop() {
+            let mut recovery = match Recovery::load(&recovery_path) {
+                Ok(recovery) => recovery,
+                Err(error) => {
+                    warn!("Couldn't load {}: {}", recovery_path.display(), error);
+                    continue;
+                }
+            };
+            recovery
+                .images
+                .retain(|image| !is_sidecar_newer(&image.path, modified));
+            if !recovery.images.is_empty() {
+                return Ok(Some((recovery_path, recovery)));
+            }
+            Recovery::remove(&recovery_path)?;
+        }
+        Ok(None)
+    }
+
+    pub fn load(path: &Path) -> Result<Recovery> {
+        let recovery: Recovery = serde_json::from_str(&fs::read_to_string(path)?)?;
+        if recovery.version > RECOVERY_VERSION {
+            return Err(anyhow!(
+                "Recovery file was written by a newer version of Image Roll"
+            ));
+        }
+        let operation_indexes_are_valid = recovery.images.iter().all(|image| {
+            image
+                .current_operation_index
+                .map_or(true, |operation_index| {
+                    operation_index < image.operations.len()
+                })
+        });
+        if !operation_indexes_are_valid {
+            return Err(anyhow!("Recovery file is corrupted"));
+        }
+        Ok(recovery)
+    }
+
+    /// Writes the recovery file, it is removed when there are no edits to recover.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if self.images.is_empty() {
+            return Recovery::remove(path);
+        }
+        if let Some(folder) = path.parent() {
+            fs::create_dir_all(folder)?;
+        }
+        // a crash while writing must not destroy the previous recovery file
+        let temporary_path = path.with_extension("json.tmp");
+        fs::write(&temporary_path, serde_json::to_string(self)?)?;
+        fs::rename(&temporary_path, path)?;
+        Ok(())
+    }
+
+    pub fn remove(path: &Path) -> Result<()> {
+        match fs::remove_file(path) {
+            Ok(()) => Ok(()),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(()),
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+/// Returns whether the instance which wrote the recovery file is still running. Instances
+/// are looked up in procfs by process id from the file name, so a suspended computer
+/// doesn't make their files look abandoned.
+fn is_written_by_running_instance(recovery_path: &Path, modified: SystemTime) -> bool {
+    let process_id = recovery_path
+        .file_name()
+        .and_then(|file_name| file_name.to_str())
+        .and_then(|file_name| file_name.strip_prefix("recovery-"))
+        .and_then(|file_name| file_name.split('-').next())
+        .and_then(|process_id| process_id.parse::<u32>().ok());
+    let process_id = match process_id {
+        // recovery files are looked for before this instance writes its own,
+        // so a file with its process id was left by an earlier process
+        Some(process_id) if process_id != std::process::id() => process_id,
+        _ => return false,
+    };
+    match fs::read_to_string("/proc/self/comm") {
+        // process ids are reused, the process has to be another instance too
+        Ok(process_name) => fs::read_to_string(format!("/proc/{}/comm", process_id))
+            .map_or(false, |other_process_name| {
+                other_process_name == process_name
+            }),
+        Err(_) => SystemTime::now()
+            .duration_since(modified)
+            .map_or(true, |age| age < ABANDONED_AFTER),
+    }
+}
+
+/// Returns whether edits of the image were written to its sidecar file after the time.
+fn is_sidecar_newer(image_path: &Path, time: SystemTime) -> bool {
+    fs::metadata(Sidecar::path(image_path))
+        .and_then(|metadata| metadata.modified())
+        .map_or(false, |modified| modified > time)
+}
+
+#[cfg(test)]
+mod tests {
+    use gtk::gdk_pixbuf::PixbufRotation;
+
+    use crate::test_utils::TestResources;
+
+    use super::*;
+
+    #[test]
+    fn abandoned_recovery_is_found_until_removed() {
+        let test_resources = TestResources::new("test/abandoned_recovery_is_found_until_removed");
+        let folder = test_resources.file_folder().join("state");
+        let recovery_path = Recovery::session_path(&folder);
+        assert!(Recovery::find_abandoned(&folder).unwrap().is_none());
+
+        let recovery = Recovery::new(
+            Some(PathBuf::from("/images/test.png")),
+            vec![RecoveredImage {
+                path: PathBuf::from("/images/test.png"),
+                operations: vec![ImageOperation::Rotate(PixbufRotation::Clockwise)],
+                current_operation_index: Some(0),
+            }],
+        );
+        recovery.save(&recovery_path).unwrap();
+
+        assert!(Recovery::find_abandoned_where(&folder, |_, _| false)
+            .unwrap()
+            .is_none());
+        let (found_path, found_recovery) = Recovery::find_abandoned_where(&folder, |_, _| true)
+            .unwrap()
+            .unwrap();
+        assert_eq!(recovery_path, found_path);
+        assert_eq!(recovery.current_file, found_recovery.current_file);
+        assert_eq!(
+            format!("{:?}", recovery.images),
+            format!("{:?}", found_recovery.images)
+        );
+
+        Recovery::new(None, Vec::new())
+            .save(&recovery_path)
+            .unwrap();
+        assert!(!recovery_path.exists());
+    }
+
+    #[test]
+    fn edits_written_to_sidecar_after_recovery_are_left_out() {
+        let mut test_resources =
+            TestResources::new("test/edits_written_to_sidecar_after_recovery_are_left_out");
+        test_resources.add_file("test.png", "image");
+        let folder = test_resources.file_folder().join("state");
+        let recovery_path = Recovery::session_path(&folder);
+        let image_path = test_resources.file_folder().join("test.png");
+        Recovery::new(
+            None,
+            vec![RecoveredImage {
+                path: image_path.clone(),
+                operations: vec![ImageOperation::Rotate(PixbufRotation::Clockwise)],
+                current_operation_index: Some(0),
+            }],
+        )
+        .save(&recovery_path)
+        .unwrap();
+
+        std::thread::sleep(Duration::from_millis(20));
+        Sidecar::new(
+            vec![ImageOperation::Rotate(PixbufRotation::Counterclockwise)],
+            Some(0),
+        )
+        .save(&image_path)
+        .unwrap();
+        assert!(Recovery::find_abandoned_where(&folder, |_, _| true)
+            .unwrap()
+            .is_none());
+        assert!(!recovery_path.exists());
+    }
+
+    #[test]
+    fn recovery_file_of_exited_process_is_abandoned() {
+        let modified = SystemTime::now();
+        // recovery file of an earlier process with the same id
+        let own_recovery_path = Recovery::session_path(Path::new("/state"));
+        assert!(!is_written_by_running_instance(
+            &own_recovery_path,
+            modified
+        ));
+        // process 0 can't be looked up in procfs
+        let recovery_path = Path::new("/state/recovery-0-1000.json");
+        assert!(!is_written_by_running_instance(recovery_path, modified));
+    }
+}
@@ -1,8 +1,11 @@
+use std::path::PathBuf;
+
 use gtk::gio;
 use gtk::gio::prelude::SettingsExt;
 use gtk::gio::SettingsSchemaSource;
+use gtk::glib::ToVariant;
 
-use crate::image::PreviewSize;
+use crate::{backup::BackupLocation, encoder::EncoderOptions, image::PreviewSize};
 
 #[derive(Clone)]
 pub struct Settings {
@@ -55,6 +58,73 @@ impl Settings {
         }
     }
 
+    /// Returns how many images after and before the current one are decoded in advance.
+    pub fn prefetch_counts(&self) -> (usize, usize) {
+        match self.gio_settings.as_ref() {
+            Some(gio_settings) => (
+                gio_settings.uint("prefetch-next-images") as usize,
+                gio_settings.uint("prefetch-previous-images") as usize,
+            ),
+            None => (2, 1),
+        }
+    }
+
+    /// Returns memory in bytes which the current image and prefetched images can use together.
+    pub fn prefetch_memory_budget(&self) -> usize {
+        let memory_budget_mib = match self.gio_settings.as_ref() {
+            Some(gio_settings) => gio_settings.uint("prefetch-memory-budget"),
+            None => 512,
+        };
+        memory_budget_mib as usize * 1024 * 1024
+    }
+
+    /// Returns memory in bytes which all decoded images can use together.
+    pub fn image_cache_memory_limit(&self) -> usize {
+        let memory_limit_mib = match self.gio_settings.as_ref() {
+            Some(gio_settings) => gio_settings.uint("image-cache-memory-limit"),
+            None => 1024,
+        };
+        memory_limit_mib as usize * 1024 * 1024
+    }
+
+    /// Returns where original files are backed up before they are overwritten,
+    /// `None` when backups are turned off.
+    pub fn backup_location(&self) -> Option<BackupLocation> {
+        let gio_settings = self.gio_settings.as_ref()?;
+        if !gio_settings.boolean("backup-originals") {
+            return None;
+        }
+        let backup_folder = gio_settings.string("backup-folder");
+        if backup_folder.is_empty() {
+            Some(BackupLocation::SameFolder)
+        } else {
+            Some(BackupLocation::Folder(PathBuf::from(
+                backup_folder.as_str(),
+            )))
+        }
+    }
+
+    /// Returns options images were last saved with.
+    pub fn encoder_options(&self) -> EncoderOptions {
+        match self.gio_settings.as_ref() {
+            Some(gio_settings) => EncoderOptions::from_values(
+                gio_settings
+                    .value("encoder-options")
+                    .get()
+                    .unwrap_or_default(),
+            ),
+            None => EncoderOptions::default(),
+        }
+    }
+
+    pub fn set_encoder_options(&self, encoder_options: &EncoderOptions) {
+        if let Some(gio_settings) = self.gio_settings.as_ref() {
+            gio_settings
+                .set_value("encoder-options", &encoder_options.values().to_variant())
+                .expect("Could not set setting encoder-options.");
+        }
+    }
+
     pub fn set_scale(&mut self, preview_size: PreviewSize) {
         self.scale = preview_size;
     }
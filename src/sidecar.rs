@@ -0,0 +1,158 @@
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::image_operation::ImageOperation;
+
+pub const SIDECAR_EXTENSION: &str = "imageroll.json";
+const SIDECAR_VERSION: u32 = 1;
+
+/// Edits of an image stored next to it (`photo.jpg.imageroll.json`), so they can be applied
+/// again when the image is opened without ever touching the original file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Sidecar {
+    version: u32,
+    pub operations: Vec<ImageOperation>,
+    pub current_operation_index: Option<usize>,
+}
+
+impl Sidecar {
+    pub fn new(operations: Vec<ImageOperation>, current_operation_index: Option<usize>) -> Self {
+        Self {
+            version: SIDECAR_VERSION,
+            operations,
+            current_operation_index,
+        }
+    }
+
+    pub fn path(image_path: &Path) -> PathBuf {
+        let mut file_name = image_path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".");
+        file_name.push(SIDECAR_EXTENSION);
+        image_path.with_file_name(file_name)
+    }
+
+    pub fn is_sidecar(path: &Path) -> bool {
+        path.file_name()
+            .and_then(|file_name| file_name.to_str())
+            .map_or(false, |file_name| {
+                file_name.ends_with(&format!(".{}", SIDECAR_EXTENSION))
+            })
+    }
+
+    /// Reads sidecar of the image, returns `None` if the image doesn't have one.
+    pub fn load(image_path: &Path) -> Result<Option<Sidecar>> {
+        let contents = match fs::read_to_string(Sidecar::path(image_path)) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
+        let sidecar: Sidecar = serde_json::from_str(&contents)?;
+        if sidecar.version > SIDECAR_VERSION {
+            return Err(anyhow!(
+                "Edits of {} were saved by a newer version of Image Roll",
+                image_path.display()
+            ));
+        }
+        let current_operation_index_is_valid = sidecar
+            .current_operation_index
+            .map_or(true, |operation_index| {
+                operation_index < sidecar.operations.len()
+            });
+        if !current_operation_index_is_valid {
+            return Err(anyhow!("Edits of {} are corrupted", image_path.display()));
+        }
+        Ok(Some(sidecar))
+    }
+
+    /// Writes sidecar of the image, images without operations don't need one so it is removed.
+    pub fn save(&self, image_path: &Path) -> Result<()> {
+        if self.operations.is_empty() {
+            return Sidecar::remove(image_path);
+        }
+        fs::write(
+            Sidecar::path(image_path),
+            serde_json::to_string_pretty(self)?,
+        )?;
+        Ok(())
+    }
+
+    pub fn remove(image_path: &Path) -> Result<()> {
+        match fs::remove_file(Sidecar::path(image_path)) {
+            Err(error) if error.kind() != ErrorKind::NotFound => Err(error.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gtk::gdk_pixbuf::PixbufRotation;
+
+    use crate::test_utils::TestResources;
+
+    use super::*;
+
+    #[test]
+    fn sidecar_path_appends_extension_to_file_name() {
+        assert_eq!(
+            PathBuf::from("/photos/photo.jpg.imageroll.json"),
+            Sidecar::path(Path::new("/photos/photo.jpg"))
+        );
+        assert!(Sidecar::is_sidecar(&Sidecar::path(Path::new(
+            "/photos/photo.jpg"
+        ))));
+        assert!(!Sidecar::is_sidecar(Path::new("/photos/photo.jpg")));
+    }
+
+    #[test]
+    fn sidecar_can_be_saved_and_loaded() {
+        let test_resources = TestResources::new("test/sidecar_can_be_saved_and_loaded");
+        let image_path = test_resources.file_folder().join("test.png");
+
+        assert!(Sidecar::load(&image_path).unwrap().is_none());
+
+        Sidecar::new(
+            vec![
+                ImageOperation::Rotate(PixbufRotation::Clockwise),
+                ImageOperation::Resize((10, 20)),
+            ],
+            Some(0),
+        )
+        .save(&image_path)
+        .unwrap();
+        let sidecar = Sidecar::load(&image_path).unwrap().unwrap();
+        assert_eq!(2, sidecar.operations.len());
+        assert_eq!(Some(0), sidecar.current_operation_index);
+    }
+
+    #[test]
+    fn saving_sidecar_without_operations_removes_it() {
+        let test_resources = TestResources::new("test/saving_sidecar_without_operations");
+        let image_path = test_resources.file_folder().join("test.png");
+
+        Sidecar::new(vec![ImageOperation::Resize((10, 20))], Some(0))
+            .save(&image_path)
+            .unwrap();
+        assert!(Sidecar::path(&image_path).exists());
+
+        Sidecar::new(Vec::new(), None).save(&image_path).unwrap();
+        assert!(!Sidecar::path(&image_path).exists());
+    }
+
+    #[test]
+    fn loading_sidecar_with_invalid_operation_index_fails() {
+        let mut test_resources = TestResources::new("test/loading_sidecar_with_invalid_index");
+        test_resources.add_file(
+            "test.png.imageroll.json",
+            br#"{"version":1,"operations":[],"current_operation_index":3}"#,
+        );
+
+        assert!(Sidecar::load(&test_resources.file_folder().join("test.png")).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use gtk::gdk_pixbuf::Pixbuf;
+
+/// Memory shared by snapshot caches of all images, so keeping several edited images
+/// doesn't multiply memory taken by snapshots.
+pub struct MemoryBudget {
+    limit: usize,
+    usage: AtomicUsize,
+}
+
+impl MemoryBudget {
+    pub const fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            usage: AtomicUsize::new(0),
+        }
+    }
+
+    /// Reserves memory if it fits within the limit.
+    fn reserve(&self, bytes: usize) -> bool {
+        self.usage
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |usage| {
+                usage
+                    .checked_add(bytes)
+                    .filter(|usage| *usage <= self.limit)
+            })
+            .is_ok()
+    }
+
+    fn release(&self, bytes: usize) {
+        self.usage.fetch_sub(bytes, Ordering::SeqCst);
+    }
+}
+
+/// Keeps image buffers for every `interval` applied operations, so replaying history can start
+/// from the nearest snapshot instead of the original image. Oldest snapshots of the cache are
+/// dropped when there is no memory left in the budget, and new ones aren't kept when
+/// the budget is taken by snapshots of other images.
+pub struct SnapshotCache {
+    snapshots: BTreeMap<usize, Pixbuf>,
+    interval: usize,
+    memory_budget: &'static MemoryBudget,
+}
+
+impl SnapshotCache {
+    pub fn new(interval: usize, memory_budget: &'static MemoryBudget) -> Self {
+        Self {
+            snapshots: BTreeMap::new(),
+            interval: interval.max(1),
+            memory_budget,
+        }
+    }
+
+    /// Returns the snapshot with the most applied operations which doesn't exceed given count.
+    pub fn nearest(&self, applied_operations: usize) -> Option<(usize, &Pixbuf)> {
+        self.snapshots
+            .range(..=applied_operations)
+            .next_back()
+            .map(|(applied_operations, image_buffer)| (*applied_operations, image_buffer))
+    }
+
+    pub fn insert(&mut self, applied_operations: usize, image_buffer: &Pixbuf) {
+        if applied_operations == 0
+            || applied_operations % self.interval != 0
+            || self.snapshots.contains_key(&applied_operations)
+            || image_buffer.byte_length() > self.memory_budget.limit
+        {
+            return;
+        }
+        while !self.memory_budget.reserve(image_buffer.byte_length()) {
+            match self.snapshots.keys().next().copied() {
+                Some(oldest_snapshot) => self.remove(oldest_snapshot),
+                None => return,
+            }
+        }
+        self.snapshots
+            .insert(applied_operations, image_buffer.clone());
+    }
+
+    /// Removes snapshots with more applied operations than given count.
+    pub fn invalidate_after(&mut self, applied_operations: usize) {
+        let invalidated_snapshots = self.snapshots.split_off(&(applied_operations + 1));
+        self.memory_budget.release(
+            invalidated_snapshots
+                .values()
+                .map(|image_buffer| image_buffer.byte_length())
+                .sum(),
+        );
+    }
+
+    pub fn clear(&mut self) {
+        self.memory_budget.release(self.memory_usage());
+        self.snapshots.clear();
+    }
+
+    fn remove(&mut self, applied_operations: usize) {
+        if let Some(image_buffer) = self.snapshots.remove(&applied_operations) {
+            self.memory_budget.release(image_buffer.byte_length());
+        }
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.snapshots
+            .values()
+            .map(|image_buffer| image_buffer.byte_length())
+            .sum()
+    }
+}
+
+impl Drop for SnapshotCache {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gtk::gdk_pixbuf::Colorspace;
+
+    use super::*;
+
+    fn image_buffer() -> Pixbuf {
+        Pixbuf::new(Colorspace::Rgb, false, 8, 10, 10).unwrap()
+    }
+
+    fn memory_budget(limit: usize) -> &'static MemoryBudget {
+        Box::leak(Box::new(MemoryBudget::new(limit)))
+    }
+
+    #[test]
+    fn insert_keeps_snapshots_only_at_interval() {
+        let mut snapshot_cache = SnapshotCache::new(4, memory_budget(usize::MAX));
+        for applied_operations in 0..10 {
+            snapshot_cache.insert(applied_operations, &image_buffer());
+        }
+
+        assert_eq!(
+            vec![4, 8],
+            snapshot_cache.snapshots.keys().copied().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn nearest_returns_closest_preceding_snapshot() {
+        let mut snapshot_cache = SnapshotCache::new(4, memory_budget(usize::MAX));
+        snapshot_cache.insert(4, &image_buffer());
+        snapshot_cache.insert(8, &image_buffer());
+
+        assert!(snapshot_cache.nearest(3).is_none());
+        assert_eq!(4, snapshot_cache.nearest(7).unwrap().0);
+        assert_eq!(8, snapshot_cache.nearest(8).unwrap().0);
+        assert_eq!(8, snapshot_cache.nearest(100).unwrap().0);
+    }
+
+    #[test]
+    fn insert_removes_oldest_snapshots_over_memory_budget() {
+        let image_buffer = image_buffer();
+        let mut snapshot_cache =
+            SnapshotCache::new(1, memory_budget(image_buffer.byte_length() * 2));
+        for applied_operations in 1..=4 {
+            snapshot_cache.insert(applied_operations, &image_buffer);
+        }
+
+        assert_eq!(
+            vec![3, 4],
+            snapshot_cache.snapshots.keys().copied().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn memory_budget_is_shared_between_caches() {
+        let image_buffer = image_buffer();
+        let memory_budget = memory_budget(image_buffer.byte_length() * 3);
+        let mut first_snapshot_cache = SnapshotCache::new(1, memory_budget);
+        let mut second_snapshot_cache = SnapshotCache::new(1, memory_budget);
+        for applied_operations in 1..=2 {
+            first_snapshot_cache.insert(applied_operations, &image_buffer);
+        }
+        for applied_operations in 1..=2 {
+            second_snapshot_cache.insert(applied_operations, &image_buffer);
+        }
+
+        assert_eq!(2, first_snapshot_cache.snapshots.len());
+        assert_eq!(
+            vec![2],
+            second_snapshot_cache
+                .snapshots
+                .keys()
+                .copied()
+                .collect::<Vec<_>>()
+        );
+
+        drop(first_snapshot_cache);
+        second_snapshot_cache.insert(3, &image_buffer);
+        assert_eq!(2, second_snapshot_cache.snapshots.len());
+        assert_eq!(
+            image_buffer.byte_length() * 2,
+            memory_budget.usage.load(Ordering::SeqCst)
+        );
+    }
+
+    #[test]
+    fn invalidate_after_removes_newer_snapshots() {
+        let mut snapshot_cache = SnapshotCache::new(2, memory_budget(usize::MAX));
+        for applied_operations in 1..=6 {
+            snapshot_cache.insert(applied_operations, &image_buffer());
+        }
+        snapshot_cache.invalidate_after(4);
+
+        assert_eq!(
+            vec![2, 4],
+            snapshot_cache.snapshots.keys().copied().collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::{HashMap, VecDeque};
+
+use gtk::gdk_pixbuf::{Colorspace, InterpType, Pixbuf};
+
+const TILE_SIZE: u32 = 256;
+/// Previews with more pixels than this are rendered tile by tile.
+const MAX_PREVIEW_PIXELS: u64 = 4096 * 4096;
+const TILE_CACHE_MEMORY_BUDGET: usize = 128 * 1024 * 1024;
+
+type TilePosition = (u32, u32);
+
+/// Preview of a large image at high zoom. Only tiles of the visible part are rendered,
+/// from a mipmap pyramid level close to the zoom, so memory stays bounded at any zoom.
+pub struct TiledPreview {
+    /// Level 0 is the image itself, each following level has half of its size.
+    mipmaps: Vec<Pixbuf>,
+    size: (u32, u32),
+    tiles: HashMap<TilePosition, Pixbuf>,
+    /// Positions of rendered tiles ordered from the least to the most recently used.
+    recently_used_tiles: VecDeque<TilePosition>,
+}
+
+impl TiledPreview {
+    /// Returns true when a preview with given size is too large to be created in one piece.
+    pub fn is_needed(preview_size: (u32, u32)) -> bool {
+        preview_size.0 as u64 * preview_size.1 as u64 > MAX_PREVIEW_PIXELS
+    }
+
+    pub fn new(image_buffer: &Pixbuf, size: (u32, u32)) -> Self {
+        Self {
+            mipmaps: vec![image_buffer.clone()],
+            size,
+            tiles: HashMap::new(),
+            recently_used_tiles: VecDeque::new(),
+        }
+    }
+
+    /// Returns true if the preview was created from given image buffer.
+    pub fn is_preview_of(&self, image_buffer: &Pixbuf) -> bool {
+        &self.mipmaps[0] == image_buffer
+    }
+
+    /// Changes zoom of the preview, mipmaps are kept and tiles are rendered again.
+    pub fn set_size(&mut self, size: (u32, u32)) {
+        if self.size != size {
+            self.size = size;
+            self.tiles.clear();
+            self.recently_used_tiles.clear();
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.mipmaps
+            .iter()
+            .skip(1)
+            .chain(self.tiles.values())
+            .map(|image_buffer| image_buffer.byte_length())
+            .sum()
+    }
+
+    /// Returns tiles overlapping the visible area given as `(x, y, width, height)` in preview
+    /// coordinates, together with their positions. Missing tiles are rendered.
+    pub fn visible_tiles(
+        &mut self,
+        visible_area: (f64, f64, f64, f64),
+    ) -> Vec<((f64, f64), Pixbuf)> {
+        let (x, y, width, height) = visible_area;
+        let column_count = (self.size.0 + TILE_SIZE - 1) / TILE_SIZE;
+        let row_count = (self.size.1 + TILE_SIZE - 1) / TILE_SIZE;
+        if column_count == 0 || row_count == 0 {
+            return Vec::new();
+        }
+        let tile_index = |coordinate: f64, count: u32| {
+            ((coordinate.max(0.0) / TILE_SIZE as f64) as u32).min(count - 1)
+        };
+
+        let mut visible_tiles = Vec::new();
+        for row in tile_index(y, row_count)..=tile_index(y + height, row_count) {
+            for column in tile_index(x, column_count)..=tile_index(x + width, column_count) {
+                if let Some(tile) = self.tile((column, row)) {
+                    visible_tiles.push((
+                        ((column * TILE_SIZE) as f64, (row * TILE_SIZE) as f64),
+                        tile,
+                    ));
+                }
+            }
+        }
+        visible_tiles
+    }
+
+    fn tile(&mut self, tile_position: TilePosition) -> Option<Pixbuf> {
+        if let Some(tile) = self.tiles.get(&tile_position).cloned() {
+            self.recently_used_tiles
+                .retain(|recently_used_tile| *recently_used_tile != tile_position);
+            self.recently_used_tiles.push_back(tile_position);
+            return Some(tile);
+        }
+        let tile = self.render_tile(tile_position)?;
+        let max_tile_count = TILE_CACHE_MEMORY_BUDGET / tile.byte_length().max(1);
+        while self.tiles.len() >= max_tile_count.max(1) {
+            match self.recently_used_tiles.pop_front() {
+                Some(evicted_tile) => self.tiles.remove(&evicted_tile),
+                None => break,
+            };
+        }
+        self.tiles.insert(tile_position, tile.clone());
+        self.recently_used_tiles.push_back(tile_position);
+        Some(tile)
+    }
+
+    fn render_tile(&mut self, tile_position: TilePosition) -> Option<Pixbuf> {
+        let (column, row) = tile_position;
+        let (tile_x, tile_y) = (column * TILE_SIZE, row * TILE_SIZE);
+        let tile_width = TILE_SIZE.min(self.size.0.checked_sub(tile_x)?);
+        let tile_height = TILE_SIZE.min(self.size.1.checked_sub(tile_y)?);
+        if tile_width == 0 || tile_height == 0 {
+            return None;
+        }
+
+        let source = self.mipmap_level();
+        let scale_x = self.size.0 as f64 / source.width() as f64;
+        let scale_y = self.size.1 as f64 / source.height() as f64;
+        let tile = Pixbuf::new(
+            Colorspace::Rgb,
+            source.has_alpha(),
+            8,
+            tile_width as i32,
+            tile_height as i32,
+        )?;
+        // enlarged pixels stay sharp, reduced ones are filtered
+        let interp_type = if scale_x >= 1.0 {
+            InterpType::Nearest
+        } else {
+            InterpType::Bilinear
+        };
+        source.scale(
+            &tile,
+            0,
+            0,
+            tile_width as i32,
+            tile_height as i32,
+            -(tile_x as f64),
+            -(tile_y as f64),
+            scale_x,
+            scale_y,
+            interp_type,
+        );
+        Some(tile)
+    }
+
+    /// Returns the smallest mipmap level which is still at least as large as the preview,
+    /// levels are created when they are needed for the first time.
+    fn mipmap_level(&mut self) -> Pixbuf {
+        loop {
+            let level = self.mipmaps.last().unwrap().clone();
+            let (half_width, half_height) = (level.width() / 2, level.height() / 2);
+            if (half_width as u32) < self.size.0 || half_height < 1 {
+                return self
+                    .mipmaps
+                    .iter()
+                    .rev()
+                    .find(|level| level.width() as u32 >= self.size.0)
+                    .unwrap_or(&self.mipmaps[0])
+                    .clone();
+            }
+            match level.scale_simple(half_width, half_height, InterpType::Bilinear) {
+                Some(next_level) => self.mipmaps.push(next_level),
+                None => return level,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_large_previews_are_tiled() {
+        assert!(!TiledPreview::is_needed((4096, 4096)));
+        assert!(TiledPreview::is_needed((8000, 8000)));
+    }
+
+    #[test]
+    fn visible_tiles_cover_visible_area_only() {
+        let image_buffer = Pixbuf::new(Colorspace::Rgb, false, 8, 100, 100).unwrap();
+        image_buffer.fill(0xff0000ff);
+        let mut tiled_preview = TiledPreview::new(&image_buffer, (1000, 1000));
+
+        let visible_tiles = tiled_preview.visible_tiles((300.0, 0.0, 300.0, 100.0));
+        assert_eq!(
+            vec![(256.0, 0.0), (512.0, 0.0)],
+            visible_tiles
+                .iter()
+                .map(|(position, _)| *position)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(256, visible_tiles[0].1.width());
+        assert_eq!(
+            &[0xff, 0x00, 0x00],
+            &visible_tiles[0].1.read_pixel_bytes().unwrap()[..3]
+        );
+
+        let last_tiles = tiled_preview.visible_tiles((900.0, 900.0, 500.0, 500.0));
+        assert_eq!(1, last_tiles.len());
+        assert_eq!(
+            (1000 - 3 * 256, 1000 - 3 * 256),
+            (
+                last_tiles[0].1.width() as u32,
+                last_tiles[0].1.height() as u32
+            )
+        );
+    }
+
+    #[test]
+    fn reduced_preview_is_rendered_from_mipmap() {
+        let image_buffer = Pixbuf::new(Colorspace::Rgb, false, 8, 1024, 1024).unwrap();
+        let mut tiled_preview = TiledPreview::new(&image_buffer, (200, 200));
+
+        tiled_preview.visible_tiles((0.0, 0.0, 200.0, 200.0));
+        assert_eq!(256, tiled_preview.mipmaps.last().unwrap().width());
+        assert!(tiled_preview.is_preview_of(&image_buffer));
+
+        tiled_preview.set_size((100, 100));
+        assert!(tiled_preview.tiles.is_empty());
+    }
+}
@@ -0,0 +1,189 @@
+use anyhow::{anyhow, Result};
+
+#[derive(Clone, Copy, Debug)]
+pub enum TileLayout {
+    Grid {
+        rows: u32,
+        columns: u32,
+        overlap: u32,
+    },
+    Size {
+        width: u32,
+        height: u32,
+        overlap: u32,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub row: u32,
+    pub column: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl TileLayout {
+    /// Returns tiles covering the image in row-major order.
+    pub fn tiles(&self, image_size: (u32, u32)) -> Result<Vec<Tile>> {
+        let (image_width, image_height) = image_size;
+        let (column_spans, row_spans) = match *self {
+            TileLayout::Grid {
+                rows,
+                columns,
+                overlap,
+            } => (
+                grid_spans(image_width, columns, overlap)?,
+                grid_spans(image_height, rows, overlap)?,
+            ),
+            TileLayout::Size {
+                width,
+                height,
+                overlap,
+            } => (
+                size_spans(image_width, width, overlap)?,
+                size_spans(image_height, height, overlap)?,
+            ),
+        };
+
+        Ok(row_spans
+            .iter()
+            .enumerate()
+            .flat_map(|(row, &(y, height))| {
+                column_spans
+                    .iter()
+                    .enumerate()
+                    .map(move |(column, &(x, width))| Tile {
+                        row: row as u32,
+                        column: column as u32,
+                        x,
+                        y,
+                        width,
+                        height,
+                    })
+            })
+            .collect())
+    }
+}
+
+fn grid_spans(length: u32, count: u32, overlap: u32) -> Result<Vec<(u32, u32)>> {
+    if count == 0 || count > length {
+        return Err(anyhow!(
+            "Image with size {} can't be split into {} tiles",
+            length,
+            count
+        ));
+    }
+    // remainder of the division is spread over the tiles, so there are always `count` of them
+    let boundary = |index: u32| (index as u64 * length as u64 / count as u64) as u32;
+    Ok((0..count)
+        .map(|index| {
+            let start = boundary(index).saturating_sub(overlap);
+            let end = (boundary(index + 1) + overlap).min(length);
+            (start, end - start)
+        })
+        .collect())
+}
+
+fn size_spans(length: u32, tile_length: u32, overlap: u32) -> Result<Vec<(u32, u32)>> {
+    if tile_length == 0 || overlap >= tile_length {
+        return Err(anyhow!("Tile overlap must be smaller than tile size"));
+    }
+    let step = tile_length - overlap;
+    let mut spans = Vec::new();
+    let mut start = 0;
+    loop {
+        let end = (start + tile_length).min(length);
+        spans.push((start, end - start));
+        if end == length {
+            return Ok(spans);
+        }
+        start += step;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_spans_cover_whole_length() {
+        assert_eq!(
+            vec![(0, 33), (33, 33), (66, 34)],
+            grid_spans(100, 3, 0).unwrap()
+        );
+        assert_eq!(
+            vec![(0, 1), (1, 1), (2, 1), (3, 2)],
+            grid_spans(5, 4, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn grid_spans_with_overlap() {
+        assert_eq!(vec![(0, 55), (45, 55)], grid_spans(100, 2, 5).unwrap());
+    }
+
+    #[test]
+    fn grid_spans_fail_for_too_many_tiles() {
+        assert!(grid_spans(2, 3, 0).is_err());
+        assert!(grid_spans(2, 0, 0).is_err());
+    }
+
+    #[test]
+    fn size_spans_cover_whole_length() {
+        assert_eq!(
+            vec![(0, 40), (40, 40), (80, 20)],
+            size_spans(100, 40, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn size_spans_with_overlap() {
+        assert_eq!(
+            vec![(0, 40), (30, 40), (60, 40)],
+            size_spans(100, 40, 10).unwrap()
+        );
+    }
+
+    #[test]
+    fn size_spans_fail_when_overlap_is_not_smaller_than_tile() {
+        assert!(size_spans(100, 40, 40).is_err());
+        assert!(size_spans(100, 0, 0).is_err());
+    }
+
+    #[test]
+    fn tiles_are_in_row_major_order() {
+        let tiles = TileLayout::Size {
+            width: 60,
+            height: 30,
+            overlap: 0,
+        }
+        .tiles((100, 50))
+        .unwrap();
+
+        assert_eq!(4, tiles.len());
+        assert_eq!(
+            Tile {
+                row: 0,
+                column: 1,
+                x: 60,
+                y: 0,
+                width: 40,
+                height: 30
+            },
+            tiles[1]
+        );
+        assert_eq!(
+            Tile {
+                row: 1,
+                column: 0,
+                x: 0,
+                y: 30,
+                width: 60,
+                height: 20
+            },
+            tiles[2]
+        );
+    }
+}
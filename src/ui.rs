@@ -1,4 +1,5 @@
 pub mod action;
+pub mod comparison;
 pub mod controllers;
 pub mod event;
 pub mod widgets;
@@ -1,7 +1,9 @@
 use std::{
     cell::{Cell, RefCell},
-    path::PathBuf,
+    path::{Path, PathBuf},
     rc::Rc,
+    sync::{atomic::AtomicU64, Arc},
+    time::Duration,
 };
 
 #[cfg(feature = "wallpaper")]
@@ -10,30 +12,57 @@ use ashpd::{
     WindowIdentifier,
 };
 use gtk::{
-    gdk, gio,
-    glib::{self, timeout_future_seconds, Sender},
+    gdk,
+    gdk_pixbuf::Pixbuf,
+    gio,
+    glib::{self, timeout_future, timeout_future_seconds, Sender},
+    pango,
     prelude::{
-        DisplayExt, FileMonitorExt, GdkCairoContextExt, GtkApplicationExt, GtkWindowExt,
-        PrintOperationExt, ToggleButtonExt, WidgetExt,
+        BoxExt, Cast, CheckButtonExt, ColorChooserExt, ComboBoxExt, DialogExt, DialogExtManual,
+        DisplayExt, FileExt, FileMonitorExt, GdkCairoContextExt, GridExt, GtkApplicationExt,
+        GtkWindowExt, PrintOperationExt, ToggleButtonExt, WidgetExt,
     },
-    traits::DrawingAreaExt,
+    traits::{ButtonExt, DrawingAreaExt},
     MessageType,
 };
 
 use crate::{
+    batch_edit::{BatchEditJob, BatchEditOptions, BatchEditProgress, BatchEditSource},
+    collage::{self, CollageLayout},
+    decoder,
+    encoder::{self, encoders, Encoder, EncoderOptionKind, EncoderOptions},
     file_list::FileList,
-    image::{self, CoordinatesPair, PreviewSize},
-    image_list::ImageList,
+    image::{
+        self, CoordinatesPair, DecodedImage, DecodedProxy, Image, OperationTag, PreviewSize, Replay,
+    },
+    image_list::{ImageList, ImageSave},
     image_operation::{ApplyImageOperation, ImageOperation},
+    recipe::Recipe,
+    recovery::Recovery,
     settings::Settings,
+    sidecar::Sidecar,
+    tiled_preview::TiledPreview,
+    tiles::TileLayout,
+    worker::{BackgroundJobs, Generation, SendableImageBuffer, WorkerPool},
 };
 
 use super::{
-    event::{post_event, Event},
+    comparison::Comparison,
+    event::{
+        post_event, Event, ExternalModificationResponse, UnsavedEditsAction, UnsavedEditsResponse,
+    },
     widgets::Widgets,
 };
 
-pub fn refresh_file_list(sender: &Sender<Event>, file_list: &mut FileList) {
+const ZOOM_SETTLE_DELAY: Duration = Duration::from_millis(200);
+
+pub fn refresh_file_list(
+    sender: &Sender<Event>,
+    widgets: &Widgets,
+    image_list: Rc<RefCell<ImageList>>,
+    file_list: &mut FileList,
+    external_modification_prompted: &mut bool,
+) {
     post_event(sender, Event::HideInfoPanel);
     if let Err(error) = file_list.refresh() {
         post_event(
@@ -43,17 +72,62 @@ pub fn refresh_file_list(sender: &Sender<Event>, file_list: &mut FileList) {
         return;
     };
 
-    post_event(sender, Event::LoadImage(file_list.current_file_path()));
+    let modified_image_path = image_list
+        .borrow()
+        .current_image_path()
+        .filter(|image_path| {
+            image_list
+                .borrow()
+                .current_image()
+                .map_or(false, |image| image.is_modified_externally(image_path))
+        });
+    if let Some(modified_image_path) = modified_image_path {
+        let image_has_operations = image_list
+            .borrow()
+            .current_image()
+            .map_or(false, Image::has_operations);
+        if image_has_operations {
+            ask_about_external_modification(
+                sender,
+                widgets,
+                external_modification_prompted,
+                modified_image_path,
+            );
+            return;
+        }
+        // there are no edits to lose, the changed file is simply decoded again
+        if let Some(image) = image_list.borrow_mut().get_mut(&modified_image_path) {
+            image.remove_image_buffers();
+        }
+    }
+
+    let current_image_is_unsaved = image_list
+        .borrow()
+        .current_image()
+        .map_or(false, |current_image| current_image.is_unsaved());
+    if !current_image_is_unsaved {
+        post_event(sender, Event::LoadImage(file_list.current_file_path()));
+    }
 }
 
 pub fn open_file(
     sender: &Sender<Event>,
+    widgets: &Widgets,
     image_list: Rc<RefCell<ImageList>>,
     file_list: &mut FileList,
     file: gio::File,
 ) {
+    let edited_image_paths = image_list.borrow().edited_image_paths();
+    if !confirm_unsaved_edits(
+        sender,
+        widgets,
+        edited_image_paths,
+        UnsavedEditsAction::OpenFile(file.clone()),
+    ) {
+        return;
+    }
     post_event(sender, Event::HideInfoPanel);
-    image_list.replace(ImageList::new());
+    image_list.borrow_mut().clear();
 
     let new_file_list = match FileList::new(Some(file)) {
         Ok(file_list) => file_list,
@@ -74,7 +148,11 @@ pub fn open_file(
     file_list
         .current_folder_monitor_mut()
         .unwrap()
-        .connect_changed(move |_, _, _, _| {
+        .connect_changed(move |_, file, _, _| {
+            // sidecars are written on every edit and don't change the list of images
+            if file.path().map_or(false, |path| Sidecar::is_sidecar(&path)) {
+                return;
+            }
             post_event(&sender, Event::RefreshFileList);
         });
 }
@@ -84,109 +162,430 @@ pub fn load_image(
     settings: &mut Settings,
     widgets: &Widgets,
     image_list: Rc<RefCell<ImageList>>,
+    background_jobs: &mut BackgroundJobs,
     file_path: Option<PathBuf>,
 ) {
     hide_info_panel(widgets);
-    let mut image_list = image_list.borrow_mut();
     if let Some(file_path) = file_path {
-        let image = if let Some(image) = image_list.remove(&file_path) {
-            image.reload(&file_path)
-        } else {
-            image::Image::load(&file_path)
+        let image_is_decoded = image_list
+            .borrow()
+            .get(&file_path)
+            .map_or(false, |image| image.has_image_buffers());
+        if image_is_decoded {
+            // prefetched or still kept since it was shown last time
+            background_jobs.cancel_loads();
+            show_loaded_image(
+                sender,
+                settings,
+                widgets,
+                &mut image_list.borrow_mut(),
+                file_path,
+            );
+            return;
+        }
+        let operations = image_list
+            .borrow()
+            .get(&file_path)
+            .map(|image| (image.operations().to_vec(), image.current_operation_index()));
+        // a viewport sized proxy is shown first when the image is fit to the screen
+        let proxy_size = match settings.scale() {
+            PreviewSize::BestFit(_, _) => {
+                let viewport_allocation = widgets.image_viewport().allocation();
+                Some((
+                    viewport_allocation.width() as u32,
+                    viewport_allocation.height() as u32,
+                ))
+                .filter(|(width, height)| *width > 0 && *height > 0)
+            }
+            _ => None,
         };
-        let image = match image {
-            Ok(image) => image,
-            Err(error) => {
-                image_list.set_current_image_path(None);
-                post_event(sender, Event::RefreshPreview(settings.scale()));
-                post_event(
-                    sender,
-                    Event::DisplayMessage(error.to_string(), MessageType::Error),
-                );
+        let generation = background_jobs.start_load();
+        let sender = sender.clone();
+        background_jobs.execute(move || {
+            if generation.is_stale() {
                 return;
             }
-        };
-        image_list.insert(file_path.clone(), image);
-        widgets.window().set_title(
-            file_path
-                .file_name()
-                .and_then(|file_name| file_name.to_str()),
-        );
-        image_list.set_current_image_path(Some(file_path));
-        if let PreviewSize::BestFit(0, 0) = settings.scale() {
-            let new_scale = PreviewSize::BestFit(
-                widgets.image_viewport().allocation().width() as u32,
-                widgets.image_viewport().allocation().height() as u32,
+            // errors are reported when the full image is decoded
+            let decoded_proxy = proxy_size.and_then(|proxy_size| {
+                DecodedProxy::decode(&file_path, operations.clone(), proxy_size)
+                    .ok()
+                    .flatten()
+            });
+            let operations = match decoded_proxy {
+                Some(decoded_proxy) => {
+                    let operations = decoded_proxy.operations();
+                    post_event(
+                        &sender,
+                        Event::ImageProxyLoaded(
+                            generation.value(),
+                            file_path.clone(),
+                            decoded_proxy,
+                        ),
+                    );
+                    if generation.is_stale() {
+                        return;
+                    }
+                    Some(operations)
+                }
+                None => operations,
+            };
+            let decoded_image =
+                DecodedImage::decode(&file_path, operations).map_err(|error| error.to_string());
+            post_event(
+                &sender,
+                Event::ImageLoaded(generation.value(), file_path, decoded_image),
             );
-            settings.set_scale(new_scale);
-        }
-        post_event(sender, Event::RefreshPreview(settings.scale()));
+        });
     } else {
-        widgets.window().set_title(Some("Image Roll"));
-        image_list.set_current_image_path(None);
+        background_jobs.cancel_loads();
+        image_list.borrow_mut().set_current_image_path(None);
         post_event(sender, Event::RefreshPreview(settings.scale()));
+        post_event(sender, Event::RefreshHistoryPanel);
     }
 }
 
-pub fn next_image(
+#[allow(clippy::too_many_arguments)]
+pub fn image_loaded(
     sender: &Sender<Event>,
+    settings: &mut Settings,
+    widgets: &Widgets,
     image_list: Rc<RefCell<ImageList>>,
-    file_list: &mut FileList,
+    background_jobs: &mut BackgroundJobs,
+    generation: u64,
+    file_path: PathBuf,
+    decoded_image: Result<DecodedImage, String>,
 ) {
-    if let Some(current_image) = image_list.borrow_mut().current_image_mut() {
-        current_image.remove_image_buffers();
+    if !background_jobs.finish_load(generation) {
+        return;
+    }
+    let mut image_list = image_list.borrow_mut();
+    match decoded_image {
+        Ok(decoded_image) => {
+            let mut image = Image::from(decoded_image);
+            // proxy preview stays visible until preview of the full image is created
+            if let Some(preview_image_buffer) = image_list
+                .get(&file_path)
+                .filter(|image| image.is_proxy())
+                .and_then(|image| image.preview_image_buffer())
+            {
+                image.set_preview_image_buffer(preview_image_buffer.clone());
+            }
+            image_list.insert(file_path.clone(), image);
+            show_loaded_image(sender, settings, widgets, &mut image_list, file_path);
+        }
+        Err(error) => {
+            image_list.remove(&file_path);
+            image_list.set_current_image_path(None);
+            post_event(sender, Event::RefreshPreview(settings.scale()));
+            post_event(sender, Event::RefreshHistoryPanel);
+            post_event(sender, Event::DisplayMessage(error, MessageType::Error));
+        }
     }
-    file_list.next();
-    post_event(sender, Event::LoadImage(file_list.current_file_path()));
 }
 
-pub fn previous_image(
+#[allow(clippy::too_many_arguments)]
+pub fn image_proxy_loaded(
     sender: &Sender<Event>,
+    settings: &mut Settings,
+    widgets: &Widgets,
     image_list: Rc<RefCell<ImageList>>,
-    file_list: &mut FileList,
+    background_jobs: &BackgroundJobs,
+    generation: u64,
+    file_path: PathBuf,
+    decoded_proxy: DecodedProxy,
+) {
+    if !background_jobs.is_current_load(generation) {
+        return;
+    }
+    let mut image_list = image_list.borrow_mut();
+    image_list.insert(file_path.clone(), Image::from(decoded_proxy));
+    show_loaded_image(sender, settings, widgets, &mut image_list, file_path);
+}
+
+fn show_loaded_image(
+    sender: &Sender<Event>,
+    settings: &mut Settings,
+    widgets: &Widgets,
+    image_list: &mut ImageList,
+    file_path: PathBuf,
+) {
+    image_list.set_current_image_path(Some(file_path));
+    if let PreviewSize::BestFit(0, 0) = settings.scale() {
+        let new_scale = PreviewSize::BestFit(
+            widgets.image_viewport().allocation().width() as u32,
+            widgets.image_viewport().allocation().height() as u32,
+        );
+        settings.set_scale(new_scale);
+    }
+    post_event(sender, Event::RefreshPreview(settings.scale()));
+    post_event(sender, Event::RefreshHistoryPanel);
+    post_event(sender, Event::PrefetchImages);
+}
+
+/// Decodes images around the current one on worker threads, nearest first, as long as
+/// they fit in the prefetch memory budget and in memory left by the image cache limit.
+/// Images further away lose their decoded buffers.
+pub fn prefetch_images(
+    sender: &Sender<Event>,
+    settings: &Settings,
+    image_list: Rc<RefCell<ImageList>>,
+    file_list: &FileList,
+    background_jobs: &mut BackgroundJobs,
+) {
+    if background_jobs.is_loading() {
+        return;
+    }
+    let (next_count, previous_count) = settings.prefetch_counts();
+    let neighbour_file_paths = file_list.neighbour_file_paths(next_count, previous_count);
+    let mut image_list = image_list.borrow_mut();
+    image_list.remove_image_buffers_except(&neighbour_file_paths);
+
+    let mut memory_usage = image_list.current_image().map_or(0, Image::memory_usage);
+    // prefetched images have to fit in the memory limit of the list too,
+    // otherwise they would evict each other when they are inserted
+    let mut free_memory = image_list
+        .memory_limit()
+        .saturating_sub(image_list.memory_usage());
+    for file_path in neighbour_file_paths {
+        let image = image_list.get(&file_path);
+        if let Some(image) = image.filter(|image| image.has_image_buffers()) {
+            memory_usage += image.memory_usage();
+            continue;
+        }
+        // size is read from the file header, edited images keep the original buffer too
+        let (width, height) = match decoder::image_size(&file_path) {
+            Some((width, height)) => (width as usize, height as usize),
+            None => continue,
+        };
+        let buffer_count = if image.map_or(false, |image| image.has_operations()) {
+            2
+        } else {
+            1
+        };
+        let image_memory_usage = width * height * 4 * buffer_count;
+        memory_usage += image_memory_usage;
+        if memory_usage > settings.prefetch_memory_budget() || image_memory_usage > free_memory {
+            break;
+        }
+        free_memory -= image_memory_usage;
+        let load_generation = match background_jobs.start_prefetch(&file_path) {
+            Some(load_generation) => load_generation,
+            None => continue,
+        };
+        let operations =
+            image.map(|image| (image.operations().to_vec(), image.current_operation_index()));
+        let sender = sender.clone();
+        background_jobs.execute(move || {
+            // another image was opened before this job started, the user moved on
+            let decoded_image = if load_generation.is_stale() {
+                None
+            } else {
+                DecodedImage::decode(&file_path, operations).ok()
+            };
+            post_event(&sender, Event::ImagePrefetched(file_path, decoded_image));
+        });
+    }
+}
+
+pub fn show_cache_usage(sender: &Sender<Event>, image_list: Rc<RefCell<ImageList>>) {
+    let image_list = image_list.borrow();
+    let to_mib = |bytes: usize| bytes as f64 / (1024.0 * 1024.0);
+    post_event(
+        sender,
+        Event::DisplayMessage(
+            format!(
+                "Decoded images use {:.1} MiB of {:.0} MiB ({} of {} images decoded)",
+                to_mib(image_list.memory_usage()),
+                to_mib(image_list.memory_limit()),
+                image_list.decoded_image_count(),
+                image_list.image_count(),
+            ),
+            MessageType::Info,
+        ),
+    );
+}
+
+pub fn image_prefetched(
+    settings: &Settings,
+    image_list: Rc<RefCell<ImageList>>,
+    file_list: &FileList,
+    background_jobs: &mut BackgroundJobs,
+    file_path: PathBuf,
+    decoded_image: Option<DecodedImage>,
 ) {
-    if let Some(current_image) = image_list.borrow_mut().current_image_mut() {
-        current_image.remove_image_buffers();
+    background_jobs.finish_prefetch(&file_path);
+    // errors are reported when the image is opened
+    let decoded_image = match decoded_image {
+        Some(decoded_image) => decoded_image,
+        None => return,
+    };
+    let (next_count, previous_count) = settings.prefetch_counts();
+    if !file_list
+        .neighbour_file_paths(next_count, previous_count)
+        .contains(&file_path)
+    {
+        return;
     }
+    let mut image_list = image_list.borrow_mut();
+    let image_is_decoded = image_list
+        .get(&file_path)
+        .map_or(false, |image| image.has_image_buffers());
+    if !image_is_decoded {
+        image_list.insert(file_path, Image::from(decoded_image));
+    }
+}
+
+pub fn next_image(sender: &Sender<Event>, file_list: &mut FileList) {
+    file_list.next();
+    post_event(sender, Event::LoadImage(file_list.current_file_path()));
+}
+
+pub fn previous_image(sender: &Sender<Event>, file_list: &mut FileList) {
     file_list.previous();
     post_event(sender, Event::LoadImage(file_list.current_file_path()));
 }
 
 pub fn image_viewport_resize(
     sender: &Sender<Event>,
+    widgets: &Widgets,
     settings: &mut Settings,
+    image_list: Rc<RefCell<ImageList>>,
+    zoom_generation: &mut u64,
     viewport_size: (u32, u32),
 ) {
     if let PreviewSize::BestFit(_, _) = settings.scale() {
         let new_scale = PreviewSize::BestFit(viewport_size.0, viewport_size.1);
         settings.set_scale(new_scale);
-        post_event(sender, Event::RefreshPreview(new_scale));
+        zoom_preview(sender, widgets, image_list, zoom_generation, new_scale);
+    }
+}
+
+/// Draws the existing preview scaled to the new size right away, a new preview is created
+/// only when zooming stops for a moment.
+fn zoom_preview(
+    sender: &Sender<Event>,
+    widgets: &Widgets,
+    image_list: Rc<RefCell<ImageList>>,
+    zoom_generation: &mut u64,
+    preview_size: PreviewSize,
+) {
+    widgets
+        .preview_size_label()
+        .set_text(String::from(preview_size).as_str());
+    let mut image_list = image_list.borrow_mut();
+    if let Some(image) = image_list.current_image_mut() {
+        image.set_display_size(preview_size);
+    }
+    show_preview(widgets, image_list.current_image());
+
+    *zoom_generation += 1;
+    let generation = *zoom_generation;
+    let sender = sender.clone();
+    glib::MainContext::default().spawn_local(async move {
+        timeout_future(ZOOM_SETTLE_DELAY).await;
+        post_event(&sender, Event::ZoomSettled(generation));
+    });
+}
+
+pub fn zoom_settled(
+    sender: &Sender<Event>,
+    settings: &Settings,
+    zoom_generation: u64,
+    generation: u64,
+) {
+    if generation == zoom_generation {
+        post_event(sender, Event::RefreshPreview(settings.scale()));
     }
 }
 
 pub fn refresh_preview(
+    sender: &Sender<Event>,
     widgets: &Widgets,
     image_list: Rc<RefCell<ImageList>>,
+    background_jobs: &BackgroundJobs,
     preview_size: PreviewSize,
 ) {
     widgets
         .preview_size_label()
         .set_text(String::from(preview_size).as_str());
-    if let Some(image) = image_list.borrow_mut().current_image_mut() {
-        image.create_preview_image_buffer(preview_size);
-        if let Some((preview_image_width, preview_image_height)) = image.preview_image_buffer_size()
+    let generation = background_jobs.start_preview();
+    let mut image_list = image_list.borrow_mut();
+    let current_image_path = image_list.current_image_path();
+    if let Some(image) = image_list.current_image_mut() {
+        // scaling large images takes a while, original size preview is just a copy
+        // and tiled previews render only visible tiles
+        let image_size = image.image_size();
+        let image_buffer = match preview_size {
+            PreviewSize::OriginalSize if !image.is_edited_downscaled() => None,
+            _ => image
+                .current_image_buffer()
+                .filter(|_| {
+                    image_size.map_or(false, |image_size| {
+                        !TiledPreview::is_needed(preview_size.fit(image_size))
+                    })
+                })
+                .and_then(SendableImageBuffer::from_image_buffer),
+        };
+        if let (Some(image_buffer), Some(image_size), Some(image_path)) =
+            (image_buffer, image_size, current_image_path)
         {
-            widgets
-                .image_widget()
-                .set_content_width(preview_image_width as i32);
-            widgets
-                .image_widget()
-                .set_content_height(preview_image_height as i32);
+            let sender = sender.clone();
+            background_jobs.execute(move || {
+                if generation.is_stale() {
+                    return;
+                }
+                let preview_image_buffer = image::create_preview_image_buffer(
+                    &image_buffer.into_image_buffer(),
+                    image_size,
+                    preview_size,
+                )
+                .as_ref()
+                .and_then(SendableImageBuffer::from_image_buffer);
+                post_event(
+                    &sender,
+                    Event::PreviewCreated(generation.value(), image_path, preview_image_buffer),
+                );
+            });
+            return;
         }
-    } else {
-        widgets.image_widget().set_content_width(0);
-        widgets.image_widget().set_content_height(0);
+        image.create_preview_image_buffer(preview_size);
+    }
+    show_preview(widgets, image_list.current_image());
+}
+
+pub fn preview_created(
+    widgets: &Widgets,
+    image_list: Rc<RefCell<ImageList>>,
+    background_jobs: &BackgroundJobs,
+    generation: u64,
+    image_path: PathBuf,
+    preview_image_buffer: Option<SendableImageBuffer>,
+) {
+    if !background_jobs.is_current_preview(generation) {
+        return;
     }
+    let mut image_list = image_list.borrow_mut();
+    if image_list.current_image_path().as_ref() != Some(&image_path) {
+        return;
+    }
+    if let (Some(image), Some(preview_image_buffer)) =
+        (image_list.current_image_mut(), preview_image_buffer)
+    {
+        image.set_preview_image_buffer(preview_image_buffer.into_image_buffer());
+    }
+    show_preview(widgets, image_list.current_image());
+}
+
+fn show_preview(widgets: &Widgets, image: Option<&Image>) {
+    let (preview_image_width, preview_image_height) = image
+        .and_then(|image| image.preview_image_buffer_size())
+        .unwrap_or((0, 0));
+    widgets
+        .image_widget()
+        .set_content_width(preview_image_width as i32);
+    widgets
+        .image_widget()
+        .set_content_height(preview_image_height as i32);
     widgets.image_widget().queue_draw();
 }
 
@@ -194,6 +593,8 @@ pub fn change_preview_size(
     sender: &Sender<Event>,
     widgets: &Widgets,
     settings: &mut Settings,
+    image_list: Rc<RefCell<ImageList>>,
+    zoom_generation: &mut u64,
     mut preview_size: PreviewSize,
 ) {
     if let PreviewSize::BestFit(_, _) = preview_size {
@@ -204,7 +605,7 @@ pub fn change_preview_size(
         );
     }
     settings.set_scale(preview_size);
-    post_event(sender, Event::RefreshPreview(preview_size));
+    zoom_preview(sender, widgets, image_list, zoom_generation, preview_size);
 }
 
 pub fn preview_smaller(sender: &Sender<Event>, settings: &Settings, value: Option<u32>) {
@@ -233,17 +634,119 @@ pub fn preview_fit_screen(sender: &Sender<Event>) {
 }
 
 pub fn image_edit(
+    sender: &Sender<Event>,
+    image_list: Rc<RefCell<ImageList>>,
+    background_jobs: &mut BackgroundJobs,
+    image_operation: ImageOperation,
+) {
+    let image_list = image_list.borrow();
+    let current_image_path = match image_list.current_image_path() {
+        Some(current_image_path) => current_image_path,
+        None => return,
+    };
+    let current_image = match image_list.current_image() {
+        Some(current_image) => current_image,
+        None => return,
+    };
+    // edits are queued while the image is loading, only an image whose decoding failed
+    // or was cancelled has no image buffer here
+    let image_buffer = match current_image
+        .current_image_buffer()
+        .and_then(SendableImageBuffer::from_image_buffer)
+    {
+        Some(image_buffer) => image_buffer,
+        None => {
+            post_event(
+                sender,
+                Event::DisplayMessage(
+                    format!("Couldn't apply {}, image isn't loaded", image_operation),
+                    MessageType::Error,
+                ),
+            );
+            return;
+        }
+    };
+    // large images are edited downscaled, the operation is scaled to the edited image buffer
+    let image_buffer_operation = current_image.operation_for_image_buffer(image_operation);
+    let operation_tag = current_image.operation_tag();
+    if !background_jobs.start_operation() {
+        post_event(
+            sender,
+            Event::DisplayMessage(
+                format!(
+                    "Couldn't apply {}, another edit is running",
+                    image_operation
+                ),
+                MessageType::Error,
+            ),
+        );
+        return;
+    }
+    let sender = sender.clone();
+    background_jobs.execute(move || {
+        let image_buffer = image_buffer
+            .into_image_buffer()
+            .apply_operation(&image_buffer_operation)
+            .as_ref()
+            .and_then(SendableImageBuffer::from_image_buffer);
+        post_event(
+            &sender,
+            Event::ImageOperationApplied(
+                current_image_path,
+                operation_tag,
+                image_operation,
+                image_buffer,
+            ),
+        );
+    });
+}
+
+pub fn image_operation_applied(
     sender: &Sender<Event>,
     settings: &Settings,
     image_list: Rc<RefCell<ImageList>>,
-    file_list: &FileList,
+    background_jobs: &mut BackgroundJobs,
+    image_path: PathBuf,
+    operation_tag: OperationTag,
     image_operation: ImageOperation,
+    image_buffer: Option<SendableImageBuffer>,
 ) {
+    background_jobs.finish_operation();
+    let image_buffer = match image_buffer {
+        Some(image_buffer) => image_buffer.into_image_buffer(),
+        None => {
+            post_event(
+                sender,
+                Event::DisplayMessage(
+                    format!("Couldn't apply {}", image_operation),
+                    MessageType::Error,
+                ),
+            );
+            return;
+        }
+    };
     let mut image_list = image_list.borrow_mut();
-    if let Some(mut current_image) = image_list.remove_current_image() {
-        current_image = current_image.apply_operation(&image_operation);
-        image_list.insert(file_list.current_file_path().unwrap(), current_image);
+    let is_current_image = image_list.current_image_path().as_ref() == Some(&image_path);
+    match image_list.get_mut(&image_path) {
+        Some(image) if image.operation_tag() == operation_tag => {
+            image.push_operation(image_operation, image_buffer);
+            if let Err(error) = image.save_edits(&image_path) {
+                report_edits_save_error(sender, &image_list, &image_path, error);
+            }
+        }
+        // the image was loaded again while the operation was running, e.g. after another
+        // program changed it, the operation is applied to the image which is shown now
+        Some(_) if is_current_image => {
+            post_event(sender, Event::ImageEdit(image_operation));
+            return;
+        }
+        // the image was closed while the operation was running
+        _ => debug!("Dropped {} of an image which changed", image_operation),
+    }
+    image_list.evict_image_buffers();
+    if image_list.current_image_path().as_ref() == Some(&image_path) {
         post_event(sender, Event::RefreshPreview(settings.scale()));
+        post_event(sender, Event::RefreshHistoryPanel);
     }
 }
 
@@ -299,6 +802,85 @@ pub fn end_selection(
     }
 }
 
+pub fn show_original(widgets: &Widgets, comparison: Rc<Cell<Comparison>>, original_shown: bool) {
+    let mut new_comparison = comparison.get();
+    new_comparison.set_original_shown(original_shown);
+    if comparison.replace(new_comparison) != new_comparison {
+        widgets.image_widget().queue_draw();
+    }
+}
+
+pub fn toggle_split_view(widgets: &Widgets, comparison: Rc<Cell<Comparison>>, split_view: bool) {
+    let mut new_comparison = comparison.get();
+    new_comparison.set_split_view(split_view);
+    comparison.set(new_comparison);
+    widgets.image_widget().queue_draw();
+}
+
+/// Starts dragging the split view divider when it is grabbed.
+pub fn start_divider_drag(
+    image_list: Rc<RefCell<ImageList>>,
+    comparison: Rc<Cell<Comparison>>,
+    position: (u32, u32),
+) {
+    if let Some((preview_width, _)) = image_list
+        .borrow()
+        .current_image()
+        .and_then(|image| image.preview_image_buffer_size())
+    {
+        let mut new_comparison = comparison.get();
+        new_comparison.start_divider_drag(position.0 as f64, preview_width as f64);
+        comparison.set(new_comparison);
+    }
+}
+
+pub fn drag_divider(
+    widgets: &Widgets,
+    image_list: Rc<RefCell<ImageList>>,
+    comparison: Rc<Cell<Comparison>>,
+    position: (u32, u32),
+) {
+    if let Some((preview_width, _)) = image_list
+        .borrow()
+        .current_image()
+        .and_then(|image| image.preview_image_buffer_size())
+    {
+        let mut new_comparison = comparison.get();
+        if new_comparison.drag_divider(position.0 as f64, preview_width as f64) {
+            comparison.set(new_comparison);
+            widgets.image_widget().queue_draw();
+        }
+    }
+}
+
+pub fn end_divider_drag(comparison: Rc<Cell<Comparison>>) {
+    let mut new_comparison = comparison.get();
+    new_comparison.end_divider_drag();
+    comparison.set(new_comparison);
+}
+
+pub fn pick_color(widgets: &Widgets, image_list: Rc<RefCell<ImageList>>, position: (u32, u32)) {
+    if let Some(current_image) = image_list.borrow().current_image() {
+        let (red, green, blue) = match current_image
+            .preview_coords_to_image_coords((position, position))
+            .and_then(|(image_position, _)| current_image.color_at(image_position))
+        {
+            Some(color) => color,
+            None => return,
+        };
+        widgets
+            .transparency_color_button()
+            .set_rgba(&gdk::RGBA::new(
+                red as f32 / 255.0,
+                green as f32 / 255.0,
+                blue as f32 / 255.0,
+                1.0,
+            ));
+        widgets.eyedropper_button().set_active(false);
+        widgets.transparency_button().popup();
+    }
+}
+
 pub fn resize_popover_displayed(widgets: &Widgets, image_list: Rc<RefCell<ImageList>>) {
     if let Some(current_image) = image_list.borrow().current_image() {
         let (image_width, image_height) = current_image.image_size().unwrap();
@@ -327,25 +909,389 @@ pub fn update_resize_popover_height(widgets: &Widgets, image_list: Rc<RefCell<Im
 
 pub fn save_current_image(
     sender: &Sender<Event>,
+    widgets: &Widgets,
     image_list: Rc<RefCell<ImageList>>,
+    background_jobs: &BackgroundJobs,
+    external_modification_prompted: &mut bool,
     filename: Option<PathBuf>,
 ) {
-    if let Err(error) = image_list.borrow_mut().save_current_image(filename) {
-        post_event(
-            sender,
-            Event::DisplayMessage(error.to_string(), MessageType::Error),
-        );
+    let current_image_is_unsaved = image_list
+        .borrow()
+        .current_image()
+        .map_or(false, |current_image| current_image.is_unsaved());
+    if current_image_is_unsaved && filename.is_none() {
+        widgets.save_as_menu_button().emit_clicked();
+        return;
     }
-}
-
+    if filename.is_none() {
+        let modified_image_path = image_list
+            .borrow()
+            .current_image_path()
+            .filter(|image_path| {
+                image_list
+                    .borrow()
+                    .current_image()
+                    .map_or(false, |image| image.is_modified_externally(image_path))
+            });
+        if let Some(modified_image_path) = modified_image_path {
+            ask_about_external_modification(
+                sender,
+                widgets,
+                external_modification_prompted,
+                modified_image_path,
+            );
+            return;
+        }
+    }
+    let image_path = filename
+        .clone()
+        .or_else(|| image_list.borrow().current_image_path());
+    let encoder = match image_path.map(|image_path| encoders().by_path(&image_path)) {
+        Some(Ok(encoder)) => encoder,
+        Some(Err(error)) => {
+            post_event(
+                sender,
+                Event::DisplayMessage(error.to_string(), MessageType::Error),
+            );
+            return;
+        }
+        None => return,
+    };
+    if encoder.options().is_empty() {
+        let encoder_options = image_list.borrow().encoder_options().clone();
+        post_event(
+            sender,
+            Event::SaveCurrentImageWithOptions(filename, encoder_options),
+        );
+        return;
+    }
+    show_save_options_dialog(
+        sender,
+        widgets,
+        &image_list.borrow(),
+        background_jobs.worker_pool(),
+        encoder,
+        filename,
+    );
+}
+
+/// Asks for options of the format the image is saved in, starting with the options
+/// used last time, and shows how big the file will be with them.
+fn show_save_options_dialog(
+    sender: &Sender<Event>,
+    widgets: &Widgets,
+    image_list: &ImageList,
+    worker_pool: &WorkerPool,
+    encoder: &'static dyn Encoder,
+    filename: Option<PathBuf>,
+) {
+    let encoder_options = Rc::new(RefCell::new(image_list.encoder_options().clone()));
+    let dialog = gtk::Dialog::builder()
+        .transient_for(widgets.window())
+        .modal(true)
+        .title(&format!("{} Options", encoder.name()))
+        .build();
+    dialog.add_buttons(&[
+        ("Cancel", gtk::ResponseType::Cancel),
+        ("Save", gtk::ResponseType::Accept),
+    ]);
+    dialog.set_default_response(gtk::ResponseType::Accept);
+
+    let options_grid = gtk::Grid::builder()
+        .row_spacing(6)
+        .column_spacing(12)
+        .margin_top(12)
+        .margin_bottom(12)
+        .margin_start(12)
+        .margin_end(12)
+        .build();
+    let size_estimate_label = gtk::Label::new(None);
+    size_estimate_label.set_xalign(0.0);
+    size_estimate_label.add_css_class("dim-label");
+
+    let current_image = image_list.current_image();
+    let image_buffer = current_image
+        .and_then(Image::current_image_buffer)
+        .and_then(SendableImageBuffer::from_image_buffer);
+    let image_size = current_image.and_then(Image::image_size);
+    // the sample is encoded on a worker thread, estimates for options which were
+    // changed in the meantime are dropped
+    let (estimate_sender, estimate_receiver) =
+        glib::MainContext::channel::<(Generation, Option<u64>)>(glib::PRIORITY_DEFAULT);
+    {
+        let size_estimate_label = size_estimate_label.clone();
+        estimate_receiver.attach(None, move |(generation, size_estimate)| {
+            if !generation.is_stale() {
+                size_estimate_label.set_text(&match size_estimate {
+                    Some(size_estimate) => {
+                        format!("Estimated size: {}", glib::format_size(size_estimate))
+                    }
+                    None => String::from("Estimated size: unknown"),
+                });
+            }
+            glib::Continue(true)
+        });
+    }
+    let estimate_generation = Arc::new(AtomicU64::new(0));
+    let update_size_estimate = {
+        let encoder_options = encoder_options.clone();
+        let size_estimate_label = size_estimate_label.clone();
+        let worker_pool = worker_pool.clone();
+        Rc::new(move || {
+            let generation = Generation::next(&estimate_generation);
+            let (image_buffer, image_size) = match image_buffer.clone().zip(image_size) {
+                Some(image) => image,
+                None => {
+                    size_estimate_label.set_text("Estimated size: unknown");
+                    return;
+                }
+            };
+            size_estimate_label.set_text("Estimating size...");
+            let encoder_options = encoder_options.borrow().clone();
+            let estimate_sender = estimate_sender.clone();
+            worker_pool.execute(move || {
+                if generation.is_stale() {
+                    return;
+                }
+                let size_estimate = encoder::estimate_encoded_size(
+                    encoder,
+                    &image_buffer.into_image_buffer(),
+                    image_size,
+                    &encoder_options,
+                )
+                .ok();
+                // the dialog may have been closed already
+                let _ = estimate_sender.send((generation, size_estimate));
+            });
+        })
+    };
+
+    for (row, option) in encoder.options().iter().enumerate() {
+        let value = encoder_options
+            .borrow()
+            .value(encoder.name(), option)
+            .to_string();
+        let value = if option.validate(&value).is_ok() {
+            value
+        } else {
+            option.default_value.to_string()
+        };
+        let option_label = gtk::Label::new(Some(option.label));
+        option_label.set_xalign(0.0);
+        let option_widget: gtk::Widget = match option.kind {
+            EncoderOptionKind::Range { min, max } => {
+                let spin_button = gtk::SpinButton::with_range(min as f64, max as f64, 1.0);
+                spin_button.set_value(value.parse().unwrap_or(min as f64));
+                let encoder_options = encoder_options.clone();
+                let update_size_estimate = update_size_estimate.clone();
+                spin_button.connect_value_changed(move |spin_button| {
+                    encoder_options.borrow_mut().set(
+                        encoder.name(),
+                        option,
+                        spin_button.value_as_int().to_string(),
+                    );
+                    update_size_estimate();
+                });
+                spin_button.upcast()
+            }
+            EncoderOptionKind::Toggle => {
+                let check_button = gtk::CheckButton::new();
+                check_button.set_active(value == "true");
+                let encoder_options = encoder_options.clone();
+                let update_size_estimate = update_size_estimate.clone();
+                check_button.connect_toggled(move |check_button| {
+                    encoder_options.borrow_mut().set(
+                        encoder.name(),
+                        option,
+                        check_button.is_active().to_string(),
+                    );
+                    update_size_estimate();
+                });
+                check_button.upcast()
+            }
+            EncoderOptionKind::Choice(choices) => {
+                let combo_box = gtk::ComboBoxText::new();
+                for &(choice_value, choice_label) in choices {
+                    combo_box.append(Some(choice_value), choice_label);
+                }
+                combo_box.set_active_id(Some(&value));
+                let encoder_options = encoder_options.clone();
+                let update_size_estimate = update_size_estimate.clone();
+                combo_box.connect_changed(move |combo_box| {
+                    if let Some(choice_value) = combo_box.active_id() {
+                        encoder_options.borrow_mut().set(
+                            encoder.name(),
+                            option,
+                            choice_value.to_string(),
+                        );
+                        update_size_estimate();
+                    }
+                });
+                combo_box.upcast()
+            }
+        };
+        option_widget.set_halign(gtk::Align::End);
+        encoder_options
+            .borrow_mut()
+            .set(encoder.name(), option, value);
+        options_grid.attach(&option_label, 0, row as i32, 1, 1);
+        options_grid.attach(&option_widget, 1, row as i32, 1, 1);
+    }
+    options_grid.attach(
+        &size_estimate_label,
+        0,
+        encoder.options().len() as i32,
+        2,
+        1,
+    );
+    dialog.content_area().append(&options_grid);
+    update_size_estimate();
+
+    let sender = sender.clone();
+    dialog.connect_response(move |dialog, response| {
+        if response == gtk::ResponseType::Accept {
+            post_event(
+                &sender,
+                Event::SaveCurrentImageWithOptions(
+                    filename.clone(),
+                    encoder_options.borrow().clone(),
+                ),
+            );
+        }
+        dialog.close();
+    });
+    dialog.present();
+}
+
+/// Saves the current image with the options, which become the defaults for next saves.
+pub fn save_current_image_with_options(
+    sender: &Sender<Event>,
+    settings: &Settings,
+    image_list: Rc<RefCell<ImageList>>,
+    background_jobs: &mut BackgroundJobs,
+    filename: Option<PathBuf>,
+    encoder_options: EncoderOptions,
+) {
+    settings.set_encoder_options(&encoder_options);
+    image_list.borrow_mut().set_encoder_options(encoder_options);
+    let image_save = image_list.borrow_mut().prepare_current_image_save(filename);
+    match image_save {
+        Ok(image_save) => write_image(sender, background_jobs, image_save, None),
+        Err(error) => post_event(
+            sender,
+            Event::DisplayMessage(error.to_string(), MessageType::Error),
+        ),
+    }
+}
+
+/// Encodes and writes the image on a worker thread. Saving counts as an image operation,
+/// so edits wait until the image is written. Images with unsaved edits which are left
+/// are saved after it, before the postponed action.
+fn write_image(
+    sender: &Sender<Event>,
+    background_jobs: &mut BackgroundJobs,
+    mut image_save: ImageSave,
+    unsaved_edits: Option<(Vec<PathBuf>, UnsavedEditsAction)>,
+) {
+    if !background_jobs.start_operation() {
+        post_event(
+            sender,
+            Event::DisplayMessage(
+                format!(
+                    "Couldn't save {}, an edit is running",
+                    image_save.file_path().display()
+                ),
+                MessageType::Error,
+            ),
+        );
+        return;
+    }
+    let sender = sender.clone();
+    background_jobs.execute(move || {
+        let result = image_save.write().map_err(|error| error.to_string());
+        post_event(
+            &sender,
+            Event::ImageSaved(image_save, result, unsaved_edits),
+        );
+    });
+}
+
+pub fn image_saved(
+    sender: &Sender<Event>,
+    image_list: Rc<RefCell<ImageList>>,
+    file_list: &mut FileList,
+    background_jobs: &mut BackgroundJobs,
+    image_save: ImageSave,
+    result: Result<(), String>,
+    unsaved_edits: Option<(Vec<PathBuf>, UnsavedEditsAction)>,
+) {
+    background_jobs.finish_operation();
+    let image_was_unsaved = image_list
+        .borrow()
+        .get(image_save.image_path())
+        .map_or(false, Image::is_unsaved);
+    let saved_file_path = image_save.file_path().to_path_buf();
+    let result = result
+        .map_err(anyhow::Error::msg)
+        .and_then(|_| image_list.borrow_mut().finish_save(image_save));
+    if let Err(error) = result {
+        post_event(
+            sender,
+            Event::DisplayMessage(error.to_string(), MessageType::Error),
+        );
+        return;
+    }
+    if image_was_unsaved {
+        if let Err(error) = file_list.refresh() {
+            post_event(
+                sender,
+                Event::DisplayMessage(error.to_string(), MessageType::Error),
+            );
+        }
+        if image_list.borrow().current_image_path().as_ref() == Some(&saved_file_path) {
+            file_list.select_file(&saved_file_path);
+        }
+    }
+    post_event(sender, Event::RefreshHistoryPanel);
+    if let Some((edited_image_paths, action)) = unsaved_edits {
+        post_event(
+            sender,
+            Event::ResolveUnsavedEdits(edited_image_paths, UnsavedEditsResponse::Save, action),
+        );
+    }
+}
+
 pub fn delete_current_image(
     sender: &Sender<Event>,
+    widgets: &Widgets,
     file_list: &mut FileList,
     image_list: Rc<RefCell<ImageList>>,
 ) {
+    let edited_image_paths = image_list
+        .borrow()
+        .current_image_path()
+        .filter(|_| {
+            image_list
+                .borrow()
+                .current_image()
+                .map_or(false, Image::is_edited)
+        })
+        .into_iter()
+        .collect();
+    if !confirm_unsaved_edits(
+        sender,
+        widgets,
+        edited_image_paths,
+        UnsavedEditsAction::DeleteCurrentImage,
+    ) {
+        return;
+    }
     match file_list.delete_current_file() {
         Ok(image_path) => {
             image_list.borrow_mut().remove(image_path.as_path());
+            if let Err(error) = Sidecar::remove(&image_path) {
+                warn!("Couldn't remove edits of deleted image: {}", error);
+            }
             post_event(
                 sender,
                 Event::DisplayMessage(
@@ -421,27 +1367,285 @@ pub fn print(sender: &Sender<Event>, widgets: &Widgets, image_list: Rc<RefCell<I
 }
 
 pub fn undo_operation(
+    sender: &Sender<Event>,
+    image_list: Rc<RefCell<ImageList>>,
+    background_jobs: &mut BackgroundJobs,
+) {
+    change_history(sender, image_list, background_jobs, |image| {
+        image.undo_operation()
+    });
+}
+
+pub fn redo_operation(
+    sender: &Sender<Event>,
+    image_list: Rc<RefCell<ImageList>>,
+    background_jobs: &mut BackgroundJobs,
+) {
+    change_history(sender, image_list, background_jobs, |image| {
+        image.redo_operation()
+    });
+}
+
+pub fn go_to_operation(
+    sender: &Sender<Event>,
+    image_list: Rc<RefCell<ImageList>>,
+    background_jobs: &mut BackgroundJobs,
+    operation_index: Option<usize>,
+) {
+    change_history(sender, image_list, background_jobs, |image| {
+        image.go_to_operation(operation_index)
+    });
+}
+
+pub fn remove_operation(
+    sender: &Sender<Event>,
+    image_list: Rc<RefCell<ImageList>>,
+    background_jobs: &mut BackgroundJobs,
+    operation_index: usize,
+) {
+    change_history(sender, image_list, background_jobs, |image| {
+        image.remove_operation(operation_index)
+    });
+}
+
+/// Changes history of the current image and replays its operations on the worker pool,
+/// the rebuilt image buffer is taken by `history_replayed`.
+fn change_history<F>(
+    sender: &Sender<Event>,
+    image_list: Rc<RefCell<ImageList>>,
+    background_jobs: &mut BackgroundJobs,
+    change: F,
+) where
+    F: FnOnce(&mut Image) -> bool,
+{
+    let mut image_list = image_list.borrow_mut();
+    let current_image_path = match image_list.current_image_path() {
+        Some(current_image_path) => current_image_path,
+        None => return,
+    };
+    let current_image = match image_list.current_image_mut() {
+        Some(current_image) => current_image,
+        None => return,
+    };
+    if !background_jobs.start_operation() {
+        post_event(
+            sender,
+            Event::DisplayMessage(
+                "Couldn't change history, another edit is running".to_string(),
+                MessageType::Error,
+            ),
+        );
+        return;
+    }
+    if !change(current_image) {
+        background_jobs.finish_operation();
+        return;
+    }
+    let replay = current_image.start_replay();
+    let operation_tag = current_image.operation_tag();
+    save_current_image_edits(sender, &mut image_list);
+    post_event(sender, Event::RefreshHistoryPanel);
+    let mut replay = match replay {
+        Some(replay) => replay,
+        // the image isn't decoded, its history is applied when it is loaded
+        None => {
+            background_jobs.finish_operation();
+            return;
+        }
+    };
+    let sender = sender.clone();
+    background_jobs.execute(move || {
+        replay.run();
+        post_event(
+            &sender,
+            Event::HistoryReplayed(current_image_path, operation_tag, replay),
+        );
+    });
+}
+
+pub fn history_replayed(
     sender: &Sender<Event>,
     settings: &Settings,
     image_list: Rc<RefCell<ImageList>>,
+    background_jobs: &mut BackgroundJobs,
+    image_path: PathBuf,
+    operation_tag: OperationTag,
+    replay: Replay,
 ) {
-    if let Some(current_image) = image_list.borrow_mut().current_image_mut() {
-        current_image.undo_operation();
+    background_jobs.finish_operation();
+    let mut image_list = image_list.borrow_mut();
+    match image_list.get_mut(&image_path) {
+        Some(image) if image.operation_tag() == operation_tag => image.finish_replay(replay),
+        // the image was loaded again or closed while the operations were replayed, a loaded
+        // image is decoded with the history saved in its sidecar
+        _ => debug!("Dropped replayed history of an image which changed"),
+    }
+    image_list.evict_image_buffers();
+    if image_list.current_image_path().as_ref() == Some(&image_path) {
         post_event(sender, Event::RefreshPreview(settings.scale()));
+        post_event(sender, Event::RefreshHistoryPanel);
     }
 }
 
-pub fn redo_operation(
+pub fn revert_current_image(
     sender: &Sender<Event>,
     settings: &Settings,
     image_list: Rc<RefCell<ImageList>>,
 ) {
-    if let Some(current_image) = image_list.borrow_mut().current_image_mut() {
-        current_image.redo_operation();
-        post_event(sender, Event::RefreshPreview(settings.scale()));
+    if let Err(error) = image_list.borrow_mut().revert_current_image() {
+        post_event(
+            sender,
+            Event::DisplayMessage(error.to_string(), MessageType::Error),
+        );
+    }
+    post_event(sender, Event::RefreshPreview(settings.scale()));
+    post_event(sender, Event::RefreshHistoryPanel);
+}
+
+pub fn copy_edits(image_list: Rc<RefCell<ImageList>>, copied_recipe: &mut Option<Recipe>) {
+    if let Some(current_image) = image_list.borrow().current_image() {
+        *copied_recipe = current_image.recipe();
     }
 }
 
+pub fn save_recipe(
+    sender: &Sender<Event>,
+    image_list: Rc<RefCell<ImageList>>,
+    recipe_path: PathBuf,
+) {
+    let recipe = match image_list
+        .borrow()
+        .current_image()
+        .and_then(|current_image| current_image.recipe())
+    {
+        Some(recipe) => recipe,
+        None => return,
+    };
+    if let Err(error) = recipe.save(&recipe_path) {
+        post_event(
+            sender,
+            Event::DisplayMessage(error.to_string(), MessageType::Error),
+        );
+    }
+}
+
+pub fn load_and_apply_recipe(
+    sender: &Sender<Event>,
+    image_list: Rc<RefCell<ImageList>>,
+    background_jobs: &mut BackgroundJobs,
+    recipe_path: PathBuf,
+) {
+    match Recipe::load(&recipe_path) {
+        Ok(recipe) => apply_recipe(sender, image_list, background_jobs, &recipe),
+        Err(error) => post_event(
+            sender,
+            Event::DisplayMessage(error.to_string(), MessageType::Error),
+        ),
+    }
+}
+
+pub fn apply_recipe(
+    sender: &Sender<Event>,
+    image_list: Rc<RefCell<ImageList>>,
+    background_jobs: &mut BackgroundJobs,
+    recipe: &Recipe,
+) {
+    change_history(sender, image_list, background_jobs, |image| {
+        image.push_recipe(recipe)
+    });
+}
+
+fn save_current_image_edits(sender: &Sender<Event>, image_list: &mut ImageList) {
+    if let Err(error) = image_list.save_current_image_edits() {
+        if let Some(image_path) = image_list.current_image_path() {
+            report_edits_save_error(sender, image_list, &image_path, error);
+        }
+    }
+}
+
+fn report_edits_save_error(
+    sender: &Sender<Event>,
+    image_list: &ImageList,
+    image_path: &Path,
+    error: anyhow::Error,
+) {
+    if image_list.mark_edits_save_failed(image_path) {
+        post_event(
+            sender,
+            Event::DisplayMessage(
+                format!("Couldn't save edits: {}", error),
+                MessageType::Error,
+            ),
+        );
+    }
+}
+
+pub fn refresh_history_panel(
+    sender: &Sender<Event>,
+    widgets: &Widgets,
+    image_list: Rc<RefCell<ImageList>>,
+) {
+    let history_list_box = widgets.history_list_box();
+    while let Some(row) = history_list_box.first_child() {
+        history_list_box.remove(&row);
+    }
+
+    let image_list = image_list.borrow();
+    let current_image = match image_list.current_image() {
+        Some(current_image) => current_image,
+        None => return,
+    };
+
+    history_list_box.append(&history_row("Original", false, None));
+    for (operation_index, operation) in current_image.operations().iter().enumerate() {
+        let undone = current_image
+            .current_operation_index()
+            .map_or(true, |current_operation_index| {
+                operation_index > current_operation_index
+            });
+        let remove_button = gtk::Button::from_icon_name("edit-delete-symbolic");
+        remove_button.set_has_frame(false);
+        remove_button.set_tooltip_text(Some("Remove from history"));
+        let sender = sender.clone();
+        remove_button.connect_clicked(move |_| {
+            post_event(&sender, Event::RemoveOperation(operation_index));
+        });
+        history_list_box.append(&history_row(
+            operation.to_string().as_str(),
+            undone,
+            Some(&remove_button),
+        ));
+    }
+
+    let current_row_index = current_image
+        .current_operation_index()
+        .map_or(0, |current_operation_index| current_operation_index + 1);
+    history_list_box.select_row(
+        history_list_box
+            .row_at_index(current_row_index as i32)
+            .as_ref(),
+    );
+}
+
+fn history_row(description: &str, undone: bool, remove_button: Option<&gtk::Button>) -> gtk::Box {
+    let row = gtk::Box::new(gtk::Orientation::Horizontal, 5);
+    row.set_margin_start(5);
+    row.set_margin_end(5);
+    let label = gtk::Label::new(Some(description));
+    label.set_hexpand(true);
+    label.set_xalign(0.0);
+    label.set_ellipsize(pango::EllipsizeMode::End);
+    label.set_tooltip_text(Some(description));
+    if undone {
+        label.add_css_class("dim-label");
+    }
+    row.append(&label);
+    if let Some(remove_button) = remove_button {
+        row.append(remove_button);
+    }
+    row
+}
+
 pub fn display_message(widgets: &Widgets, message: &str, message_type: gtk::MessageType) {
     match message_type {
         MessageType::Error => error!("{}", message),
@@ -478,13 +1682,321 @@ pub fn toggle_fullscreen(widgets: &Widgets, settings: &mut Settings) {
     }
 }
 
-pub fn quit(application: &gtk::Application) {
+pub fn quit(
+    sender: &Sender<Event>,
+    widgets: &Widgets,
+    application: &gtk::Application,
+    image_list: Rc<RefCell<ImageList>>,
+) {
+    let edited_image_paths = image_list.borrow().edited_image_paths();
+    if !confirm_unsaved_edits(
+        sender,
+        widgets,
+        edited_image_paths,
+        UnsavedEditsAction::Quit,
+    ) {
+        return;
+    }
     application
         .windows()
         .iter()
         .for_each(|window| window.close());
 }
 
+/// Asks whether unsaved edits of given images should be saved or discarded before the action.
+/// Returns true when there are no unsaved edits and the action can continue right away,
+/// otherwise the action is posted again once the user decides.
+fn confirm_unsaved_edits(
+    sender: &Sender<Event>,
+    widgets: &Widgets,
+    edited_image_paths: Vec<PathBuf>,
+    action: UnsavedEditsAction,
+) -> bool {
+    let message = match edited_image_paths.as_slice() {
+        [] => return true,
+        [image_path] => format!(
+            "Save changes to {}?",
+            image_path
+                .file_name()
+                .and_then(|file_name| file_name.to_str())
+                .unwrap_or_default()
+        ),
+        image_paths => format!("Save changes to {} images?", image_paths.len()),
+    };
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(widgets.window())
+        .modal(true)
+        .message_type(MessageType::Question)
+        .text(&message)
+        .secondary_text(
+            "If you don't save, edits which aren't written into the image files will be discarded.",
+        )
+        .build();
+    dialog.add_buttons(&[
+        ("Cancel", gtk::ResponseType::Cancel),
+        ("Discard", gtk::ResponseType::Reject),
+        ("Save", gtk::ResponseType::Accept),
+    ]);
+    dialog.set_default_response(gtk::ResponseType::Accept);
+
+    let sender = sender.clone();
+    let action = RefCell::new(Some(action));
+    dialog.connect_response(move |dialog, response| {
+        let response = match response {
+            gtk::ResponseType::Accept => Some(UnsavedEditsResponse::Save),
+            gtk::ResponseType::Reject => Some(UnsavedEditsResponse::Discard),
+            _ => None,
+        };
+        if let (Some(response), Some(action)) = (response, action.borrow_mut().take()) {
+            post_event(
+                &sender,
+                Event::ResolveUnsavedEdits(edited_image_paths.clone(), response, action),
+            );
+        }
+        dialog.close();
+    });
+    dialog.present();
+    false
+}
+
+/// Writes edits of all edited images and the open file into the recovery file.
+pub fn autosave(image_list: &ImageList, file_list: &FileList, recovery_path: &Path) {
+    let recovery = Recovery::new(
+        file_list.current_file_path(),
+        image_list.recoverable_images(),
+    );
+    if let Err(error) = recovery.save(recovery_path) {
+        warn!("Couldn't autosave edits: {}", error);
+    }
+}
+
+/// Asks whether edits left by an unclean exit should be restored.
+pub fn offer_recovery(
+    sender: &Sender<Event>,
+    widgets: &Widgets,
+    recovery_path: PathBuf,
+    recovery: Recovery,
+) {
+    let secondary_text = match recovery.images.len() {
+        1 => String::from("Image Roll didn't exit properly, edits of 1 image can be restored."),
+        image_count => format!(
+            "Image Roll didn't exit properly, edits of {} images can be restored.",
+            image_count
+        ),
+    };
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(widgets.window())
+        .modal(true)
+        .message_type(MessageType::Question)
+        .text("Restore edits from the previous session?")
+        .secondary_text(&secondary_text)
+        .build();
+    dialog.add_buttons(&[
+        ("Discard", gtk::ResponseType::Reject),
+        ("Restore", gtk::ResponseType::Accept),
+    ]);
+    dialog.set_default_response(gtk::ResponseType::Accept);
+
+    let sender = sender.clone();
+    let recovery = RefCell::new(Some(recovery));
+    dialog.connect_response(move |dialog, response| {
+        if let Some(recovery) = recovery.borrow_mut().take() {
+            post_event(
+                &sender,
+                Event::ResolveRecovery(
+                    recovery_path.clone(),
+                    recovery,
+                    response == gtk::ResponseType::Accept,
+                ),
+            );
+        }
+        dialog.close();
+    });
+    dialog.present();
+}
+
+/// Restores edits from the recovery file when `restore` is true, the recovery file is removed
+/// either way. The file open in the previous session is opened only when the application
+/// wasn't launched with another one.
+pub fn resolve_recovery(
+    sender: &Sender<Event>,
+    widgets: &Widgets,
+    image_list: Rc<RefCell<ImageList>>,
+    file_list: &mut FileList,
+    recovery_path: PathBuf,
+    recovery: Recovery,
+    restore: bool,
+) {
+    if let Err(error) = Recovery::remove(&recovery_path) {
+        warn!("Couldn't remove recovery file: {}", error);
+    }
+    if !restore {
+        return;
+    }
+    let current_image_path = image_list.borrow().current_image_path();
+    let current_image_is_restored = recovery
+        .images
+        .iter()
+        .any(|image| Some(&image.path) == current_image_path.as_ref());
+    match recovery.current_file {
+        Some(current_file) if file_list.current_file_path().is_none() => open_file(
+            sender,
+            widgets,
+            image_list.clone(),
+            file_list,
+            gio::File::for_path(current_file),
+        ),
+        // restored image replaces the decoded one, it is decoded again with the restored edits
+        _ if current_image_is_restored => post_event(sender, Event::LoadImage(current_image_path)),
+        _ => {}
+    }
+    // opening a file clears the image list, so edits are restored after it
+    image_list.borrow_mut().restore(recovery.images);
+}
+
+/// Asks whether to reload the image changed by another program, keep the edited version or
+/// save it as a new file. The user is asked only once while the dialog is shown.
+fn ask_about_external_modification(
+    sender: &Sender<Event>,
+    widgets: &Widgets,
+    external_modification_prompted: &mut bool,
+    image_path: PathBuf,
+) {
+    if *external_modification_prompted {
+        return;
+    }
+    *external_modification_prompted = true;
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(widgets.window())
+        .modal(true)
+        .message_type(MessageType::Warning)
+        .text(&format!(
+            "{} was changed by another program",
+            image_path
+                .file_name()
+                .and_then(|file_name| file_name.to_str())
+                .unwrap_or_default()
+        ))
+        .secondary_text(
+            "Reload it to apply your edits to the changed file, keep your version \
+             to overwrite the changes when it is saved, or save it as a new file.",
+        )
+        .build();
+    dialog.add_buttons(&[
+        ("Save As…", gtk::ResponseType::Other(0)),
+        ("Keep My Version", gtk::ResponseType::Reject),
+        ("Reload", gtk::ResponseType::Accept),
+    ]);
+    dialog.set_default_response(gtk::ResponseType::Accept);
+
+    let sender = sender.clone();
+    dialog.connect_response(move |dialog, response| {
+        let response = match response {
+            gtk::ResponseType::Accept => Some(ExternalModificationResponse::Reload),
+            gtk::ResponseType::Reject => Some(ExternalModificationResponse::KeepCurrentVersion),
+            gtk::ResponseType::Other(0) => Some(ExternalModificationResponse::SaveAsNewFile),
+            _ => None,
+        };
+        post_event(
+            &sender,
+            Event::ResolveExternalModification(image_path.clone(), response),
+        );
+        dialog.close();
+    });
+    dialog.present();
+}
+
+/// Resolves conflict between the edited image and its file changed by another program,
+/// the user is asked again on the next change or save when the dialog was dismissed.
+pub fn resolve_external_modification(
+    sender: &Sender<Event>,
+    widgets: &Widgets,
+    image_list: Rc<RefCell<ImageList>>,
+    external_modification_prompted: &mut bool,
+    image_path: PathBuf,
+    response: Option<ExternalModificationResponse>,
+) {
+    *external_modification_prompted = false;
+    match response {
+        Some(ExternalModificationResponse::Reload) => {
+            if let Some(image) = image_list.borrow_mut().get_mut(&image_path) {
+                image.remove_image_buffers();
+            }
+            if image_list.borrow().current_image_path().as_ref() == Some(&image_path) {
+                post_event(sender, Event::LoadImage(Some(image_path)));
+            }
+        }
+        Some(ExternalModificationResponse::KeepCurrentVersion) => {
+            let result = image_list
+                .borrow_mut()
+                .get_mut(&image_path)
+                .map_or(Ok(()), |image| {
+                    image.ignore_external_modification(&image_path)
+                });
+            if let Err(error) = result {
+                post_event(
+                    sender,
+                    Event::DisplayMessage(error.to_string(), MessageType::Error),
+                );
+            }
+        }
+        Some(ExternalModificationResponse::SaveAsNewFile) => {
+            widgets.save_as_menu_button().emit_clicked();
+        }
+        None => {}
+    }
+}
+
+/// Saves or discards unsaved edits of given images and continues with the postponed action.
+pub fn resolve_unsaved_edits(
+    sender: &Sender<Event>,
+    settings: &Settings,
+    image_list: Rc<RefCell<ImageList>>,
+    background_jobs: &mut BackgroundJobs,
+    mut edited_image_paths: Vec<PathBuf>,
+    response: UnsavedEditsResponse,
+    action: UnsavedEditsAction,
+) {
+    // images are written one after another on a worker thread, this is called again
+    // with the rest of them when one is written
+    if matches!(response, UnsavedEditsResponse::Save) && !edited_image_paths.is_empty() {
+        let image_path = edited_image_paths.remove(0);
+        let image_save = image_list.borrow_mut().prepare_save(&image_path, None);
+        match image_save {
+            Ok(image_save) => write_image(
+                sender,
+                background_jobs,
+                image_save,
+                Some((edited_image_paths, action)),
+            ),
+            Err(error) => post_event(
+                sender,
+                Event::DisplayMessage(error.to_string(), MessageType::Error),
+            ),
+        }
+        return;
+    }
+    for image_path in &edited_image_paths {
+        if let Err(error) = image_list.borrow_mut().discard_edits(image_path) {
+            post_event(
+                sender,
+                Event::DisplayMessage(error.to_string(), MessageType::Error),
+            );
+            return;
+        }
+    }
+    post_event(sender, Event::RefreshPreview(settings.scale()));
+    post_event(sender, Event::RefreshHistoryPanel);
+    post_event(
+        sender,
+        match action {
+            UnsavedEditsAction::Quit => Event::Quit,
+            UnsavedEditsAction::OpenFile(file) => Event::OpenFile(file),
+            UnsavedEditsAction::DeleteCurrentImage => Event::DeleteCurrentImage,
+        },
+    );
+}
+
 #[cfg(feature = "wallpaper")]
 pub fn set_as_wallpaper(sender: &Sender<Event>, file_list: &FileList) {
     if let Some(current_file_uri) = file_list.current_file_uri() {
@@ -517,6 +2029,354 @@ pub fn copy_current_image(image_list: Rc<RefCell<ImageList>>) {
     image_list.borrow().copy_current_image(display.clipboard());
 }
 
+/// Lists files of the folder in the collage dialog. Their paths are kept in order of the rows,
+/// the file list may be refreshed while the dialog is open.
+pub fn show_collage_dialog(
+    widgets: &Widgets,
+    file_list: &FileList,
+    collage_file_paths: &mut Vec<PathBuf>,
+) {
+    let collage_file_list_box = widgets.collage_file_list_box();
+    while let Some(row) = collage_file_list_box.first_child() {
+        collage_file_list_box.remove(&row);
+    }
+    *collage_file_paths = file_list.file_paths();
+    for file_path in collage_file_paths.iter() {
+        let file_name_label = gtk::Label::new(
+            file_path
+                .file_name()
+                .map(|file_name| file_name.to_string_lossy())
+                .as_deref(),
+        );
+        file_name_label.set_halign(gtk::Align::Start);
+        collage_file_list_box.append(&file_name_label);
+    }
+    widgets.collage_dialog().present();
+}
+
+/// Decodes the selected images and composes the collage on a worker thread, it counts
+/// as an image operation so edits wait until the collage is shown.
+pub fn create_collage(
+    sender: &Sender<Event>,
+    file_list: &FileList,
+    background_jobs: &mut BackgroundJobs,
+    selected_file_paths: Vec<PathBuf>,
+    layout: CollageLayout,
+) {
+    let (cell_width, cell_height) = match layout.cell_size() {
+        Some(cell_size) => cell_size,
+        None => {
+            post_event(
+                sender,
+                Event::DisplayMessage(
+                    String::from("Collage output size is too small for the chosen layout"),
+                    MessageType::Error,
+                ),
+            );
+            return;
+        }
+    };
+    if !background_jobs.start_operation() {
+        post_event(
+            sender,
+            Event::DisplayMessage(
+                String::from("Couldn't create the collage, an edit is running"),
+                MessageType::Error,
+            ),
+        );
+        return;
+    }
+    let folder = file_list
+        .current_file_path()
+        .and_then(|file_path| file_path.parent().map(Path::to_path_buf))
+        .unwrap_or_else(glib::home_dir);
+    let sender = sender.clone();
+    background_jobs.execute(move || {
+        let collage = selected_file_paths
+            .iter()
+            .take(layout.capacity())
+            .map(|file_path| {
+                decoder::load_image_buffer_at_scale(
+                    file_path,
+                    cell_width as i32,
+                    cell_height as i32,
+                )
+            })
+            .collect::<Result<Vec<Pixbuf>, _>>()
+            .and_then(|image_buffers| collage::create_collage(&image_buffers, &layout))
+            .and_then(|collage| {
+                SendableImageBuffer::from_image_buffer(&collage)
+                    .ok_or_else(|| anyhow::anyhow!("Couldn't read pixels of the collage"))
+            })
+            .map_err(|error| error.to_string());
+        post_event(&sender, Event::CollageCreated(folder, collage));
+    });
+}
+
+/// Shows the collage as a new image, which is saved into the folder when the user saves it.
+pub fn collage_created(
+    sender: &Sender<Event>,
+    settings: &Settings,
+    image_list: Rc<RefCell<ImageList>>,
+    background_jobs: &mut BackgroundJobs,
+    folder: PathBuf,
+    collage: Result<SendableImageBuffer, String>,
+) {
+    background_jobs.finish_operation();
+    let collage = match collage {
+        Ok(collage) => collage.into_image_buffer(),
+        Err(error) => {
+            post_event(sender, Event::DisplayMessage(error, MessageType::Error));
+            return;
+        }
+    };
+    let mut image_list = image_list.borrow_mut();
+    if let Some(current_image) = image_list.current_image_mut() {
+        if !current_image.is_unsaved() {
+            current_image.remove_image_buffers();
+        }
+    }
+    image_list.insert_new_image(&folder, "Collage", Image::from_image_buffer(collage));
+    post_event(sender, Event::RefreshPreview(settings.scale()));
+}
+
+pub fn export_tiles(
+    sender: &Sender<Event>,
+    image_list: Rc<RefCell<ImageList>>,
+    folder: PathBuf,
+    file_name_pattern: String,
+    tile_layout: TileLayout,
+) {
+    let image_list = image_list.borrow();
+    let current_image = match image_list.current_image() {
+        Some(current_image) => current_image,
+        None => return,
+    };
+    let name = image_list
+        .current_image_path()
+        .and_then(|image_path| {
+            image_path
+                .file_stem()
+                .and_then(|file_stem| file_stem.to_str())
+                .map(String::from)
+        })
+        .unwrap_or_default();
+    match current_image.export_tiles(&folder, &name, &file_name_pattern, &tile_layout) {
+        Ok(tile_paths) => post_event(
+            sender,
+            Event::DisplayMessage(
+                format!(
+                    "Exported {} tiles to {}",
+                    tile_paths.len(),
+                    folder.display()
+                ),
+                MessageType::Info,
+            ),
+        ),
+        Err(error) => post_event(
+            sender,
+            Event::DisplayMessage(error.to_string(), MessageType::Error),
+        ),
+    }
+}
+
+/// Lists files of the folder in the batch edit dialog, unless a batch is running. Their paths
+/// are kept in order of the rows, the file list may be refreshed while the dialog is open.
+pub fn show_batch_edit_dialog(
+    widgets: &Widgets,
+    file_list: &FileList,
+    batch_file_paths: &mut Vec<PathBuf>,
+    batch_edit_job: &Option<BatchEditJob>,
+) {
+    if batch_edit_job.is_none() {
+        let batch_file_list_box = widgets.batch_file_list_box();
+        while let Some(row) = batch_file_list_box.first_child() {
+            batch_file_list_box.remove(&row);
+        }
+        *batch_file_paths = file_list.file_paths();
+        for file_path in batch_file_paths.iter() {
+            let file_name_label = gtk::Label::new(
+                file_path
+                    .file_name()
+                    .and_then(|file_name| file_name.to_str()),
+            );
+            file_name_label.set_halign(gtk::Align::Start);
+            batch_file_list_box.append(&file_name_label);
+        }
+    }
+    widgets.batch_edit_dialog().present();
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn start_batch_edit(
+    sender: &Sender<Event>,
+    widgets: &Widgets,
+    image_list: Rc<RefCell<ImageList>>,
+    background_jobs: &BackgroundJobs,
+    batch_edit_job: &mut Option<BatchEditJob>,
+    source: BatchEditSource,
+    file_paths: Vec<PathBuf>,
+    options: BatchEditOptions,
+) {
+    if batch_edit_job.is_some() {
+        return;
+    }
+    let recipe = match source {
+        BatchEditSource::CurrentEdits => image_list
+            .borrow()
+            .current_image()
+            .and_then(|current_image| current_image.recipe())
+            .ok_or_else(|| anyhow::anyhow!("Current image doesn't have edits")),
+        BatchEditSource::RecipeFile(recipe_path) => Recipe::load(&recipe_path),
+    };
+    let recipe = match recipe {
+        Ok(recipe) => recipe,
+        Err(error) => {
+            post_event(
+                sender,
+                Event::DisplayMessage(error.to_string(), MessageType::Error),
+            );
+            return;
+        }
+    };
+    if file_paths.is_empty() {
+        post_event(
+            sender,
+            Event::DisplayMessage(
+                String::from("Select files for batch edit"),
+                MessageType::Error,
+            ),
+        );
+        return;
+    }
+
+    let batch_errors_list_box = widgets.batch_errors_list_box();
+    while let Some(row) = batch_errors_list_box.first_child() {
+        batch_errors_list_box.remove(&row);
+    }
+    widgets.batch_progress_bar().set_fraction(0.0);
+    widgets
+        .batch_progress_bar()
+        .set_text(Some(format!("0 / {}", file_paths.len()).as_str()));
+    widgets.batch_start_button().set_sensitive(false);
+    widgets.batch_cancel_button().set_label("Cancel");
+
+    let backup_location = image_list.borrow().backup_location().cloned();
+    let job = batch_edit_job.insert(BatchEditJob::new(
+        file_paths,
+        recipe,
+        options,
+        backup_location,
+    ));
+    run_next_batch_edit_file(sender, background_jobs, job);
+}
+
+/// Edits the next file of the batch on the worker pool, or finishes the batch
+/// when there is nothing left to edit.
+fn run_next_batch_edit_file(
+    sender: &Sender<Event>,
+    background_jobs: &BackgroundJobs,
+    batch_edit_job: &mut BatchEditJob,
+) {
+    if let Some(file_edit) = batch_edit_job.next_file_edit() {
+        let sender = sender.clone();
+        background_jobs.execute(move || {
+            post_event(&sender, Event::BatchEditProgress(file_edit()));
+        });
+    } else if batch_edit_job.is_finished() {
+        post_event(sender, Event::BatchEditFinished);
+    }
+}
+
+pub fn cancel_batch_edit(
+    sender: &Sender<Event>,
+    widgets: &Widgets,
+    background_jobs: &BackgroundJobs,
+    batch_edit_job: &mut Option<BatchEditJob>,
+) {
+    match batch_edit_job {
+        Some(batch_edit_job) if !batch_edit_job.is_cancelled() => {
+            batch_edit_job.cancel();
+            run_next_batch_edit_file(sender, background_jobs, batch_edit_job);
+        }
+        Some(_) => (),
+        None => widgets.batch_edit_dialog().close(),
+    }
+}
+
+pub fn update_batch_edit_progress(
+    sender: &Sender<Event>,
+    widgets: &Widgets,
+    background_jobs: &BackgroundJobs,
+    batch_edit_job: &mut Option<BatchEditJob>,
+    progress: BatchEditProgress,
+) {
+    let batch_edit_job = match batch_edit_job {
+        Some(batch_edit_job) => batch_edit_job,
+        None => return,
+    };
+    batch_edit_job.update(&progress);
+    widgets
+        .batch_progress_bar()
+        .set_fraction(batch_edit_job.processed_files() as f64 / batch_edit_job.file_count() as f64);
+    widgets.batch_progress_bar().set_text(Some(
+        format!(
+            "{} / {}",
+            batch_edit_job.processed_files(),
+            batch_edit_job.file_count()
+        )
+        .as_str(),
+    ));
+    if let BatchEditProgress::FileFailed(file_path, error) = progress {
+        let error_label = gtk::Label::new(Some(
+            format!(
+                "{}: {}",
+                file_path
+                    .file_name()
+                    .and_then(|file_name| file_name.to_str())
+                    .unwrap_or_default(),
+                error
+            )
+            .as_str(),
+        ));
+        error_label.set_halign(gtk::Align::Start);
+        error_label.set_wrap(true);
+        error_label.add_css_class("error");
+        widgets.batch_errors_list_box().append(&error_label);
+    }
+    run_next_batch_edit_file(sender, background_jobs, batch_edit_job);
+}
+
+pub fn finish_batch_edit(
+    sender: &Sender<Event>,
+    widgets: &Widgets,
+    batch_edit_job: &mut Option<BatchEditJob>,
+) {
+    let batch_edit_job = match batch_edit_job.take() {
+        Some(batch_edit_job) => batch_edit_job,
+        None => return,
+    };
+    widgets.batch_start_button().set_sensitive(true);
+    widgets.batch_cancel_button().set_label("Close");
+    let edited_files = batch_edit_job.processed_files() - batch_edit_job.failed_files();
+    let message = if batch_edit_job.is_cancelled() {
+        format!("Batch edit cancelled after editing {} files", edited_files)
+    } else {
+        format!("Batch edit finished, {} files edited", edited_files)
+    };
+    let message_type = if batch_edit_job.failed_files() > 0 {
+        MessageType::Warning
+    } else {
+        MessageType::Info
+    };
+    let message = match batch_edit_job.failed_files() {
+        0 => message,
+        failed_files => format!("{}, {} failed", message, failed_files),
+    };
+    post_event(sender, Event::DisplayMessage(message, message_type));
+    post_event(sender, Event::RefreshFileList);
+}
+
 pub fn start_zoom_gesture(settings: &mut Settings) {
     settings.set_scale_before_zoom_gesture(Some(settings.scale()));
 }
@@ -539,31 +2399,39 @@ pub fn update_buttons_state(
     widgets: &Widgets,
     file_list: &FileList,
     image_list: Rc<RefCell<ImageList>>,
+    has_copied_edits: bool,
+    busy: bool,
     settings: &Settings,
 ) {
+    widgets.loading_spinner().set_spinning(busy);
+    widgets.loading_spinner().set_visible(busy);
+    update_window_title(widgets, &image_list.borrow());
+
     let previous_next_active = file_list.len() > 1;
     widgets.next_button().set_sensitive(previous_next_active);
     widgets
         .previous_button()
         .set_sensitive(previous_next_active);
 
-    let buttons_active = if let Some(current_image) = image_list.borrow().current_image() {
-        widgets
-            .undo_button()
-            .set_sensitive(current_image.can_undo_operation());
-        widgets
-            .redo_button()
-            .set_sensitive(current_image.can_redo_operation());
-        widgets
-            .save_menu_button()
-            .set_sensitive(current_image.has_operations());
-        true
-    } else {
-        widgets.undo_button().set_sensitive(false);
-        widgets.redo_button().set_sensitive(false);
-        widgets.save_menu_button().set_sensitive(false);
-        false
-    };
+    // edits of the current image are disabled while it is being loaded or edited
+    let buttons_active =
+        if let Some(current_image) = image_list.borrow().current_image().filter(|_| !busy) {
+            widgets
+                .undo_button()
+                .set_sensitive(current_image.can_undo_operation());
+            widgets
+                .redo_button()
+                .set_sensitive(current_image.can_redo_operation());
+            widgets
+                .save_menu_button()
+                .set_sensitive(current_image.has_operations() || current_image.is_unsaved());
+            true
+        } else {
+            widgets.undo_button().set_sensitive(false);
+            widgets.redo_button().set_sensitive(false);
+            widgets.save_menu_button().set_sensitive(false);
+            false
+        };
 
     widgets
         .rotate_counterclockwise_button()
@@ -573,6 +2441,7 @@ pub fn update_buttons_state(
         .set_sensitive(buttons_active);
     widgets.crop_button().set_sensitive(buttons_active);
     widgets.resize_button().set_sensitive(buttons_active);
+    widgets.transparency_button().set_sensitive(buttons_active);
     widgets.print_menu_button().set_sensitive(buttons_active);
     widgets.save_as_menu_button().set_sensitive(buttons_active);
     widgets.delete_button().set_sensitive(buttons_active);
@@ -585,6 +2454,40 @@ pub fn update_buttons_state(
     widgets.set_as_wallpaper_menu_button().set_sensitive(false);
 
     widgets.copy_menu_button().set_sensitive(buttons_active);
+    let has_applied_operations = image_list
+        .borrow()
+        .current_image()
+        .map_or(false, |current_image| current_image.has_operations());
+    widgets
+        .copy_edits_menu_button()
+        .set_sensitive(has_applied_operations);
+    widgets
+        .save_recipe_menu_button()
+        .set_sensitive(has_applied_operations);
+    widgets
+        .paste_edits_menu_button()
+        .set_sensitive(buttons_active && has_copied_edits);
+    widgets
+        .apply_recipe_menu_button()
+        .set_sensitive(buttons_active);
+    widgets.revert_menu_button().set_sensitive(
+        !busy
+            && image_list
+                .borrow()
+                .current_image()
+                .map_or(false, |current_image| {
+                    !current_image.operations().is_empty()
+                }),
+    );
+    widgets
+        .export_tiles_menu_button()
+        .set_sensitive(buttons_active);
+    widgets
+        .create_collage_menu_button()
+        .set_sensitive(file_list.len() > 0);
+    widgets
+        .batch_edit_menu_button()
+        .set_sensitive(file_list.len() > 0);
 
     widgets
         .preview_smaller_button()
@@ -593,3 +2496,21 @@ pub fn update_buttons_state(
         .preview_larger_button()
         .set_sensitive(settings.scale().can_be_larger());
 }
+
+/// Shows name of the current image in the window title, edited images are marked with an asterisk.
+fn update_window_title(widgets: &Widgets, image_list: &ImageList) {
+    let file_name = image_list.current_image_path().and_then(|image_path| {
+        image_path
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .map(String::from)
+    });
+    let title = match (file_name, image_list.current_image()) {
+        (Some(file_name), Some(current_image)) if current_image.is_edited() => {
+            format!("*{}", file_name)
+        }
+        (Some(file_name), _) => file_name,
+        (None, _) => String::from("Image Roll"),
+    };
+    widgets.window().set_title(Some(&title));
+}
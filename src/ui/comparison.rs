@@ -0,0 +1,84 @@
+/// Distance in pixels from the divider at which it can be grabbed.
+const DIVIDER_GRAB_DISTANCE: f64 = 8.0;
+
+/// How the original image is compared with the edited one in the preview.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Comparison {
+    original_shown: bool,
+    /// Position of the split view divider as a fraction of the preview width,
+    /// the original image is shown left of it. `None` when split view is off.
+    divider_position: Option<f64>,
+    divider_dragged: bool,
+}
+
+impl Comparison {
+    /// Returns true when original image is shown instead of the edited one.
+    pub fn is_original_shown(&self) -> bool {
+        self.original_shown
+    }
+
+    pub fn set_original_shown(&mut self, original_shown: bool) {
+        self.original_shown = original_shown;
+    }
+
+    pub fn divider_position(&self) -> Option<f64> {
+        self.divider_position
+    }
+
+    pub fn set_split_view(&mut self, split_view: bool) {
+        self.divider_position = if split_view { Some(0.5) } else { None };
+        self.divider_dragged = false;
+    }
+
+    /// Returns true when original image has to be drawn.
+    pub fn is_active(&self) -> bool {
+        self.original_shown || self.divider_position.is_some()
+    }
+
+    /// Starts dragging the divider when `x` is close to it, returns true if it was grabbed.
+    pub fn start_divider_drag(&mut self, x: f64, preview_width: f64) -> bool {
+        self.divider_dragged = self.divider_position.map_or(false, |divider_position| {
+            (x - divider_position * preview_width).abs() <= DIVIDER_GRAB_DISTANCE
+        });
+        self.divider_dragged
+    }
+
+    /// Moves the divider to `x` while it is dragged, returns true if it was moved.
+    pub fn drag_divider(&mut self, x: f64, preview_width: f64) -> bool {
+        if !self.divider_dragged || preview_width <= 0.0 {
+            return false;
+        }
+        self.divider_position = Some((x / preview_width).clamp(0.0, 1.0));
+        true
+    }
+
+    pub fn end_divider_drag(&mut self) {
+        self.divider_dragged = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn divider_is_dragged_only_when_grabbed() {
+        let mut comparison = Comparison::default();
+        assert!(!comparison.start_divider_drag(50.0, 100.0));
+
+        comparison.set_split_view(true);
+        assert!(comparison.is_active());
+        assert!(!comparison.start_divider_drag(20.0, 100.0));
+        assert!(!comparison.drag_divider(20.0, 100.0));
+        assert_eq!(Some(0.5), comparison.divider_position());
+
+        assert!(comparison.start_divider_drag(55.0, 100.0));
+        assert!(comparison.drag_divider(150.0, 100.0));
+        assert_eq!(Some(1.0), comparison.divider_position());
+        comparison.end_divider_drag();
+        assert!(!comparison.drag_divider(20.0, 100.0));
+
+        comparison.set_split_view(false);
+        assert!(!comparison.is_active());
+    }
+}
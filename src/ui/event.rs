@@ -1,11 +1,13 @@
 use gtk::{
+    cairo,
     gdk::{self, Key},
-    gdk_pixbuf::PixbufRotation,
+    gdk_pixbuf::{Pixbuf, PixbufRotation},
     gio,
     glib::{self, timeout_future, Sender},
     prelude::{
-        ButtonExt, DrawingAreaExtManual, FileChooserExt, FileExt, GdkCairoContextExt,
-        NativeDialogExt, PopoverExt, ToggleButtonExt, WidgetExt,
+        AdjustmentExt, ButtonExt, Cast, CheckButtonExt, ColorChooserExt, ComboBoxExt,
+        DrawingAreaExtManual, EditableExt, FileChooserExt, FileExt, GdkCairoContextExt,
+        ListBoxRowExt, NativeDialogExt, PopoverExt, ToggleButtonExt, WidgetExt,
     },
     traits::{GestureExt, GestureSingleExt, GtkWindowExt},
     MessageType, Window,
@@ -18,25 +20,54 @@ use std::{
 };
 
 use crate::{
-    image::{CoordinatesPair, PreviewSize},
-    image_list::ImageList,
-    image_operation::ImageOperation,
+    batch_edit::{BatchEditOptions, BatchEditProgress, BatchEditSource, OutputLocation},
+    collage::CollageLayout,
+    decoder,
+    encoder::{encoders, EncoderOptions},
+    image::{CoordinatesPair, DecodedImage, DecodedProxy, OperationTag, PreviewSize, Replay},
+    image_list::{ImageList, ImageSave},
+    image_operation::{Color, ImageOperation},
+    recovery::Recovery,
     settings::Settings,
+    tiles::TileLayout,
+    worker::SendableImageBuffer,
 };
 
-use super::{controllers::Controllers, widgets::Widgets};
+use super::{comparison::Comparison, controllers::Controllers, widgets::Widgets};
 
 #[derive(Debug)]
 pub enum Event {
     OpenFile(gio::File),
     LoadImage(Option<PathBuf>),
+    ImageProxyLoaded(u64, PathBuf, DecodedProxy),
+    ImageLoaded(u64, PathBuf, Result<DecodedImage, String>),
+    PrefetchImages,
+    ImagePrefetched(PathBuf, Option<DecodedImage>),
+    ShowCacheUsage,
     ImageViewportResize((u32, u32)),
     RefreshPreview(PreviewSize),
+    PreviewCreated(u64, PathBuf, Option<SendableImageBuffer>),
     ChangePreviewSize(PreviewSize),
+    ZoomSettled(u64),
+    ShowOriginal(bool),
+    ToggleSplitView(bool),
     ImageEdit(ImageOperation),
+    ImageOperationApplied(
+        PathBuf,
+        OperationTag,
+        ImageOperation,
+        Option<SendableImageBuffer>,
+    ),
+    HistoryReplayed(PathBuf, OperationTag, Replay),
     StartSelection((u32, u32)),
     DragSelection((u32, u32)),
     SaveCurrentImage(Option<PathBuf>),
+    SaveCurrentImageWithOptions(Option<PathBuf>, EncoderOptions),
+    ImageSaved(
+        ImageSave,
+        Result<(), String>,
+        Option<(Vec<PathBuf>, UnsavedEditsAction)>,
+    ),
     DeleteCurrentImage,
     EndSelection,
     StartZoomGesture,
@@ -52,27 +83,78 @@ pub enum Event {
     UpdateResizePopoverHeight,
     UndoOperation,
     RedoOperation,
+    GoToOperation(Option<usize>),
+    RemoveOperation(usize),
+    RefreshHistoryPanel,
     Print,
     DisplayMessage(String, gtk::MessageType),
     HideInfoPanel,
     ToggleFullscreen,
     CopyCurrentImage,
+    RevertCurrentImage,
+    CopyEdits,
+    PasteEdits,
+    SaveRecipe(PathBuf),
+    ApplyRecipe(PathBuf),
+    ShowCollageDialog,
+    CreateCollage(Vec<PathBuf>, CollageLayout),
+    CollageCreated(PathBuf, Result<SendableImageBuffer, String>),
+    ExportTiles(PathBuf, String, TileLayout),
+    ShowBatchEditDialog,
+    StartBatchEdit(BatchEditSource, Vec<PathBuf>, BatchEditOptions),
+    CancelBatchEdit,
+    BatchEditProgress(BatchEditProgress),
+    BatchEditFinished,
+    ResolveUnsavedEdits(Vec<PathBuf>, UnsavedEditsResponse, UnsavedEditsAction),
+    ResolveExternalModification(PathBuf, Option<ExternalModificationResponse>),
+    Autosave,
+    OfferRecovery(PathBuf, Recovery),
+    ResolveRecovery(PathBuf, Recovery, bool),
     Quit,
     SetAsWallpaper,
 }
 
+/// Action which was postponed until the user decides what to do with unsaved edits.
+#[derive(Debug)]
+pub enum UnsavedEditsAction {
+    Quit,
+    OpenFile(gio::File),
+    DeleteCurrentImage,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum UnsavedEditsResponse {
+    Save,
+    Discard,
+}
+
+/// What to do with an edited image whose file was changed by another program.
+#[derive(Clone, Copy, Debug)]
+pub enum ExternalModificationResponse {
+    /// Decode the changed file and apply edits to it again.
+    Reload,
+    /// Keep the edited image, saving it overwrites the changed file.
+    KeepCurrentVersion,
+    SaveAsNewFile,
+}
+
 pub fn post_event(sender: &glib::Sender<Event>, action: Event) {
     if let Err(err) = sender.send(action) {
         error!("Send error: {}", err);
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn connect_events(
     widgets: Widgets,
     sender: Sender<Event>,
     image_list: Rc<RefCell<ImageList>>,
     selection_coords: Rc<Cell<Option<CoordinatesPair>>>,
+    comparison: Rc<Cell<Comparison>>,
+    collage_file_paths: Rc<RefCell<Vec<PathBuf>>>,
+    batch_file_paths: Rc<RefCell<Vec<PathBuf>>>,
     settings: Settings,
+    recovery_path: PathBuf,
 ) {
     connect_open_menu_button_clicked(widgets.clone(), sender.clone());
     connect_next_button_clicked(widgets.clone(), sender.clone());
@@ -86,20 +168,69 @@ pub fn connect_events(
     connect_preview_fit_screen_button_clicked(widgets.clone(), sender.clone());
     connect_rotate_counterclockwise_button_clicked(widgets.clone(), sender.clone());
     connect_rotate_clockwise_button_clicked(widgets.clone(), sender.clone());
-    connect_image_widget_draw(widgets.clone(), image_list.clone(), selection_coords);
+    connect_image_widget_draw(
+        widgets.clone(),
+        image_list.clone(),
+        selection_coords,
+        comparison,
+    );
+    connect_split_view_button_toggled(widgets.clone(), sender.clone());
+    connect_window_close_request(
+        widgets.clone(),
+        image_list.clone(),
+        sender.clone(),
+        recovery_path,
+    );
+    connect_image_scrolled_window_scrolled(widgets.clone());
     connect_resize_button_activated(widgets.clone(), sender.clone());
     connect_width_spin_button_value_changed(widgets.clone(), sender.clone());
     connect_height_spin_button_value_changed(widgets.clone(), sender.clone());
     connect_apply_resize_button_clicked(widgets.clone(), sender.clone());
+    connect_eyedropper_button_toggled(widgets.clone());
+    connect_apply_transparency_button_clicked(widgets.clone(), sender.clone());
+    connect_apply_flatten_button_clicked(widgets.clone(), sender.clone());
     connect_save_menu_button_clicked(widgets.clone(), sender.clone());
     connect_print_menu_button_clicked(widgets.clone(), sender.clone());
     connect_undo_button_clicked(widgets.clone(), sender.clone());
     connect_redo_button_clicked(widgets.clone(), sender.clone());
+    connect_history_list_box_row_activated(widgets.clone(), sender.clone());
     connect_save_as_menu_button_clicked(widgets.clone(), image_list, sender.clone());
     connect_delete_button_clicked(widgets.clone(), sender.clone());
     connect_info_bar_response(widgets.clone());
     connect_set_as_wallpaper_menu_button_clicked(widgets.clone(), sender.clone());
-    connect_copy_menu_button_clicked(widgets.clone(), sender);
+    connect_copy_menu_button_clicked(widgets.clone(), sender.clone());
+    connect_revert_menu_button_clicked(widgets.clone(), sender.clone());
+    connect_cache_usage_menu_button_clicked(widgets.clone(), sender.clone());
+    connect_copy_edits_menu_button_clicked(widgets.clone(), sender.clone());
+    connect_paste_edits_menu_button_clicked(widgets.clone(), sender.clone());
+    connect_save_recipe_menu_button_clicked(widgets.clone(), sender.clone());
+    connect_apply_recipe_menu_button_clicked(widgets.clone(), sender.clone());
+    connect_create_collage_menu_button_clicked(widgets.clone(), sender.clone());
+    connect_collage_cancel_button_clicked(widgets.clone());
+    connect_collage_create_button_clicked(widgets.clone(), collage_file_paths, sender.clone());
+    connect_export_tiles_menu_button_clicked(widgets.clone());
+    connect_tiles_grid_check_button_toggled(widgets.clone());
+    connect_tiles_size_check_button_toggled(widgets.clone());
+    connect_export_tiles_cancel_button_clicked(widgets.clone());
+    connect_export_tiles_export_button_clicked(widgets.clone(), sender.clone());
+
+    let batch_recipe_path: Rc<RefCell<Option<PathBuf>>> = Rc::new(RefCell::new(None));
+    let batch_output_folder: Rc<RefCell<Option<PathBuf>>> = Rc::new(RefCell::new(None));
+    connect_batch_edit_menu_button_clicked(widgets.clone(), sender.clone());
+    connect_batch_file_filter_entry_search_changed(widgets.clone());
+    connect_batch_select_all_button_clicked(widgets.clone());
+    connect_batch_recipe_check_button_toggled(widgets.clone());
+    connect_batch_output_folder_check_button_toggled(widgets.clone());
+    connect_batch_recipe_file_button_clicked(widgets.clone(), batch_recipe_path.clone());
+    connect_batch_output_folder_button_clicked(widgets.clone(), batch_output_folder.clone());
+    connect_batch_cancel_button_clicked(widgets.clone(), sender.clone());
+    connect_batch_start_button_clicked(
+        widgets.clone(),
+        batch_file_paths,
+        batch_recipe_path,
+        batch_output_folder,
+        sender,
+    );
 
     widgets.window().present();
 }
@@ -137,11 +268,17 @@ fn connect_controllers_to_widgets(widgets: Widgets, controllers: Controllers) {
 }
 
 pub fn connect_keybinds(controllers: Controllers, widgets: Widgets, sender: Sender<Event>) {
+    let sender_for_release = sender.clone();
     controllers
         .window_key_event_controller()
         .connect_key_pressed(move |_, key, _, state| {
             match key {
                 Key::F11 => post_event(&sender, Event::ToggleFullscreen),
+                Key::backslash => post_event(&sender, Event::ShowOriginal(true)),
+                Key::bar => {
+                    let split_view_button = widgets.split_view_button();
+                    split_view_button.set_active(!split_view_button.is_active());
+                }
                 Key::Left | Key::h => {
                     if widgets.previous_button().is_sensitive() {
                         widgets.previous_button().emit_clicked();
@@ -248,6 +385,13 @@ pub fn connect_keybinds(controllers: Controllers, widgets: Widgets, sender: Send
             }
             gtk::Inhibit(false)
         });
+    controllers
+        .window_key_event_controller()
+        .connect_key_released(move |_, key, _, _| {
+            if key == Key::backslash {
+                post_event(&sender_for_release, Event::ShowOriginal(false));
+            }
+        });
 }
 
 fn connect_open_menu_button_clicked(widgets: Widgets, sender: Sender<Event>) {
@@ -267,6 +411,9 @@ fn connect_open_menu_button_clicked(widgets: Widgets, sender: Sender<Event>) {
 
             let file_filter = gtk::FileFilter::new();
             file_filter.add_mime_type("image/*");
+            for extension in decoder::FALLBACK_EXTENSIONS {
+                file_filter.add_suffix(extension);
+            }
             file_filter.set_name(Some("Image"));
 
             file_chooser.add_filter(&file_filter);
@@ -455,17 +602,98 @@ fn connect_image_widget_draw(
     widgets: Widgets,
     image_list: Rc<RefCell<ImageList>>,
     selection_coords: Rc<Cell<Option<CoordinatesPair>>>,
+    comparison: Rc<Cell<Comparison>>,
 ) {
     widgets
+        .clone()
         .image_widget()
         .set_draw_func(move |_, cairo_context, _, _| {
+            if let Some(current_image) = image_list.borrow_mut().current_image_mut() {
+                let comparison = comparison.get();
+                let hadjustment = widgets.image_scrolled_window().hadjustment();
+                let vadjustment = widgets.image_scrolled_window().vadjustment();
+                // while zooming the existing preview is scaled until a new one is created,
+                // magnified pixels stay sharp
+                let (scale_x, scale_y) =
+                    current_image.preview_display_scale().unwrap_or((1.0, 1.0));
+                let filter = if current_image.is_magnified() {
+                    cairo::Filter::Nearest
+                } else {
+                    cairo::Filter::Good
+                };
+                let tiles = match current_image.tiled_preview_mut() {
+                    Some(tiled_preview) => tiled_preview.visible_tiles((
+                        hadjustment.value() / scale_x,
+                        vadjustment.value() / scale_y,
+                        hadjustment.page_size() / scale_x,
+                        vadjustment.page_size() / scale_y,
+                    )),
+                    None => current_image
+                        .preview_image_buffer()
+                        .map(|image_buffer| vec![((0.0, 0.0), image_buffer.clone())])
+                        .unwrap_or_default(),
+                };
+                let (preview_width, preview_height) =
+                    current_image.preview_image_buffer_size().unwrap_or((0, 0));
+                // original preview is created at the zoom of the drawn preview, so it isn't scaled
+                let original_tiles = if comparison.is_active() {
+                    current_image
+                        .original_preview_mut()
+                        .map(|original_preview| {
+                            original_preview.visible_tiles((
+                                hadjustment.value(),
+                                vadjustment.value(),
+                                hadjustment.page_size(),
+                                vadjustment.page_size(),
+                            ))
+                        })
+                        .unwrap_or_default()
+                } else {
+                    Vec::new()
+                };
+                // original image is shown left of the divider
+                let divider_x = if comparison.is_original_shown() {
+                    preview_width as f64
+                } else {
+                    comparison.divider_position().unwrap_or(0.0) * preview_width as f64
+                };
+
+                let mut result = Ok(());
+                if !comparison.is_original_shown() {
+                    result = paint_tiles(
+                        cairo_context,
+                        &tiles,
+                        (scale_x, scale_y),
+                        filter,
+                        (preview_width as f64, preview_height as f64),
+                    );
+                }
+                if result.is_ok() && !original_tiles.is_empty() {
+                    result = paint_tiles(
+                        cairo_context,
+                        &original_tiles,
+                        (1.0, 1.0),
+                        filter,
+                        (divider_x, preview_height as f64),
+                    );
+                }
+                if result.is_ok()
+                    && comparison.divider_position().is_some()
+                    && !comparison.is_original_shown()
+                {
+                    cairo_context.set_source_rgb(1.0, 1.0, 1.0);
+                    cairo_context.set_line_width(2.0);
+                    cairo_context.move_to(divider_x, 0.0);
+                    cairo_context.line_to(divider_x, preview_height as f64);
+                    result = cairo_context.stroke();
+                }
+                if let Err(error) = result {
+                    error!("{}", error);
+                    return;
+                }
+            }
             if let Some(current_image) = image_list.borrow().current_image() {
-                if let Some(image_buffer) = current_image.preview_image_buffer() {
-                    cairo_context.set_source_pixbuf(image_buffer, 0.0, 0.0);
-                    if let Err(error) = cairo_context.paint() {
-                        error!("{}", error);
-                        return;
-                    }
+                if current_image.preview_image_buffer_size().is_some() {
                     if let Some((
                         (start_selection_coord_x, start_selection_coord_y),
                         (end_selection_coord_x, end_selection_coord_y),
@@ -488,6 +716,69 @@ fn connect_image_widget_draw(
         });
 }
 
+/// Paints tiles of a preview given with their positions, scaled by given factors.
+/// Only the area from the top left corner with given size is painted.
+fn paint_tiles(
+    cairo_context: &cairo::Context,
+    tiles: &[((f64, f64), Pixbuf)],
+    (scale_x, scale_y): (f64, f64),
+    filter: cairo::Filter,
+    (width, height): (f64, f64),
+) -> Result<(), cairo::Error> {
+    cairo_context.save()?;
+    cairo_context.rectangle(0.0, 0.0, width, height);
+    cairo_context.clip();
+    cairo_context.scale(scale_x, scale_y);
+    for ((tile_x, tile_y), tile) in tiles {
+        cairo_context.set_source_pixbuf(tile, *tile_x, *tile_y);
+        cairo_context.source().set_filter(filter);
+        cairo_context.paint()?;
+    }
+    cairo_context.restore()
+}
+
+fn connect_window_close_request(
+    widgets: Widgets,
+    image_list: Rc<RefCell<ImageList>>,
+    sender: Sender<Event>,
+    recovery_path: PathBuf,
+) {
+    widgets.window().connect_close_request(move |_| {
+        if image_list.borrow().edited_image_paths().is_empty() {
+            // exit is clean, there is nothing to recover on the next start
+            if let Err(error) = Recovery::remove(&recovery_path) {
+                warn!("Couldn't remove recovery file: {}", error);
+            }
+            return gtk::Inhibit(false);
+        }
+        // quitting asks what to do with unsaved edits first
+        post_event(&sender, Event::Quit);
+        gtk::Inhibit(true)
+    });
+}
+
+fn connect_split_view_button_toggled(widgets: Widgets, sender: Sender<Event>) {
+    widgets
+        .split_view_button()
+        .connect_toggled(move |split_view_button| {
+            post_event(
+                &sender,
+                Event::ToggleSplitView(split_view_button.is_active()),
+            );
+        });
+}
+
+fn connect_image_scrolled_window_scrolled(widgets: Widgets) {
+    // only the visible part of tiled previews is drawn
+    for adjustment in [
+        widgets.image_scrolled_window().hadjustment(),
+        widgets.image_scrolled_window().vadjustment(),
+    ] {
+        let widgets = widgets.clone();
+        adjustment.connect_value_changed(move |_| widgets.image_widget().queue_draw());
+    }
+}
+
 fn connect_resize_button_activated(widgets: Widgets, sender: Sender<Event>) {
     widgets.resize_button().connect_activate(move |_| {
         post_event(&sender, Event::ResizePopoverDisplayed);
@@ -532,6 +823,58 @@ fn connect_apply_resize_button_clicked(widgets: Widgets, sender: Sender<Event>)
         });
 }
 
+fn connect_eyedropper_button_toggled(widgets: Widgets) {
+    widgets
+        .clone()
+        .eyedropper_button()
+        .connect_toggled(move |eyedropper_button| {
+            if eyedropper_button.is_active() {
+                widgets.crop_button().set_active(false);
+                widgets.transparency_button().popdown();
+            }
+        });
+}
+
+fn connect_apply_transparency_button_clicked(widgets: Widgets, sender: Sender<Event>) {
+    widgets
+        .clone()
+        .apply_transparency_button()
+        .connect_clicked(move |_| {
+            post_event(
+                &sender,
+                Event::ImageEdit(ImageOperation::MakeColorTransparent(
+                    rgba_to_color(&widgets.transparency_color_button().rgba()),
+                    widgets.tolerance_spin_button().value() as u8,
+                    widgets.feathering_spin_button().value() as u8,
+                )),
+            );
+            widgets.transparency_button().popdown();
+        });
+}
+
+fn connect_apply_flatten_button_clicked(widgets: Widgets, sender: Sender<Event>) {
+    widgets
+        .clone()
+        .apply_flatten_button()
+        .connect_clicked(move |_| {
+            post_event(
+                &sender,
+                Event::ImageEdit(ImageOperation::FlattenOntoColor(rgba_to_color(
+                    &widgets.flatten_color_button().rgba(),
+                ))),
+            );
+            widgets.transparency_button().popdown();
+        });
+}
+
+fn rgba_to_color(rgba: &gdk::RGBA) -> Color {
+    (
+        (rgba.red() * 255.0).round() as u8,
+        (rgba.green() * 255.0).round() as u8,
+        (rgba.blue() * 255.0).round() as u8,
+    )
+}
+
 fn connect_save_menu_button_clicked(widgets: Widgets, sender: Sender<Event>) {
     widgets
         .clone()
@@ -571,27 +914,46 @@ fn connect_save_as_menu_button_clicked(
                 }
             }
 
-            let file_filter = gtk::FileFilter::new();
-            file_filter.add_mime_type("image/*");
-            file_filter.set_name(Some("Image"));
+            // files are saved in the format of their extension, or of the chosen filter
+            // when the extension isn't one of a supported format
+            let all_formats_file_filter = gtk::FileFilter::new();
+            all_formats_file_filter.set_name(Some("All supported formats"));
+            file_chooser.add_filter(&all_formats_file_filter);
+            for encoder in encoders().encoders() {
+                let file_filter = gtk::FileFilter::new();
+                file_filter.set_name(Some(encoder.name()));
+                for mime_type in encoder.mime_types() {
+                    file_filter.add_mime_type(mime_type);
+                    all_formats_file_filter.add_mime_type(mime_type);
+                }
+                file_chooser.add_filter(&file_filter);
+            }
 
-            file_chooser.add_filter(&file_filter);
             let sender = sender.clone();
             file_chooser.connect_response(move |file_chooser, response| {
                 if response == gtk::ResponseType::Accept {
-                    let file = if let Some(file) = file_chooser.file() {
-                        file
-                    } else {
-                        post_event(
-                            &sender,
-                            Event::DisplayMessage(
-                                String::from("Couldn't save file"),
-                                MessageType::Error,
-                            ),
-                        );
-                        return;
+                    let file_path =
+                        if let Some(file_path) = file_chooser.file().and_then(|file| file.path()) {
+                            file_path
+                        } else {
+                            post_event(
+                                &sender,
+                                Event::DisplayMessage(
+                                    String::from("Couldn't save file"),
+                                    MessageType::Error,
+                                ),
+                            );
+                            return;
+                        };
+                    let chosen_encoder = file_chooser
+                        .filter()
+                        .and_then(|file_filter| file_filter.name())
+                        .and_then(|format_name| encoders().by_name(&format_name));
+                    let file_path = match chosen_encoder {
+                        Some(encoder) => encoders().with_extension_of(file_path, encoder),
+                        None => file_path,
                     };
-                    post_event(&sender, Event::SaveCurrentImage(Some(file.path().unwrap())));
+                    post_event(&sender, Event::SaveCurrentImage(Some(file_path)));
                 }
                 file_chooser.destroy();
             });
@@ -673,6 +1035,496 @@ fn connect_copy_menu_button_clicked(widgets: Widgets, sender: Sender<Event>) {
         });
 }
 
+fn connect_revert_menu_button_clicked(widgets: Widgets, sender: Sender<Event>) {
+    widgets
+        .clone()
+        .revert_menu_button()
+        .connect_clicked(move |_| {
+            widgets.popover_menu().popdown();
+            post_event(&sender, Event::RevertCurrentImage);
+        });
+}
+
+fn connect_cache_usage_menu_button_clicked(widgets: Widgets, sender: Sender<Event>) {
+    widgets
+        .clone()
+        .cache_usage_menu_button()
+        .connect_clicked(move |_| {
+            widgets.popover_menu().popdown();
+            post_event(&sender, Event::ShowCacheUsage);
+        });
+}
+
+fn connect_copy_edits_menu_button_clicked(widgets: Widgets, sender: Sender<Event>) {
+    widgets
+        .clone()
+        .copy_edits_menu_button()
+        .connect_clicked(move |_| {
+            widgets.popover_menu().popdown();
+            post_event(&sender, Event::CopyEdits);
+        });
+}
+
+fn connect_paste_edits_menu_button_clicked(widgets: Widgets, sender: Sender<Event>) {
+    widgets
+        .clone()
+        .paste_edits_menu_button()
+        .connect_clicked(move |_| {
+            widgets.popover_menu().popdown();
+            post_event(&sender, Event::PasteEdits);
+        });
+}
+
+fn connect_save_recipe_menu_button_clicked(widgets: Widgets, sender: Sender<Event>) {
+    widgets
+        .clone()
+        .save_recipe_menu_button()
+        .connect_clicked(move |_| {
+            widgets.popover_menu().popdown();
+            let file_chooser = gtk::FileChooserNative::new(
+                Some("Save recipe"),
+                gtk::Window::NONE,
+                gtk::FileChooserAction::Save,
+                None,
+                None,
+            );
+            file_chooser.set_transient_for(Some(widgets.window()));
+            file_chooser.set_current_name("recipe.json");
+            file_chooser.add_filter(&recipe_file_filter());
+
+            let sender = sender.clone();
+            file_chooser.connect_response(move |file_chooser, response| {
+                if response == gtk::ResponseType::Accept {
+                    if let Some(file_path) = file_chooser.file().and_then(|file| file.path()) {
+                        post_event(&sender, Event::SaveRecipe(file_path));
+                    }
+                }
+                file_chooser.destroy();
+            });
+
+            file_chooser.show();
+            widgets.file_chooser().replace(Some(file_chooser));
+        });
+}
+
+fn connect_apply_recipe_menu_button_clicked(widgets: Widgets, sender: Sender<Event>) {
+    widgets
+        .clone()
+        .apply_recipe_menu_button()
+        .connect_clicked(move |_| {
+            widgets.popover_menu().popdown();
+            let file_chooser = gtk::FileChooserNative::new(
+                Some("Apply recipe"),
+                gtk::Window::NONE,
+                gtk::FileChooserAction::Open,
+                None,
+                None,
+            );
+            file_chooser.set_transient_for(Some(widgets.window()));
+            file_chooser.add_filter(&recipe_file_filter());
+
+            let sender = sender.clone();
+            file_chooser.connect_response(move |file_chooser, response| {
+                if response == gtk::ResponseType::Accept {
+                    if let Some(file_path) = file_chooser.file().and_then(|file| file.path()) {
+                        post_event(&sender, Event::ApplyRecipe(file_path));
+                    }
+                }
+                file_chooser.destroy();
+            });
+
+            file_chooser.show();
+            widgets.file_chooser().replace(Some(file_chooser));
+        });
+}
+
+fn recipe_file_filter() -> gtk::FileFilter {
+    let file_filter = gtk::FileFilter::new();
+    file_filter.add_pattern("*.json");
+    file_filter.set_name(Some("Image Roll recipe"));
+    file_filter
+}
+
+fn connect_create_collage_menu_button_clicked(widgets: Widgets, sender: Sender<Event>) {
+    widgets
+        .clone()
+        .create_collage_menu_button()
+        .connect_clicked(move |_| {
+            widgets.popover_menu().popdown();
+            post_event(&sender, Event::ShowCollageDialog);
+        });
+}
+
+fn connect_collage_cancel_button_clicked(widgets: Widgets) {
+    widgets
+        .clone()
+        .collage_cancel_button()
+        .connect_clicked(move |_| {
+            widgets.collage_dialog().close();
+        });
+}
+
+fn connect_collage_create_button_clicked(
+    widgets: Widgets,
+    collage_file_paths: Rc<RefCell<Vec<PathBuf>>>,
+    sender: Sender<Event>,
+) {
+    widgets
+        .clone()
+        .collage_create_button()
+        .connect_clicked(move |_| {
+            let selected_file_paths = widgets
+                .collage_file_list_box()
+                .selected_rows()
+                .iter()
+                .filter_map(|row| {
+                    collage_file_paths
+                        .borrow()
+                        .get(row.index() as usize)
+                        .cloned()
+                })
+                .collect();
+            let layout = CollageLayout {
+                rows: widgets.collage_rows_spin_button().value() as u32,
+                columns: widgets.collage_columns_spin_button().value() as u32,
+                spacing: widgets.collage_spacing_spin_button().value() as u32,
+                background_color: rgba_to_color(&widgets.collage_background_color_button().rgba()),
+                output_size: (
+                    widgets.collage_width_spin_button().value() as u32,
+                    widgets.collage_height_spin_button().value() as u32,
+                ),
+            };
+            post_event(&sender, Event::CreateCollage(selected_file_paths, layout));
+            widgets.collage_dialog().close();
+        });
+}
+
+fn connect_history_list_box_row_activated(widgets: Widgets, sender: Sender<Event>) {
+    widgets
+        .history_list_box()
+        .connect_row_activated(move |_, row| {
+            let operation_index = (row.index() as usize).checked_sub(1);
+            post_event(&sender, Event::GoToOperation(operation_index));
+        });
+}
+
+fn connect_export_tiles_menu_button_clicked(widgets: Widgets) {
+    widgets
+        .clone()
+        .export_tiles_menu_button()
+        .connect_clicked(move |_| {
+            widgets.popover_menu().popdown();
+            widgets.export_tiles_dialog().present();
+        });
+}
+
+fn connect_tiles_grid_check_button_toggled(widgets: Widgets) {
+    widgets
+        .clone()
+        .tiles_grid_check_button()
+        .connect_toggled(move |tiles_grid_check_button| {
+            let grid_active = tiles_grid_check_button.is_active();
+            widgets.tiles_rows_spin_button().set_sensitive(grid_active);
+            widgets
+                .tiles_columns_spin_button()
+                .set_sensitive(grid_active);
+        });
+}
+
+fn connect_tiles_size_check_button_toggled(widgets: Widgets) {
+    widgets.tiles_width_spin_button().set_sensitive(false);
+    widgets.tiles_height_spin_button().set_sensitive(false);
+    widgets
+        .clone()
+        .tiles_size_check_button()
+        .connect_toggled(move |tiles_size_check_button| {
+            let size_active = tiles_size_check_button.is_active();
+            widgets.tiles_width_spin_button().set_sensitive(size_active);
+            widgets
+                .tiles_height_spin_button()
+                .set_sensitive(size_active);
+        });
+}
+
+fn connect_export_tiles_cancel_button_clicked(widgets: Widgets) {
+    widgets
+        .clone()
+        .export_tiles_cancel_button()
+        .connect_clicked(move |_| {
+            widgets.export_tiles_dialog().close();
+        });
+}
+
+fn connect_export_tiles_export_button_clicked(widgets: Widgets, sender: Sender<Event>) {
+    widgets
+        .clone()
+        .export_tiles_export_button()
+        .connect_clicked(move |_| {
+            let overlap = widgets.tiles_overlap_spin_button().value() as u32;
+            let tile_layout = if widgets.tiles_grid_check_button().is_active() {
+                TileLayout::Grid {
+                    rows: widgets.tiles_rows_spin_button().value() as u32,
+                    columns: widgets.tiles_columns_spin_button().value() as u32,
+                    overlap,
+                }
+            } else {
+                TileLayout::Size {
+                    width: widgets.tiles_width_spin_button().value() as u32,
+                    height: widgets.tiles_height_spin_button().value() as u32,
+                    overlap,
+                }
+            };
+            let file_name_pattern = widgets.tiles_file_name_pattern_entry().text().to_string();
+            widgets.export_tiles_dialog().close();
+
+            let file_chooser = gtk::FileChooserNative::new(
+                Some("Export tiles to folder"),
+                gtk::Window::NONE,
+                gtk::FileChooserAction::SelectFolder,
+                None,
+                None,
+            );
+            file_chooser.set_transient_for(Some(widgets.window()));
+
+            let sender = sender.clone();
+            file_chooser.connect_response(move |file_chooser, response| {
+                if response == gtk::ResponseType::Accept {
+                    match file_chooser.file().and_then(|folder| folder.path()) {
+                        Some(folder) => post_event(
+                            &sender,
+                            Event::ExportTiles(folder, file_name_pattern.clone(), tile_layout),
+                        ),
+                        None => post_event(
+                            &sender,
+                            Event::DisplayMessage(
+                                String::from("Couldn't open selected folder"),
+                                MessageType::Error,
+                            ),
+                        ),
+                    }
+                }
+                file_chooser.destroy();
+            });
+            file_chooser.show();
+            widgets.file_chooser().replace(Some(file_chooser));
+        });
+}
+
+fn connect_batch_edit_menu_button_clicked(widgets: Widgets, sender: Sender<Event>) {
+    widgets
+        .clone()
+        .batch_edit_menu_button()
+        .connect_clicked(move |_| {
+            widgets.popover_menu().popdown();
+            post_event(&sender, Event::ShowBatchEditDialog);
+        });
+}
+
+fn connect_batch_file_filter_entry_search_changed(widgets: Widgets) {
+    let filter_entry = widgets.batch_file_filter_entry().clone();
+    widgets.batch_file_list_box().set_filter_func(move |row| {
+        let filter = filter_entry.text().to_lowercase();
+        row.child()
+            .and_then(|child| child.downcast::<gtk::Label>().ok())
+            .map_or(true, |label| label.text().to_lowercase().contains(&filter))
+    });
+    widgets
+        .clone()
+        .batch_file_filter_entry()
+        .connect_search_changed(move |_| {
+            widgets.batch_file_list_box().invalidate_filter();
+        });
+}
+
+fn connect_batch_select_all_button_clicked(widgets: Widgets) {
+    widgets
+        .clone()
+        .batch_select_all_button()
+        .connect_clicked(move |_| {
+            let batch_file_list_box = widgets.batch_file_list_box();
+            let mut row_index = 0;
+            while let Some(row) = batch_file_list_box.row_at_index(row_index) {
+                if row.is_child_visible() {
+                    batch_file_list_box.select_row(Some(&row));
+                }
+                row_index += 1;
+            }
+        });
+}
+
+fn connect_batch_recipe_check_button_toggled(widgets: Widgets) {
+    widgets
+        .clone()
+        .batch_recipe_check_button()
+        .connect_toggled(move |batch_recipe_check_button| {
+            widgets
+                .batch_recipe_file_button()
+                .set_sensitive(batch_recipe_check_button.is_active());
+        });
+}
+
+fn connect_batch_output_folder_check_button_toggled(widgets: Widgets) {
+    widgets
+        .clone()
+        .batch_output_folder_check_button()
+        .connect_toggled(move |batch_output_folder_check_button| {
+            widgets
+                .batch_output_folder_button()
+                .set_sensitive(batch_output_folder_check_button.is_active());
+        });
+}
+
+fn connect_batch_recipe_file_button_clicked(
+    widgets: Widgets,
+    batch_recipe_path: Rc<RefCell<Option<PathBuf>>>,
+) {
+    widgets
+        .clone()
+        .batch_recipe_file_button()
+        .connect_clicked(move |_| {
+            let file_chooser = gtk::FileChooserNative::new(
+                Some("Choose recipe"),
+                gtk::Window::NONE,
+                gtk::FileChooserAction::Open,
+                None,
+                None,
+            );
+            file_chooser.set_transient_for(Some(widgets.batch_edit_dialog()));
+            file_chooser.add_filter(&recipe_file_filter());
+
+            let widgets = widgets.clone();
+            let batch_recipe_path = batch_recipe_path.clone();
+            file_chooser.connect_response(move |file_chooser, response| {
+                if response == gtk::ResponseType::Accept {
+                    if let Some(file_path) = file_chooser.file().and_then(|file| file.path()) {
+                        widgets.batch_recipe_file_button().set_label(
+                            file_path
+                                .file_name()
+                                .and_then(|file_name| file_name.to_str())
+                                .unwrap_or_default(),
+                        );
+                        batch_recipe_path.replace(Some(file_path));
+                    }
+                }
+                file_chooser.destroy();
+            });
+            file_chooser.show();
+            widgets.file_chooser().replace(Some(file_chooser));
+        });
+}
+
+fn connect_batch_output_folder_button_clicked(
+    widgets: Widgets,
+    batch_output_folder: Rc<RefCell<Option<PathBuf>>>,
+) {
+    widgets
+        .clone()
+        .batch_output_folder_button()
+        .connect_clicked(move |_| {
+            let file_chooser = gtk::FileChooserNative::new(
+                Some("Choose output folder"),
+                gtk::Window::NONE,
+                gtk::FileChooserAction::SelectFolder,
+                None,
+                None,
+            );
+            file_chooser.set_transient_for(Some(widgets.batch_edit_dialog()));
+
+            let widgets = widgets.clone();
+            let batch_output_folder = batch_output_folder.clone();
+            file_chooser.connect_response(move |file_chooser, response| {
+                if response == gtk::ResponseType::Accept {
+                    if let Some(folder) = file_chooser.file().and_then(|folder| folder.path()) {
+                        widgets.batch_output_folder_button().set_label(
+                            folder
+                                .file_name()
+                                .and_then(|folder_name| folder_name.to_str())
+                                .unwrap_or("/"),
+                        );
+                        batch_output_folder.replace(Some(folder));
+                    }
+                }
+                file_chooser.destroy();
+            });
+            file_chooser.show();
+            widgets.file_chooser().replace(Some(file_chooser));
+        });
+}
+
+fn connect_batch_cancel_button_clicked(widgets: Widgets, sender: Sender<Event>) {
+    widgets.batch_cancel_button().connect_clicked(move |_| {
+        post_event(&sender, Event::CancelBatchEdit);
+    });
+}
+
+fn connect_batch_start_button_clicked(
+    widgets: Widgets,
+    batch_file_paths: Rc<RefCell<Vec<PathBuf>>>,
+    batch_recipe_path: Rc<RefCell<Option<PathBuf>>>,
+    batch_output_folder: Rc<RefCell<Option<PathBuf>>>,
+    sender: Sender<Event>,
+) {
+    widgets
+        .clone()
+        .batch_start_button()
+        .connect_clicked(move |_| {
+            let source = if widgets.batch_recipe_check_button().is_active() {
+                match batch_recipe_path.borrow().clone() {
+                    Some(recipe_path) => BatchEditSource::RecipeFile(recipe_path),
+                    None => {
+                        post_event(
+                            &sender,
+                            Event::DisplayMessage(
+                                String::from("Choose a recipe for batch edit"),
+                                MessageType::Error,
+                            ),
+                        );
+                        return;
+                    }
+                }
+            } else {
+                BatchEditSource::CurrentEdits
+            };
+            let output_location = if widgets.batch_output_folder_check_button().is_active() {
+                match batch_output_folder.borrow().clone() {
+                    Some(folder) => OutputLocation::Folder(folder),
+                    None => {
+                        post_event(
+                            &sender,
+                            Event::DisplayMessage(
+                                String::from("Choose an output folder for batch edit"),
+                                MessageType::Error,
+                            ),
+                        );
+                        return;
+                    }
+                }
+            } else {
+                OutputLocation::SameFolder
+            };
+            let options = BatchEditOptions {
+                output_location,
+                file_name_suffix: widgets.batch_suffix_entry().text().to_string(),
+                output_format: widgets
+                    .batch_format_combo_box()
+                    .active_id()
+                    .filter(|format| format != "original")
+                    .map(|format| format.to_string()),
+            };
+            let selected_file_paths = widgets
+                .batch_file_list_box()
+                .selected_rows()
+                .iter()
+                .filter(|row| row.is_child_visible())
+                .filter_map(|row| batch_file_paths.borrow().get(row.index() as usize).cloned())
+                .collect();
+            post_event(
+                &sender,
+                Event::StartBatchEdit(source, selected_file_paths, options),
+            );
+        });
+}
+
 fn connect_zoom_gesture_begin(controllers: Controllers, sender: Sender<Event>) {
     controllers.image_zoom_gesture().connect_begin(move |_, _| {
         post_event(&sender, Event::StartZoomGesture);
@@ -30,13 +30,68 @@ pub struct Widgets {
     info_bar_text: gtk::Label,
     save_menu_button: gtk::Button,
     print_menu_button: gtk::Button,
+    cache_usage_menu_button: gtk::Button,
     undo_button: gtk::Button,
     redo_button: gtk::Button,
     save_as_menu_button: gtk::Button,
+    revert_menu_button: gtk::Button,
+    copy_edits_menu_button: gtk::Button,
+    paste_edits_menu_button: gtk::Button,
+    save_recipe_menu_button: gtk::Button,
+    apply_recipe_menu_button: gtk::Button,
+    batch_edit_menu_button: gtk::Button,
+    batch_edit_dialog: gtk::Window,
+    batch_file_filter_entry: gtk::SearchEntry,
+    batch_select_all_button: gtk::Button,
+    batch_file_list_box: gtk::ListBox,
+    batch_recipe_check_button: gtk::CheckButton,
+    batch_recipe_file_button: gtk::Button,
+    batch_output_folder_check_button: gtk::CheckButton,
+    batch_output_folder_button: gtk::Button,
+    batch_suffix_entry: gtk::Entry,
+    batch_format_combo_box: gtk::ComboBoxText,
+    batch_progress_bar: gtk::ProgressBar,
+    batch_errors_list_box: gtk::ListBox,
+    batch_cancel_button: gtk::Button,
+    batch_start_button: gtk::Button,
     preview_fit_screen_button: gtk::Button,
     delete_button: gtk::Button,
     copy_menu_button: gtk::Button,
     set_as_wallpaper_menu_button: gtk::Button,
+    transparency_button: gtk::MenuButton,
+    transparency_color_button: gtk::ColorButton,
+    eyedropper_button: gtk::ToggleButton,
+    tolerance_spin_button: gtk::SpinButton,
+    feathering_spin_button: gtk::SpinButton,
+    apply_transparency_button: gtk::Button,
+    flatten_color_button: gtk::ColorButton,
+    apply_flatten_button: gtk::Button,
+    create_collage_menu_button: gtk::Button,
+    collage_dialog: gtk::Window,
+    collage_file_list_box: gtk::ListBox,
+    collage_rows_spin_button: gtk::SpinButton,
+    collage_columns_spin_button: gtk::SpinButton,
+    collage_spacing_spin_button: gtk::SpinButton,
+    collage_width_spin_button: gtk::SpinButton,
+    collage_height_spin_button: gtk::SpinButton,
+    collage_background_color_button: gtk::ColorButton,
+    collage_cancel_button: gtk::Button,
+    collage_create_button: gtk::Button,
+    export_tiles_menu_button: gtk::Button,
+    export_tiles_dialog: gtk::Window,
+    tiles_grid_check_button: gtk::CheckButton,
+    tiles_size_check_button: gtk::CheckButton,
+    tiles_rows_spin_button: gtk::SpinButton,
+    tiles_columns_spin_button: gtk::SpinButton,
+    tiles_width_spin_button: gtk::SpinButton,
+    tiles_height_spin_button: gtk::SpinButton,
+    tiles_overlap_spin_button: gtk::SpinButton,
+    tiles_file_name_pattern_entry: gtk::Entry,
+    export_tiles_cancel_button: gtk::Button,
+    export_tiles_export_button: gtk::Button,
+    split_view_button: gtk::ToggleButton,
+    history_list_box: gtk::ListBox,
+    loading_spinner: gtk::Spinner,
     file_chooser: RefCell<Option<gtk::FileChooserNative>>,
 }
 
@@ -162,6 +217,226 @@ impl Widgets {
             .object("set_as_wallpaper_menu_button")
             .expect("Couldn't get set_as_wallpaper_menu_button");
 
+        let transparency_button: gtk::MenuButton = builder
+            .object("transparency_button")
+            .expect("Couldn't get transparency_button");
+
+        let transparency_color_button: gtk::ColorButton = builder
+            .object("transparency_color_button")
+            .expect("Couldn't get transparency_color_button");
+
+        let eyedropper_button: gtk::ToggleButton = builder
+            .object("eyedropper_button")
+            .expect("Couldn't get eyedropper_button");
+
+        let tolerance_spin_button: gtk::SpinButton = builder
+            .object("tolerance_spin_button")
+            .expect("Couldn't get tolerance_spin_button");
+
+        let feathering_spin_button: gtk::SpinButton = builder
+            .object("feathering_spin_button")
+            .expect("Couldn't get feathering_spin_button");
+
+        let apply_transparency_button: gtk::Button = builder
+            .object("apply_transparency_button")
+            .expect("Couldn't get apply_transparency_button");
+
+        let flatten_color_button: gtk::ColorButton = builder
+            .object("flatten_color_button")
+            .expect("Couldn't get flatten_color_button");
+
+        let apply_flatten_button: gtk::Button = builder
+            .object("apply_flatten_button")
+            .expect("Couldn't get apply_flatten_button");
+
+        let create_collage_menu_button: gtk::Button = builder
+            .object("create_collage_menu_button")
+            .expect("Couldn't get create_collage_menu_button");
+
+        let collage_dialog: gtk::Window = builder
+            .object("collage_dialog")
+            .expect("Couldn't get collage_dialog");
+
+        let collage_file_list_box: gtk::ListBox = builder
+            .object("collage_file_list_box")
+            .expect("Couldn't get collage_file_list_box");
+
+        let collage_rows_spin_button: gtk::SpinButton = builder
+            .object("collage_rows_spin_button")
+            .expect("Couldn't get collage_rows_spin_button");
+
+        let collage_columns_spin_button: gtk::SpinButton = builder
+            .object("collage_columns_spin_button")
+            .expect("Couldn't get collage_columns_spin_button");
+
+        let collage_spacing_spin_button: gtk::SpinButton = builder
+            .object("collage_spacing_spin_button")
+            .expect("Couldn't get collage_spacing_spin_button");
+
+        let collage_width_spin_button: gtk::SpinButton = builder
+            .object("collage_width_spin_button")
+            .expect("Couldn't get collage_width_spin_button");
+
+        let collage_height_spin_button: gtk::SpinButton = builder
+            .object("collage_height_spin_button")
+            .expect("Couldn't get collage_height_spin_button");
+
+        let collage_background_color_button: gtk::ColorButton = builder
+            .object("collage_background_color_button")
+            .expect("Couldn't get collage_background_color_button");
+
+        let collage_cancel_button: gtk::Button = builder
+            .object("collage_cancel_button")
+            .expect("Couldn't get collage_cancel_button");
+
+        let collage_create_button: gtk::Button = builder
+            .object("collage_create_button")
+            .expect("Couldn't get collage_create_button");
+
+        let export_tiles_menu_button: gtk::Button = builder
+            .object("export_tiles_menu_button")
+            .expect("Couldn't get export_tiles_menu_button");
+
+        let export_tiles_dialog: gtk::Window = builder
+            .object("export_tiles_dialog")
+            .expect("Couldn't get export_tiles_dialog");
+
+        let tiles_grid_check_button: gtk::CheckButton = builder
+            .object("tiles_grid_check_button")
+            .expect("Couldn't get tiles_grid_check_button");
+
+        let tiles_size_check_button: gtk::CheckButton = builder
+            .object("tiles_size_check_button")
+            .expect("Couldn't get tiles_size_check_button");
+
+        let tiles_rows_spin_button: gtk::SpinButton = builder
+            .object("tiles_rows_spin_button")
+            .expect("Couldn't get tiles_rows_spin_button");
+
+        let tiles_columns_spin_button: gtk::SpinButton = builder
+            .object("tiles_columns_spin_button")
+            .expect("Couldn't get tiles_columns_spin_button");
+
+        let tiles_width_spin_button: gtk::SpinButton = builder
+            .object("tiles_width_spin_button")
+            .expect("Couldn't get tiles_width_spin_button");
+
+        let tiles_height_spin_button: gtk::SpinButton = builder
+            .object("tiles_height_spin_button")
+            .expect("Couldn't get tiles_height_spin_button");
+
+        let tiles_overlap_spin_button: gtk::SpinButton = builder
+            .object("tiles_overlap_spin_button")
+            .expect("Couldn't get tiles_overlap_spin_button");
+
+        let tiles_file_name_pattern_entry: gtk::Entry = builder
+            .object("tiles_file_name_pattern_entry")
+            .expect("Couldn't get tiles_file_name_pattern_entry");
+
+        let export_tiles_cancel_button: gtk::Button = builder
+            .object("export_tiles_cancel_button")
+            .expect("Couldn't get export_tiles_cancel_button");
+
+        let export_tiles_export_button: gtk::Button = builder
+            .object("export_tiles_export_button")
+            .expect("Couldn't get export_tiles_export_button");
+
+        let history_list_box: gtk::ListBox = builder
+            .object("history_list_box")
+            .expect("Couldn't get history_list_box");
+
+        let revert_menu_button: gtk::Button = builder
+            .object("revert_menu_button")
+            .expect("Couldn't get revert_menu_button");
+
+        let copy_edits_menu_button: gtk::Button = builder
+            .object("copy_edits_menu_button")
+            .expect("Couldn't get copy_edits_menu_button");
+
+        let paste_edits_menu_button: gtk::Button = builder
+            .object("paste_edits_menu_button")
+            .expect("Couldn't get paste_edits_menu_button");
+
+        let save_recipe_menu_button: gtk::Button = builder
+            .object("save_recipe_menu_button")
+            .expect("Couldn't get save_recipe_menu_button");
+
+        let apply_recipe_menu_button: gtk::Button = builder
+            .object("apply_recipe_menu_button")
+            .expect("Couldn't get apply_recipe_menu_button");
+
+        let batch_edit_menu_button: gtk::Button = builder
+            .object("batch_edit_menu_button")
+            .expect("Couldn't get batch_edit_menu_button");
+
+        let batch_edit_dialog: gtk::Window = builder
+            .object("batch_edit_dialog")
+            .expect("Couldn't get batch_edit_dialog");
+
+        let batch_file_filter_entry: gtk::SearchEntry = builder
+            .object("batch_file_filter_entry")
+            .expect("Couldn't get batch_file_filter_entry");
+
+        let batch_select_all_button: gtk::Button = builder
+            .object("batch_select_all_button")
+            .expect("Couldn't get batch_select_all_button");
+
+        let batch_file_list_box: gtk::ListBox = builder
+            .object("batch_file_list_box")
+            .expect("Couldn't get batch_file_list_box");
+
+        let batch_recipe_check_button: gtk::CheckButton = builder
+            .object("batch_recipe_check_button")
+            .expect("Couldn't get batch_recipe_check_button");
+
+        let batch_recipe_file_button: gtk::Button = builder
+            .object("batch_recipe_file_button")
+            .expect("Couldn't get batch_recipe_file_button");
+
+        let batch_output_folder_check_button: gtk::CheckButton = builder
+            .object("batch_output_folder_check_button")
+            .expect("Couldn't get batch_output_folder_check_button");
+
+        let batch_output_folder_button: gtk::Button = builder
+            .object("batch_output_folder_button")
+            .expect("Couldn't get batch_output_folder_button");
+
+        let batch_suffix_entry: gtk::Entry = builder
+            .object("batch_suffix_entry")
+            .expect("Couldn't get batch_suffix_entry");
+
+        let batch_format_combo_box: gtk::ComboBoxText = builder
+            .object("batch_format_combo_box")
+            .expect("Couldn't get batch_format_combo_box");
+
+        let batch_progress_bar: gtk::ProgressBar = builder
+            .object("batch_progress_bar")
+            .expect("Couldn't get batch_progress_bar");
+
+        let batch_errors_list_box: gtk::ListBox = builder
+            .object("batch_errors_list_box")
+            .expect("Couldn't get batch_errors_list_box");
+
+        let batch_cancel_button: gtk::Button = builder
+            .object("batch_cancel_button")
+            .expect("Couldn't get batch_cancel_button");
+
+        let batch_start_button: gtk::Button = builder
+            .object("batch_start_button")
+            .expect("Couldn't get batch_start_button");
+
+        let loading_spinner: gtk::Spinner = builder
+            .object("loading_spinner")
+            .expect("Couldn't get loading_spinner");
+
+        let cache_usage_menu_button: gtk::Button = builder
+            .object("cache_usage_menu_button")
+            .expect("Couldn't get cache_usage_menu_button");
+
+        let split_view_button: gtk::ToggleButton = builder
+            .object("split_view_button")
+            .expect("Couldn't get split_view_button");
+
         Self {
             window,
             open_menu_button,
@@ -186,13 +461,68 @@ impl Widgets {
             info_bar_text: error_info_bar_text,
             save_menu_button,
             print_menu_button,
+            cache_usage_menu_button,
             undo_button,
             redo_button,
             save_as_menu_button,
+            revert_menu_button,
+            copy_edits_menu_button,
+            paste_edits_menu_button,
+            save_recipe_menu_button,
+            apply_recipe_menu_button,
+            batch_edit_menu_button,
+            batch_edit_dialog,
+            batch_file_filter_entry,
+            batch_select_all_button,
+            batch_file_list_box,
+            batch_recipe_check_button,
+            batch_recipe_file_button,
+            batch_output_folder_check_button,
+            batch_output_folder_button,
+            batch_suffix_entry,
+            batch_format_combo_box,
+            batch_progress_bar,
+            batch_errors_list_box,
+            batch_cancel_button,
+            batch_start_button,
             preview_fit_screen_button,
             delete_button,
             copy_menu_button,
             set_as_wallpaper_menu_button,
+            transparency_button,
+            transparency_color_button,
+            eyedropper_button,
+            tolerance_spin_button,
+            feathering_spin_button,
+            apply_transparency_button,
+            flatten_color_button,
+            apply_flatten_button,
+            create_collage_menu_button,
+            collage_dialog,
+            collage_file_list_box,
+            collage_rows_spin_button,
+            collage_columns_spin_button,
+            collage_spacing_spin_button,
+            collage_width_spin_button,
+            collage_height_spin_button,
+            collage_background_color_button,
+            collage_cancel_button,
+            collage_create_button,
+            export_tiles_menu_button,
+            export_tiles_dialog,
+            tiles_grid_check_button,
+            tiles_size_check_button,
+            tiles_rows_spin_button,
+            tiles_columns_spin_button,
+            tiles_width_spin_button,
+            tiles_height_spin_button,
+            tiles_overlap_spin_button,
+            tiles_file_name_pattern_entry,
+            export_tiles_cancel_button,
+            export_tiles_export_button,
+            split_view_button,
+            history_list_box,
+            loading_spinner,
             file_chooser: RefCell::new(None),
         }
     }
@@ -347,6 +677,283 @@ impl Widgets {
         &self.copy_menu_button
     }
 
+    /// Get a reference to the widgets's transparency button.
+    pub fn transparency_button(&self) -> &gtk::MenuButton {
+        &self.transparency_button
+    }
+
+    /// Get a reference to the widgets's transparency color button.
+    pub fn transparency_color_button(&self) -> &gtk::ColorButton {
+        &self.transparency_color_button
+    }
+
+    /// Get a reference to the widgets's eyedropper button.
+    pub fn eyedropper_button(&self) -> &gtk::ToggleButton {
+        &self.eyedropper_button
+    }
+
+    /// Get a reference to the widgets's tolerance spin button.
+    pub fn tolerance_spin_button(&self) -> &gtk::SpinButton {
+        &self.tolerance_spin_button
+    }
+
+    /// Get a reference to the widgets's feathering spin button.
+    pub fn feathering_spin_button(&self) -> &gtk::SpinButton {
+        &self.feathering_spin_button
+    }
+
+    /// Get a reference to the widgets's apply transparency button.
+    pub fn apply_transparency_button(&self) -> &gtk::Button {
+        &self.apply_transparency_button
+    }
+
+    /// Get a reference to the widgets's flatten color button.
+    pub fn flatten_color_button(&self) -> &gtk::ColorButton {
+        &self.flatten_color_button
+    }
+
+    /// Get a reference to the widgets's apply flatten button.
+    pub fn apply_flatten_button(&self) -> &gtk::Button {
+        &self.apply_flatten_button
+    }
+
+    /// Get a reference to the widgets's create collage menu button.
+    pub fn create_collage_menu_button(&self) -> &gtk::Button {
+        &self.create_collage_menu_button
+    }
+
+    /// Get a reference to the widgets's collage dialog.
+    pub fn collage_dialog(&self) -> &gtk::Window {
+        &self.collage_dialog
+    }
+
+    /// Get a reference to the widgets's collage file list box.
+    pub fn collage_file_list_box(&self) -> &gtk::ListBox {
+        &self.collage_file_list_box
+    }
+
+    /// Get a reference to the widgets's collage rows spin button.
+    pub fn collage_rows_spin_button(&self) -> &gtk::SpinButton {
+        &self.collage_rows_spin_button
+    }
+
+    /// Get a reference to the widgets's collage columns spin button.
+    pub fn collage_columns_spin_button(&self) -> &gtk::SpinButton {
+        &self.collage_columns_spin_button
+    }
+
+    /// Get a reference to the widgets's collage spacing spin button.
+    pub fn collage_spacing_spin_button(&self) -> &gtk::SpinButton {
+        &self.collage_spacing_spin_button
+    }
+
+    /// Get a reference to the widgets's collage width spin button.
+    pub fn collage_width_spin_button(&self) -> &gtk::SpinButton {
+        &self.collage_width_spin_button
+    }
+
+    /// Get a reference to the widgets's collage height spin button.
+    pub fn collage_height_spin_button(&self) -> &gtk::SpinButton {
+        &self.collage_height_spin_button
+    }
+
+    /// Get a reference to the widgets's collage background color button.
+    pub fn collage_background_color_button(&self) -> &gtk::ColorButton {
+        &self.collage_background_color_button
+    }
+
+    /// Get a reference to the widgets's collage cancel button.
+    pub fn collage_cancel_button(&self) -> &gtk::Button {
+        &self.collage_cancel_button
+    }
+
+    /// Get a reference to the widgets's collage create button.
+    pub fn collage_create_button(&self) -> &gtk::Button {
+        &self.collage_create_button
+    }
+
+    /// Get a reference to the widgets's export tiles menu button.
+    pub fn export_tiles_menu_button(&self) -> &gtk::Button {
+        &self.export_tiles_menu_button
+    }
+
+    /// Get a reference to the widgets's export tiles dialog.
+    pub fn export_tiles_dialog(&self) -> &gtk::Window {
+        &self.export_tiles_dialog
+    }
+
+    /// Get a reference to the widgets's tiles grid check button.
+    pub fn tiles_grid_check_button(&self) -> &gtk::CheckButton {
+        &self.tiles_grid_check_button
+    }
+
+    /// Get a reference to the widgets's tiles size check button.
+    pub fn tiles_size_check_button(&self) -> &gtk::CheckButton {
+        &self.tiles_size_check_button
+    }
+
+    /// Get a reference to the widgets's tiles rows spin button.
+    pub fn tiles_rows_spin_button(&self) -> &gtk::SpinButton {
+        &self.tiles_rows_spin_button
+    }
+
+    /// Get a reference to the widgets's tiles columns spin button.
+    pub fn tiles_columns_spin_button(&self) -> &gtk::SpinButton {
+        &self.tiles_columns_spin_button
+    }
+
+    /// Get a reference to the widgets's tiles width spin button.
+    pub fn tiles_width_spin_button(&self) -> &gtk::SpinButton {
+        &self.tiles_width_spin_button
+    }
+
+    /// Get a reference to the widgets's tiles height spin button.
+    pub fn tiles_height_spin_button(&self) -> &gtk::SpinButton {
+        &self.tiles_height_spin_button
+    }
+
+    /// Get a reference to the widgets's tiles overlap spin button.
+    pub fn tiles_overlap_spin_button(&self) -> &gtk::SpinButton {
+        &self.tiles_overlap_spin_button
+    }
+
+    /// Get a reference to the widgets's tiles file name pattern entry.
+    pub fn tiles_file_name_pattern_entry(&self) -> &gtk::Entry {
+        &self.tiles_file_name_pattern_entry
+    }
+
+    /// Get a reference to the widgets's export tiles cancel button.
+    pub fn export_tiles_cancel_button(&self) -> &gtk::Button {
+        &self.export_tiles_cancel_button
+    }
+
+    /// Get a reference to the widgets's export tiles export button.
+    pub fn export_tiles_export_button(&self) -> &gtk::Button {
+        &self.export_tiles_export_button
+    }
+
+    /// Get a reference to the widgets's history list box.
+    pub fn history_list_box(&self) -> &gtk::ListBox {
+        &self.history_list_box
+    }
+
+    /// Get a reference to the widgets's revert menu button.
+    pub fn revert_menu_button(&self) -> &gtk::Button {
+        &self.revert_menu_button
+    }
+
+    /// Get a reference to the widgets's copy edits menu button.
+    pub fn copy_edits_menu_button(&self) -> &gtk::Button {
+        &self.copy_edits_menu_button
+    }
+
+    /// Get a reference to the widgets's paste edits menu button.
+    pub fn paste_edits_menu_button(&self) -> &gtk::Button {
+        &self.paste_edits_menu_button
+    }
+
+    /// Get a reference to the widgets's save recipe menu button.
+    pub fn save_recipe_menu_button(&self) -> &gtk::Button {
+        &self.save_recipe_menu_button
+    }
+
+    /// Get a reference to the widgets's apply recipe menu button.
+    pub fn apply_recipe_menu_button(&self) -> &gtk::Button {
+        &self.apply_recipe_menu_button
+    }
+
+    /// Get a reference to the widgets's batch edit menu button.
+    pub fn batch_edit_menu_button(&self) -> &gtk::Button {
+        &self.batch_edit_menu_button
+    }
+
+    /// Get a reference to the widgets's batch edit dialog.
+    pub fn batch_edit_dialog(&self) -> &gtk::Window {
+        &self.batch_edit_dialog
+    }
+
+    /// Get a reference to the widgets's batch file filter entry.
+    pub fn batch_file_filter_entry(&self) -> &gtk::SearchEntry {
+        &self.batch_file_filter_entry
+    }
+
+    /// Get a reference to the widgets's batch select all button.
+    pub fn batch_select_all_button(&self) -> &gtk::Button {
+        &self.batch_select_all_button
+    }
+
+    /// Get a reference to the widgets's batch file list box.
+    pub fn batch_file_list_box(&self) -> &gtk::ListBox {
+        &self.batch_file_list_box
+    }
+
+    /// Get a reference to the widgets's batch current edits check button.
+    /// Get a reference to the widgets's batch recipe check button.
+    pub fn batch_recipe_check_button(&self) -> &gtk::CheckButton {
+        &self.batch_recipe_check_button
+    }
+
+    /// Get a reference to the widgets's batch recipe file button.
+    pub fn batch_recipe_file_button(&self) -> &gtk::Button {
+        &self.batch_recipe_file_button
+    }
+
+    /// Get a reference to the widgets's batch same folder check button.
+    /// Get a reference to the widgets's batch output folder check button.
+    pub fn batch_output_folder_check_button(&self) -> &gtk::CheckButton {
+        &self.batch_output_folder_check_button
+    }
+
+    /// Get a reference to the widgets's batch output folder button.
+    pub fn batch_output_folder_button(&self) -> &gtk::Button {
+        &self.batch_output_folder_button
+    }
+
+    /// Get a reference to the widgets's batch suffix entry.
+    pub fn batch_suffix_entry(&self) -> &gtk::Entry {
+        &self.batch_suffix_entry
+    }
+
+    /// Get a reference to the widgets's batch format combo box.
+    pub fn batch_format_combo_box(&self) -> &gtk::ComboBoxText {
+        &self.batch_format_combo_box
+    }
+
+    /// Get a reference to the widgets's batch progress bar.
+    pub fn batch_progress_bar(&self) -> &gtk::ProgressBar {
+        &self.batch_progress_bar
+    }
+
+    /// Get a reference to the widgets's batch errors list box.
+    pub fn batch_errors_list_box(&self) -> &gtk::ListBox {
+        &self.batch_errors_list_box
+    }
+
+    /// Get a reference to the widgets's batch cancel button.
+    pub fn batch_cancel_button(&self) -> &gtk::Button {
+        &self.batch_cancel_button
+    }
+
+    /// Get a reference to the widgets's batch start button.
+    pub fn batch_start_button(&self) -> &gtk::Button {
+        &self.batch_start_button
+    }
+
+    /// Get a reference to the widgets's loading spinner.
+    pub fn loading_spinner(&self) -> &gtk::Spinner {
+        &self.loading_spinner
+    }
+
+    /// Get a reference to the widgets's cache usage menu button.
+    pub fn cache_usage_menu_button(&self) -> &gtk::Button {
+        &self.cache_usage_menu_button
+    }
+
+    /// Get a reference to the widgets's split view button.
+    pub fn split_view_button(&self) -> &gtk::ToggleButton {
+        &self.split_view_button
+    }
+
     pub fn file_chooser(&self) -> &RefCell<Option<gtk::FileChooserNative>> {
         &self.file_chooser
     }
@@ -0,0 +1,329 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+};
+
+use gtk::{
+    gdk_pixbuf::{Colorspace, Pixbuf},
+    glib,
+};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Fixed number of threads taking jobs from a shared queue, clones send jobs to the same threads.
+#[derive(Clone)]
+pub struct WorkerPool {
+    job_sender: mpsc::Sender<Job>,
+}
+
+impl WorkerPool {
+    pub fn new(thread_count: usize) -> Self {
+        let (job_sender, job_receiver) = mpsc::channel::<Job>();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        for thread_index in 0..thread_count.max(1) {
+            let job_receiver = job_receiver.clone();
+            let spawn_result = thread::Builder::new()
+                .name(format!("image-worker-{}", thread_index))
+                .spawn(move || loop {
+                    let job = match job_receiver.lock() {
+                        Ok(job_receiver) => job_receiver.recv(),
+                        Err(_) => break,
+                    };
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => break,
+                    }
+                });
+            if let Err(error) = spawn_result {
+                error!("Couldn't start worker thread: {}", error);
+            }
+        }
+        Self { job_sender }
+    }
+
+    pub fn execute<F: FnOnce() + Send + 'static>(&self, job: F) {
+        if let Err(error) = self.job_sender.send(Box::new(job)) {
+            error!("Couldn't send job to worker threads: {}", error);
+        }
+    }
+}
+
+/// Generation of a job, it becomes stale when a newer job of the same kind is started.
+#[derive(Clone)]
+pub struct Generation {
+    value: u64,
+    latest: Arc<AtomicU64>,
+}
+
+impl Generation {
+    pub fn next(latest: &Arc<AtomicU64>) -> Self {
+        Self {
+            value: latest.fetch_add(1, Ordering::SeqCst) + 1,
+            latest: latest.clone(),
+        }
+    }
+
+    fn current(latest: &Arc<AtomicU64>) -> Self {
+        Self {
+            value: latest.load(Ordering::SeqCst),
+            latest: latest.clone(),
+        }
+    }
+
+    pub fn value(&self) -> u64 {
+        self.value
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.latest.load(Ordering::SeqCst) != self.value
+    }
+}
+
+/// Jobs of the application running on worker threads. Results of loads and previews
+/// which were superseded by newer ones are dropped, only one image operation runs at a time.
+pub struct BackgroundJobs {
+    worker_pool: WorkerPool,
+    load_generation: Arc<AtomicU64>,
+    preview_generation: Arc<AtomicU64>,
+    loading: bool,
+    operation_running: bool,
+    prefetched_image_paths: HashSet<PathBuf>,
+}
+
+impl BackgroundJobs {
+    pub fn new(worker_pool: WorkerPool) -> Self {
+        Self {
+            worker_pool,
+            load_generation: Arc::new(AtomicU64::new(0)),
+            preview_generation: Arc::new(AtomicU64::new(0)),
+            loading: false,
+            operation_running: false,
+            prefetched_image_paths: HashSet::new(),
+        }
+    }
+
+    pub fn execute<F: FnOnce() + Send + 'static>(&self, job: F) {
+        self.worker_pool.execute(job);
+    }
+
+    pub fn worker_pool(&self) -> &WorkerPool {
+        &self.worker_pool
+    }
+
+    pub fn start_load(&mut self) -> Generation {
+        self.loading = true;
+        Generation::next(&self.load_generation)
+    }
+
+    pub fn cancel_loads(&mut self) {
+        self.loading = false;
+        Generation::next(&self.load_generation);
+    }
+
+    /// Returns false if a newer load was started, its result should be dropped then.
+    pub fn finish_load(&mut self, generation: u64) -> bool {
+        if self.load_generation.load(Ordering::SeqCst) != generation {
+            return false;
+        }
+        self.loading = false;
+        true
+    }
+
+    pub fn is_current_load(&self, generation: u64) -> bool {
+        self.loading && self.load_generation.load(Ordering::SeqCst) == generation
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loading
+    }
+
+    /// Returns generation of the latest load, prefetching becomes stale when another image
+    /// is loaded. Returns `None` when the image is already being prefetched.
+    pub fn start_prefetch(&mut self, image_path: &Path) -> Option<Generation> {
+        if !self.prefetched_image_paths.insert(image_path.to_path_buf()) {
+            return None;
+        }
+        Some(Generation::current(&self.load_generation))
+    }
+
+    pub fn finish_prefetch(&mut self, image_path: &Path) {
+        self.prefetched_image_paths.remove(image_path);
+    }
+
+    pub fn start_preview(&self) -> Generation {
+        Generation::next(&self.preview_generation)
+    }
+
+    pub fn is_current_preview(&self, generation: u64) -> bool {
+        self.preview_generation.load(Ordering::SeqCst) == generation
+    }
+
+    /// Returns false if another image operation is still running.
+    pub fn start_operation(&mut self) -> bool {
+        !std::mem::replace(&mut self.operation_running, true)
+    }
+
+    pub fn finish_operation(&mut self) {
+        self.operation_running = false;
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.loading || self.operation_running
+    }
+}
+
+/// Pixel data of an image buffer which can be sent between threads, unlike `Pixbuf`.
+/// Clones share the pixels.
+#[derive(Clone, Debug)]
+pub struct SendableImageBuffer {
+    bytes: glib::Bytes,
+    has_alpha: bool,
+    width: i32,
+    height: i32,
+    rowstride: i32,
+}
+
+impl SendableImageBuffer {
+    /// Buffers created from bytes, which are all buffers decoded or edited on worker threads,
+    /// share their pixels, so sending them doesn't copy the image on the main thread.
+    /// Pixels of other buffers are copied.
+    pub fn from_image_buffer(image_buffer: &Pixbuf) -> Option<Self> {
+        Some(Self {
+            bytes: image_buffer.read_pixel_bytes()?,
+            has_alpha: image_buffer.has_alpha(),
+            width: image_buffer.width(),
+            height: image_buffer.height(),
+            rowstride: image_buffer.rowstride(),
+        })
+    }
+
+    pub fn into_image_buffer(self) -> Pixbuf {
+        Pixbuf::from_bytes(
+            &self.bytes,
+            Colorspace::Rgb,
+            self.has_alpha,
+            8,
+            self.width,
+            self.height,
+            self.rowstride,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn sendable_image_buffer_keeps_pixels() {
+        let image_buffer = Pixbuf::new(Colorspace::Rgb, true, 8, 3, 2).unwrap();
+        image_buffer.fill(0x11223344);
+
+        let sendable_image_buffer = SendableImageBuffer::from_image_buffer(&image_buffer).unwrap();
+        let received_image_buffer = thread::spawn(move || sendable_image_buffer)
+            .join()
+            .unwrap()
+            .into_image_buffer();
+
+        assert_eq!(
+            (3, 2),
+            (
+                received_image_buffer.width(),
+                received_image_buffer.height()
+            )
+        );
+        assert_eq!(
+            image_buffer.read_pixel_bytes(),
+            received_image_buffer.read_pixel_bytes()
+        );
+    }
+
+    #[test]
+    fn sendable_image_buffer_shares_pixels_of_buffer_created_from_bytes() {
+        let image_buffer = Pixbuf::new(Colorspace::Rgb, true, 8, 3, 2).unwrap();
+        let received_image_buffer = SendableImageBuffer::from_image_buffer(&image_buffer)
+            .unwrap()
+            .into_image_buffer();
+
+        let sendable_image_buffer =
+            SendableImageBuffer::from_image_buffer(&received_image_buffer).unwrap();
+        assert_eq!(
+            received_image_buffer.read_pixel_bytes().unwrap().as_ptr(),
+            sendable_image_buffer.bytes.as_ptr()
+        );
+    }
+
+    #[test]
+    fn worker_pool_runs_jobs() {
+        let worker_pool = WorkerPool::new(2);
+        let (result_sender, result_receiver) = mpsc::channel();
+        for job_index in 0..4 {
+            let result_sender = result_sender.clone();
+            worker_pool.execute(move || result_sender.send(job_index).unwrap());
+        }
+
+        let mut results = (0..4)
+            .map(|_| {
+                result_receiver
+                    .recv_timeout(Duration::from_secs(5))
+                    .unwrap()
+            })
+            .collect::<Vec<i32>>();
+        results.sort_unstable();
+        assert_eq!(vec![0, 1, 2, 3], results);
+    }
+
+    #[test]
+    fn starting_load_makes_previous_loads_stale() {
+        let mut background_jobs = BackgroundJobs::new(WorkerPool::new(1));
+
+        let first_load = background_jobs.start_load();
+        let second_load = background_jobs.start_load();
+        assert!(first_load.is_stale());
+        assert!(!second_load.is_stale());
+        assert!(background_jobs.is_busy());
+
+        assert!(!background_jobs.finish_load(first_load.value()));
+        assert!(background_jobs.is_busy());
+        assert!(background_jobs.finish_load(second_load.value()));
+        assert!(!background_jobs.is_busy());
+
+        let third_load = background_jobs.start_load();
+        background_jobs.cancel_loads();
+        assert!(third_load.is_stale());
+        assert!(!background_jobs.is_busy());
+    }
+
+    #[test]
+    fn image_is_prefetched_once_until_it_is_finished() {
+        let mut background_jobs = BackgroundJobs::new(WorkerPool::new(1));
+        background_jobs.start_load();
+
+        let prefetch = background_jobs.start_prefetch(Path::new("a.png")).unwrap();
+        assert!(!prefetch.is_stale());
+        assert!(background_jobs.start_prefetch(Path::new("a.png")).is_none());
+        background_jobs.finish_prefetch(Path::new("a.png"));
+        assert!(background_jobs.start_prefetch(Path::new("a.png")).is_some());
+
+        background_jobs.start_load();
+        assert!(prefetch.is_stale());
+    }
+
+    #[test]
+    fn only_one_operation_runs_at_a_time() {
+        let mut background_jobs = BackgroundJobs::new(WorkerPool::new(1));
+
+        assert!(background_jobs.start_operation());
+        assert!(!background_jobs.start_operation());
+        assert!(background_jobs.is_busy());
+        background_jobs.finish_operation();
+        assert!(background_jobs.start_operation());
+    }
+}